@@ -1,20 +1,26 @@
 use criterion::{Criterion, criterion_group, criterion_main};
 use std::path::Path;
 
-use wubraglib::{chunking::chunk_all_documents, document::grab_all_documents, embedding::Embedder};
+use wubraglib::{chunking::Chunker, document::grab_all_documents, embedding::Embedder};
 
 fn bench_embed_for_dir(c: &mut Criterion, name: &str, dir: &str) {
     let root_path = Path::new(dir);
 
-    let docs = grab_all_documents(std::hint::black_box(&root_path));
+    let docs = grab_all_documents(std::hint::black_box(root_path))
+        .map_err(|e| format!("failed to collect {dir}: {e}"))
+        .unwrap();
 
-    let (mut chunks, _) = chunk_all_documents(std::hint::black_box(&docs));
+    let (chunks, _) = Chunker::new()
+        .chunk_all_documents(std::hint::black_box(&docs))
+        .expect("chunking failed");
 
-    let mut embedder = Embedder::new();
+    let embedder = Embedder::new();
 
     c.bench_function(name, |b| {
         b.iter(|| {
-            let _ = embedder.embed_chunks(std::hint::black_box(&mut chunks));
+            let _ = embedder
+                .embed_chunks(std::hint::black_box(&chunks))
+                .expect("embedding failed");
         })
     });
 }
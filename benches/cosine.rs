@@ -0,0 +1,36 @@
+use criterion::{Criterion, criterion_group, criterion_main};
+use std::hint::black_box;
+
+use wubraglib::util::cosine_similarity;
+
+const DIM: usize = 768;
+
+fn embedding(seed: u32) -> Vec<f32> {
+    (0..DIM as u32)
+        .map(|i| ((i.wrapping_mul(2_654_435_761) ^ seed) % 2000) as f32 / 1000.0 - 1.0)
+        .collect()
+}
+
+// The straightforward loop `cosine_similarity` replaced, as a baseline
+fn scalar_cosine(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let na = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let nb = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    dot / (na * nb)
+}
+
+fn bench_cosine(c: &mut Criterion) {
+    let (a, b) = (embedding(7), embedding(13));
+
+    let mut group = c.benchmark_group("cosine_768");
+    group.bench_function("scalar", |bench| {
+        bench.iter(|| scalar_cosine(black_box(&a), black_box(&b)))
+    });
+    group.bench_function("vectorized", |bench| {
+        bench.iter(|| cosine_similarity(black_box(&a), black_box(&b)))
+    });
+    group.finish();
+}
+
+criterion_group!(cosine_benches, bench_cosine);
+criterion_main!(cosine_benches);
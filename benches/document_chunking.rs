@@ -1,15 +1,18 @@
 use criterion::{Criterion, criterion_group, criterion_main};
 use std::path::Path;
 
-use wubraglib::{chunking::chunk_all_documents, document::grab_all_documents};
+use wubraglib::{chunking::Chunker, document::grab_all_documents};
 
 fn bench_chunk_for_dir(c: &mut Criterion, name: &str, dir: &str) {
     let root_path = Path::new(dir);
-    let docs = grab_all_documents(std::hint::black_box(&root_path));
+    let docs = grab_all_documents(std::hint::black_box(root_path))
+        .map_err(|e| format!("failed to collect {dir}: {e}"))
+        .unwrap();
+    let chunker = Chunker::new();
 
     c.bench_function(name, |b| {
         b.iter(|| {
-            let _ = chunk_all_documents(std::hint::black_box(&docs));
+            let _ = chunker.chunk_all_documents(std::hint::black_box(&docs));
         })
     });
 }
@@ -8,7 +8,8 @@ fn bench_grab_for_dir(c: &mut Criterion, name: &str, dir: &str) {
 
     c.bench_function(name, |b| {
         b.iter(|| {
-            let _ = grab_all_documents(std::hint::black_box(&root_path));
+            let _ = grab_all_documents(std::hint::black_box(root_path))
+                .map_err(|e| eprintln!("failed to collect {dir}: {e}"));
         })
     });
 }
@@ -0,0 +1,75 @@
+use criterion::{Criterion, criterion_group, criterion_main};
+use std::path::Path;
+
+use wubraglib::{
+    chunking::Chunker,
+    document::{Document, grab_all_documents},
+};
+
+// Compare the two ID hashes by running this bench with and without `--features blake3`
+const ALGORITHM: &str = if cfg!(feature = "blake3") {
+    "blake3"
+} else {
+    "sha256"
+};
+
+// Hashing alone: document IDs for 64 MiB of text
+fn bench_document_ids(c: &mut Criterion) {
+    let text = "fn main() { println!(\"hello\"); }\n".repeat(32 * 1024);
+    c.bench_function(&format!("document_ids_{ALGORITHM}"), |b| {
+        b.iter(|| {
+            for i in 0..64 {
+                let _ =
+                    Document::from_str(&format!("src/{i}.rs"), std::hint::black_box(&text).clone());
+            }
+        })
+    });
+}
+
+// Thousands of one-line functions, where per-chunk ID overhead dominates chunking
+fn bench_many_small_chunks(c: &mut Criterion) {
+    let text: String = (0..5000).map(|i| format!("fn f{i}() {{}}\n")).collect();
+    let doc = Document::from_str("many.rs", text);
+    let chunker = Chunker::new();
+    c.bench_function(&format!("many_small_chunks_{ALGORITHM}"), |b| {
+        b.iter(|| {
+            let _ = chunker.chunk_document(std::hint::black_box(&doc));
+        })
+    });
+}
+
+// Collecting and chunking a checkout, which hashes every document and chunk
+fn bench_ingest_for_dir(c: &mut Criterion, name: &str, dir: &str) {
+    let root = Path::new(dir);
+    // The example checkouts aren't vendored; skip the bench instead of aborting the group
+    if !root.is_dir() {
+        return;
+    }
+    let chunker = Chunker::new();
+    c.bench_function(&format!("ingest_{name}_{ALGORITHM}"), |b| {
+        b.iter(|| {
+            let docs = grab_all_documents(std::hint::black_box(root)).unwrap();
+            let _ = chunker.chunk_all_documents(&docs);
+        })
+    });
+}
+
+fn bench_ingest_ratatui(c: &mut Criterion) {
+    bench_ingest_for_dir(c, "ratatui", "tests/examples/ratatui");
+}
+
+fn bench_ingest_coreutils(c: &mut Criterion) {
+    bench_ingest_for_dir(c, "coreutils", "tests/examples/coreutils");
+}
+
+criterion_group! {
+    name = id_benches;
+    config = Criterion::default().sample_size(10);
+    targets =
+        bench_document_ids,
+        bench_many_small_chunks,
+        bench_ingest_ratatui,
+        bench_ingest_coreutils
+}
+
+criterion_main!(id_benches);
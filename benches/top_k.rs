@@ -0,0 +1,61 @@
+use criterion::{Criterion, criterion_group, criterion_main};
+use std::hint::black_box;
+
+use wubraglib::{
+    chunking::{Chunk, ChunkKind},
+    indexing::Index,
+};
+
+const COUNT: usize = 200_000;
+const DIM: usize = 384;
+
+fn embedding(seed: u32) -> Vec<f32> {
+    (0..DIM as u32)
+        .map(|i| {
+            ((i.wrapping_mul(2_654_435_761) ^ seed.wrapping_mul(40_503)) % 2000) as f32 / 1000.0
+                - 1.0
+        })
+        .collect()
+}
+
+fn chunk(i: usize) -> Chunk {
+    let mut id = [0; 32];
+    id[..8].copy_from_slice(&(i as u64).to_le_bytes());
+    Chunk {
+        id,
+        doc_id: [0; 32],
+        path: "synthetic.txt".to_string(),
+        text: String::new(),
+        kind: ChunkKind::Paragraph,
+        raw_kind: "paragraph".into(),
+        char_count: 0,
+        start_line: i + 1,
+        end_line: i + 1,
+        start_byte: i,
+        end_byte: i,
+        doc_order: i,
+        parent_id: None,
+        token_count: None,
+    }
+}
+
+// Top-10 selection against ranking every score, on a large synthetic index
+fn bench_top_k(c: &mut Criterion) {
+    let chunks = (0..COUNT).map(chunk).collect();
+    let embeddings = (0..COUNT as u32).map(embedding).collect();
+    let index = Index::new(chunks, embeddings).unwrap();
+    let query = embedding(u32::MAX);
+
+    let mut group = c.benchmark_group("search_200k");
+    group.sample_size(20);
+    group.bench_function("top_10", |b| {
+        b.iter(|| index.search(black_box(&query), 10).unwrap())
+    });
+    group.bench_function("rank_all", |b| {
+        b.iter(|| index.search(black_box(&query), COUNT).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_top_k);
+criterion_main!(benches);
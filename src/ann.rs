@@ -0,0 +1,117 @@
+//! Approximate nearest-neighbour search over an [`Index`] using HNSW.
+//!
+//! Exact search scores every stored embedding, which is linear in the corpus
+//! size. An HNSW graph answers queries in roughly logarithmic time, at the cost
+//! of a build step and of recall: a true nearest neighbour is occasionally
+//! missed. Raising `ef_search` (and, at build time, `ef_construction` and
+//! `max_nb_connection`) trades speed back for recall. Below
+//! [`AnnParams::exact_below`] chunks no graph is built and queries fall back to
+//! exact search, which is both faster and lossless at that size.
+
+use hnsw_rs::hnsw::Hnsw;
+use hnsw_rs::prelude::{DistCosine, DistL2};
+
+use crate::Result;
+use crate::indexing::{Index, Metric};
+
+#[derive(Debug, Clone)]
+pub struct AnnParams {
+    // maximum links per node per layer
+    pub max_nb_connection: usize,
+    // candidate list size while building; higher gives a better graph, slower
+    pub ef_construction: usize,
+    // candidate list size while searching; raised to `k` when smaller
+    pub ef_search: usize,
+    // indexes with fewer chunks than this use exact search instead
+    pub exact_below: usize,
+}
+
+impl Default for AnnParams {
+    fn default() -> Self {
+        Self {
+            max_nb_connection: 16,
+            ef_construction: 200,
+            ef_search: 64,
+            exact_below: 1000,
+        }
+    }
+}
+
+// Dot-product indexes are graphed by cosine distance, which orders unit-length
+// embeddings identically; results are always re-scored with the index metric.
+enum Graph<'i> {
+    Cosine(Hnsw<'i, f32, DistCosine>),
+    L2(Hnsw<'i, f32, DistL2>),
+}
+
+/// An HNSW graph over the embeddings of an [`Index`], built with [`Index::build_ann`].
+pub struct AnnIndex<'i> {
+    index: &'i Index,
+    graph: Option<Graph<'i>>,
+    params: AnnParams,
+}
+
+impl Index {
+    pub fn build_ann(&self, params: AnnParams) -> AnnIndex<'_> {
+        let count = self.embeddings().len();
+        let graph = (count >= params.exact_below && count > 0).then(|| {
+            let layers = 16.min((count as f32).ln().trunc() as usize).max(1);
+            let embeddings: Vec<_> = self.embeddings().collect();
+            let data: Vec<(&[f32], usize)> =
+                embeddings.iter().map(|e| e.as_ref()).zip(0..).collect();
+            match self.metric() {
+                Metric::Cosine | Metric::DotProduct => {
+                    let hnsw = Hnsw::new(
+                        params.max_nb_connection,
+                        count,
+                        layers,
+                        params.ef_construction,
+                        DistCosine {},
+                    );
+                    hnsw.parallel_insert_slice(&data);
+                    Graph::Cosine(hnsw)
+                }
+                Metric::Euclidean => {
+                    let hnsw = Hnsw::new(
+                        params.max_nb_connection,
+                        count,
+                        layers,
+                        params.ef_construction,
+                        DistL2 {},
+                    );
+                    hnsw.parallel_insert_slice(&data);
+                    Graph::L2(hnsw)
+                }
+            }
+        });
+
+        AnnIndex {
+            index: self,
+            graph,
+            params,
+        }
+    }
+}
+
+impl AnnIndex<'_> {
+    /// Same contract as [`Index::search`], but as `(position, score)` pairs:
+    /// the `k` best, best first, scored with the index metric.
+    pub fn search(&self, query: &[f32], k: usize) -> Result<Vec<(usize, f32)>> {
+        self.index.check_dim(query)?;
+        let Some(graph) = &self.graph else {
+            return self.index.search_filtered(query, k, |_| true);
+        };
+
+        let ef = self.params.ef_search.max(k);
+        let neighbours = match graph {
+            Graph::Cosine(hnsw) => hnsw.search(query, k, ef),
+            Graph::L2(hnsw) => hnsw.search(query, k, ef),
+        };
+        let positions: Vec<usize> = neighbours.into_iter().map(|n| n.d_id).collect();
+        Ok(self.index.rescore(query, &positions, k))
+    }
+
+    pub fn is_exact(&self) -> bool {
+        self.graph.is_none()
+    }
+}
@@ -0,0 +1,72 @@
+use std::collections::HashMap;
+
+// Standard Okapi BM25 parameters
+const K1: f32 = 1.2;
+const B: f32 = 0.75;
+
+/// Keyword index over chunk texts, used to mix exact-term matches into vector search.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Bm25 {
+    doc_freq: HashMap<String, usize>,
+    term_freqs: Vec<HashMap<String, u32>>,
+    doc_lens: Vec<usize>,
+    avg_len: f32,
+}
+
+// Identifiers keep their underscores so `parse_header` stays one term
+pub(crate) fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .filter(|t| !t.is_empty())
+        .map(str::to_lowercase)
+}
+
+impl Bm25 {
+    pub(crate) fn new<'t>(texts: impl IntoIterator<Item = &'t str>) -> Self {
+        let mut bm25 = Bm25::default();
+        for text in texts {
+            let mut tf: HashMap<String, u32> = HashMap::new();
+            let mut len = 0;
+            for term in tokenize(text) {
+                *tf.entry(term).or_default() += 1;
+                len += 1;
+            }
+            for term in tf.keys() {
+                *bm25.doc_freq.entry(term.clone()).or_default() += 1;
+            }
+            bm25.term_freqs.push(tf);
+            bm25.doc_lens.push(len);
+        }
+        let total: usize = bm25.doc_lens.iter().sum();
+        bm25.avg_len = total as f32 / bm25.doc_lens.len().max(1) as f32;
+        bm25
+    }
+
+    /// Scores every indexed text against `query`, in insertion order.
+    pub(crate) fn scores(&self, query: &str) -> Vec<f32> {
+        let n = self.term_freqs.len() as f32;
+        let mut terms: Vec<String> = tokenize(query).collect();
+        terms.sort();
+        terms.dedup();
+        let idfs: Vec<(&str, f32)> = terms
+            .iter()
+            .filter_map(|t| {
+                let df = *self.doc_freq.get(t)? as f32;
+                Some((t.as_str(), ((n - df + 0.5) / (df + 0.5) + 1.0).ln()))
+            })
+            .collect();
+
+        self.term_freqs
+            .iter()
+            .zip(&self.doc_lens)
+            .map(|(tf, &len)| {
+                let length_norm = 1.0 - B + B * len as f32 / self.avg_len.max(1.0);
+                idfs.iter()
+                    .filter_map(|(term, idf)| {
+                        let f = *tf.get(*term)? as f32;
+                        Some(idf * f * (K1 + 1.0) / (f + K1 * length_norm))
+                    })
+                    .sum()
+            })
+            .collect()
+    }
+}
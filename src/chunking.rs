@@ -1,205 +1,2228 @@
 use lazy_static::lazy_static;
 use rayon::prelude::*;
-use sha2::Digest;
-use std::collections::HashMap;
-use tree_sitter::{Language, Parser, Query, QueryCursor, StreamingIterator};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+use tree_sitter::{Language, Node, Parser, Query, QueryCursor, StreamingIterator};
 
 use crate::document::{Document, DocumentID};
+use crate::id_hash::IdHasher;
+use crate::tokenizer::TokenCounter;
+use crate::{RAGError, Result};
 
 pub type ChunkID = [u8; 32];
 
-fn compute_chunk_id(doc_id: &DocumentID, chunk_text: &str) -> ChunkID {
-    let mut hash = sha2::Sha256::new();
-    hash.update(doc_id);
-    hash.update(chunk_text.as_bytes());
-    hash.finalize().into()
+// The offset keeps identical text at two places in one document (repeated boilerplate,
+// copy-pasted functions) from sharing an ID
+fn compute_chunk_id(doc_id: &DocumentID, start_byte: usize, chunk_text: &str) -> ChunkID {
+    ChunkIds::new(doc_id).id(start_byte, chunk_text)
+}
+
+// `compute_chunk_id` for the many chunks of one document: the hasher is seeded with the
+// document ID once and cloned for each chunk
+struct ChunkIds(IdHasher);
+
+impl ChunkIds {
+    fn new(doc_id: &DocumentID) -> Self {
+        let mut hash = IdHasher::new();
+        hash.update(doc_id);
+        Self(hash)
+    }
+
+    fn id(&self, start_byte: usize, chunk_text: &str) -> ChunkID {
+        let mut hash = self.0.clone();
+        hash.update((start_byte as u64).to_le_bytes());
+        hash.update(chunk_text.as_bytes());
+        hash.finalize()
+    }
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Chunk {
-    pub id: ChunkID,              // primary key
-    pub doc_id: DocumentID,       // foreign key id of the document that the chunk is attached to
-    pub text: String,             // content of the chunk
-    pub chunk_type: &'static str, // whatever is returned by node.kind() with tree-sitter (or "paragraph"/"document")
-    pub char_count: usize,        // amount of characters
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex"))]
+    pub id: ChunkID, // primary key
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex"))]
+    pub doc_id: DocumentID, // foreign key id of the document that the chunk is attached to
+    pub path: String, // `Document::path` of the source, filled in by chunk_document
+    pub text: String, // content of the chunk
+    pub kind: ChunkKind, // semantic category, comparable across languages
+    pub raw_kind: Cow<'static, str>, // node.kind() from tree-sitter, or "paragraph"/"document"
+    pub char_count: usize, // amount of characters
+    pub start_line: usize, // 1-based, inclusive
+    pub end_line: usize, // 1-based, inclusive
+    pub start_byte: usize, // offset of `text` in the document text
+    pub end_byte: usize, // exclusive
+    pub doc_order: usize, // rank by position in the document, enclosing chunks first
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex::option"))]
+    pub parent_id: Option<ChunkID>, // enclosing container chunk, for nested functions
+    pub token_count: Option<usize>, // set when the chunker has a token counter
+}
+
+impl Chunk {
+    /// The chunk text cut to at most `max_chars` characters followed by `…`
+    /// for display, or all of it when it fits. Code is cut after the last
+    /// whole line that fits, when there is one; prose is cut mid-line.
+    pub fn preview(&self, max_chars: usize) -> Cow<'_, str> {
+        let Some((cut, _)) = self.text.char_indices().nth(max_chars) else {
+            return Cow::Borrowed(&self.text);
+        };
+        let head = &self.text[..cut];
+        let head = match head.rfind('\n') {
+            Some(line_end) if line_end > 0 && self.kind != ChunkKind::Paragraph => {
+                &head[..line_end]
+            }
+            _ => head,
+        };
+        Cow::Owned(format!("{}…", head.trim_end()))
+    }
+}
+
+/// What a chunk holds, independent of the grammar it came from: a Rust
+/// `function_item` and a Python `function_definition` are both
+/// [`Function`](Self::Function). The grammar's own name stays in
+/// [`Chunk::raw_kind`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ChunkKind {
+    /// Functions, methods, constructors and arrow functions bound to a name.
+    Function,
+    /// Structs, unions and records.
+    Struct,
+    Class,
+    Enum,
+    /// Traits and interfaces.
+    Trait,
+    /// Modules and namespaces.
+    Module,
+    /// A piece of prose or unstructured text: a paragraph or a Markdown section.
+    Paragraph,
+    /// The whole document, when nothing smaller could be found.
+    WholeDocument,
+    /// Any other node, named by its tree-sitter kind.
+    Other(String),
+}
+
+impl ChunkKind {
+    // Wrapper nodes (exports, templates, declarations) take the category of what they declare
+    fn of_node(node: &Node) -> ChunkKind {
+        match node.kind() {
+            "function_item"
+            | "function_definition"
+            | "function_declaration"
+            | "method_declaration"
+            | "method_definition"
+            | "constructor_declaration"
+            | "arrow_function"
+            | "mixin_statement"
+            | "function_statement" => ChunkKind::Function,
+            "struct_item" | "struct_specifier" | "struct_declaration" | "union_specifier"
+            | "record_declaration" | "struct_type" => ChunkKind::Struct,
+            "class_definition"
+            | "class_declaration"
+            | "abstract_class_declaration"
+            | "class_specifier" => ChunkKind::Class,
+            "enum_item" | "enum_specifier" | "enum_declaration" => ChunkKind::Enum,
+            "trait_item" | "interface_declaration" | "interface_type" => ChunkKind::Trait,
+            "mod_item" | "namespace_definition" => ChunkKind::Module,
+            "export_statement"
+            | "template_declaration"
+            | "declaration"
+            | "type_declaration"
+            | "type_spec"
+            | "lexical_declaration"
+            | "variable_declaration"
+            | "variable_declarator" => {
+                let mut cursor = node.walk();
+                node.named_children(&mut cursor)
+                    .map(|child| ChunkKind::of_node(&child))
+                    .find(|kind| !matches!(kind, ChunkKind::Other(_)))
+                    .unwrap_or_else(|| ChunkKind::Other(node.kind().to_string()))
+            }
+            raw => ChunkKind::Other(raw.to_string()),
+        }
+    }
+}
+
+/// How a document was split into chunks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkStrategy {
+    /// Split along tree-sitter query matches.
+    TreeSitter,
+    /// Split into heading sections.
+    Markdown,
+    /// Split into TOML tables.
+    Toml,
+    /// Split into paragraphs, either because no grammar handles the extension or
+    /// because the source failed to parse.
+    Naive,
+    /// The source parsed, but no query matched, so the whole file is one chunk.
+    WholeDocument,
+}
+
+/// How tree-sitter chunking treats a container (an `impl`, class, module...)
+/// whose functions can be chunks of their own, so a method's text needn't be
+/// embedded twice.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ContainerMode {
+    /// The container chunk holds its full text; its functions are chunked
+    /// separately only with [`ChunkOptions::emit_nested_functions`].
+    #[default]
+    Full,
+    /// Like `Full`, but function bodies in the container's text are elided to
+    /// `{ ... }` (`...` in Python), leaving an outline of signatures. Byte and
+    /// line ranges still span the whole container. The functions are always
+    /// chunked separately, so their bodies are still indexed.
+    Skeleton,
+    /// Containers with functions are dropped and the functions emitted in
+    /// their place, as top-level chunks without a `parent_id`. Containers
+    /// without any functions are kept whole.
+    ChildrenOnly,
+}
+
+/// Per-document outcome of chunking, for spotting files that lost their structure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkStats {
+    pub strategy: ChunkStrategy,
+    pub chunk_count: usize,
+    // the grammar reported syntax errors, so the document fell back to `Naive`
+    pub parse_failed: bool,
+}
+
+/// Shape of a chunked corpus, for sanity-checking a chunking config before
+/// paying for embeddings. See [`corpus_stats`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CorpusStats {
+    pub total_chunks: usize,
+    pub by_raw_kind: HashMap<String, usize>,
+    // keyed by the extension of `Chunk::path`, "" when it has none
+    pub by_extension: HashMap<String, usize>,
+    pub char_counts: SizeHistogram,
+}
+
+/// Distribution of [`Chunk::char_count`]; all zero for an empty corpus.
+/// Percentiles use the nearest-rank method, so they are actual chunk sizes.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SizeHistogram {
+    pub min: usize,
+    pub max: usize,
+    pub mean: f64,
+    pub p50: usize,
+    pub p95: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct ChunkOptions {
+    // trailing chars of the previous paragraph prepended to the next one (naive path only)
+    pub overlap_chars: usize,
+    // lines of source preceding a tree-sitter node that are included as leading context
+    pub context_lines: usize,
+    // chunks longer than this (in bytes) are subdivided: tree-sitter chunks along child nodes,
+    // then lines, and naive paragraphs with the recursive splitter
+    pub max_chunk_chars: Option<usize>,
+    // consecutive same-type chunks shorter than this are merged (spanning the source between them)
+    pub min_chunk_chars: Option<usize>,
+    // markdown headings deeper than this stay inside their parent section (None splits on all)
+    pub heading_split_level: Option<usize>,
+    // pull doc comments, attributes and decorators directly above a node into its chunk
+    pub attach_leading_comments: bool,
+    // also emit functions nested in containers (methods) as their own chunks
+    pub emit_nested_functions: bool,
+    // definitions up to this many bodies (a namespace's, a class's...) below the root are
+    // chunked as if top-level, under their container's chunk if it has one
+    pub max_nesting_depth: usize,
+    // when paragraph splitting leaves one chunk longer than this, recursively split it on
+    // progressively finer separators (with `overlap_chars` of overlap) instead
+    pub target_chars: Option<usize>,
+    // prose mode: naive paragraphs longer than this are split between sentences into chunks
+    // of about this size, each starting with the last `sentence_overlap` sentences of the one
+    // before; a single longer sentence stays whole
+    pub sentence_target_chars: Option<usize>,
+    pub sentence_overlap: usize,
+    // naive chunking splits paragraphs wherever any of these occurs; line endings are left
+    // as-is so chunk offsets stay valid, hence both LF and CRLF blank lines by default
+    pub paragraph_delimiters: Vec<String>,
+    // remove comments from tree-sitter chunk text (doc comments stay while
+    // `attach_leading_comments` is on); offsets still span the original source
+    pub strip_comments: bool,
+    // what happens to containers whose functions are chunks of their own; see `ContainerMode`
+    pub container_mode: ContainerMode,
+    // chunks whose `raw_kind` is listed are dropped, e.g. `mod_item` for Rust modules that only
+    // re-export; whatever an excluded container held goes with it unless emitted separately
+    pub exclude_raw_kinds: HashSet<String>,
+    // when set, the size budgets above are counted in tokens instead of bytes
+    pub token_counter: Option<Arc<dyn TokenCounter>>,
+    // fail with `RAGError::NoChunks` on documents with nothing to chunk instead of emitting
+    // an empty whole-document chunk
+    pub error_on_empty: bool,
+    // trim surrounding whitespace from chunk text; when off, chunks keep it (and a node's
+    // indentation), so text and offsets cover the raw span
+    pub trim: bool,
+}
+
+impl Default for ChunkOptions {
+    fn default() -> Self {
+        Self {
+            overlap_chars: 0,
+            context_lines: 0,
+            max_chunk_chars: None,
+            min_chunk_chars: None,
+            heading_split_level: None,
+            attach_leading_comments: true,
+            emit_nested_functions: false,
+            max_nesting_depth: 0,
+            target_chars: None,
+            sentence_target_chars: None,
+            sentence_overlap: 0,
+            paragraph_delimiters: vec!["\n\n".to_string(), "\r\n\r\n".to_string()],
+            strip_comments: false,
+            container_mode: ContainerMode::Full,
+            exclude_raw_kinds: HashSet::new(),
+            token_counter: None,
+            error_on_empty: false,
+            trim: true,
+        }
+    }
+}
+
+impl ChunkOptions {
+    // Size of `text` in the unit the budgets are expressed in
+    fn measure(&self, text: &str) -> usize {
+        match &self.token_counter {
+            Some(counter) => counter.count(text),
+            None => text.len(),
+        }
+    }
+
+    // Absolute byte range of a chunk's text within `text`, which sits at `offset` in its
+    // document: the trimmed content, or all of it when `trim` is off
+    fn content_range(&self, text: &str, offset: usize) -> (usize, usize) {
+        if self.trim {
+            trimmed_range(text, offset)
+        } else {
+            (offset, offset + text.len())
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Chunker {
+    options: ChunkOptions,
+    // extension -> (container, function) query overrides
+    queries: HashMap<String, (Option<String>, Option<String>)>,
+    // extension -> grammar registered with `with_language`, ahead of `LANGUAGE_MAP`
+    languages: HashMap<String, Language>,
+    // extension -> root node kinds registered with `with_root_kinds`, replacing `ROOT_KINDS`
+    root_kinds: HashMap<String, Vec<String>>,
+}
+
+impl Chunker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_options(options: ChunkOptions) -> Self {
+        Self {
+            options,
+            ..Self::default()
+        }
+    }
+
+    pub fn builder() -> ChunkerBuilder {
+        ChunkerBuilder::default()
+    }
+
+    pub fn options(&self) -> &ChunkOptions {
+        &self.options
+    }
+
+    /// Overrides the built-in tree-sitter queries for `ext`. `None` keeps the built-in query for
+    /// that tier and an empty string disables the tier entirely. Queries are compiled here, so a
+    /// bad query (or an extension without a grammar) fails with `RAGError::ParsingFailed`.
+    pub fn with_query(
+        &mut self,
+        ext: &str,
+        container: Option<String>,
+        function: Option<String>,
+    ) -> Result<&mut Self> {
+        let parsing_failed = || RAGError::ParsingFailed {
+            extension: ext.to_string(),
+        };
+
+        let lang = self.language_for(ext).ok_or_else(parsing_failed)?;
+        for query in [&container, &function].into_iter().flatten() {
+            if !query.trim().is_empty() {
+                Query::new(lang, query).map_err(|_| parsing_failed())?;
+            }
+        }
+
+        self.queries.insert(ext.to_string(), (container, function));
+        Ok(self)
+    }
+
+    /// Parses `ext` files with `language`, replacing the built-in grammar if there is one.
+    /// Without built-in queries for `ext`, register some with [`with_query`](Self::with_query).
+    /// A grammar the linked tree-sitter can't load is not an error here: its documents fall
+    /// back to paragraph chunking with `parse_failed` set in their stats.
+    pub fn with_language(&mut self, ext: &str, language: Language) -> &mut Self {
+        self.languages.insert(ext.to_string(), language);
+        self
+    }
+
+    /// Replaces the node kinds that count as the root of an `ext` syntax tree (by default
+    /// [`ROOT_KINDS`]). Query matches whose parent is one of them are chunked as top-level
+    /// definitions; see [`ChunkOptions::max_nesting_depth`] for reaching deeper.
+    pub fn with_root_kinds<S: Into<String>>(
+        &mut self,
+        ext: &str,
+        kinds: impl IntoIterator<Item = S>,
+    ) -> &mut Self {
+        let kinds = kinds.into_iter().map(Into::into).collect();
+        self.root_kinds.insert(ext.to_string(), kinds);
+        self
+    }
+
+    pub fn chunk_all_documents(
+        &self,
+        docs: &[Document],
+    ) -> Result<(Vec<Chunk>, HashMap<ChunkID, usize>)> {
+        let (chunks, _) = self.chunk_all_documents_with_stats(docs)?;
+
+        let id_to_idx: HashMap<ChunkID, usize> =
+            chunks.iter().enumerate().map(|(i, c)| (c.id, i)).collect();
+
+        Ok((chunks, id_to_idx))
+    }
+
+    /// Like [`chunk_all_documents`](Self::chunk_all_documents), but also reports
+    /// how each document was chunked, keyed by document ID.
+    pub fn chunk_all_documents_with_stats(
+        &self,
+        docs: &[Document],
+    ) -> Result<(Vec<Chunk>, HashMap<DocumentID, ChunkStats>)> {
+        let per_doc: Vec<(Vec<Chunk>, ChunkStats)> = docs
+            .par_iter()
+            .map(|doc| self.chunk_document_with_stats(doc))
+            .collect::<Result<_>>()?;
+
+        let mut stats = HashMap::with_capacity(docs.len());
+        let mut chunks = Vec::new();
+        for (doc, (doc_chunks, doc_stats)) in docs.iter().zip(per_doc) {
+            stats.insert(doc.id, doc_stats);
+            chunks.extend(doc_chunks);
+        }
+
+        Ok((chunks, stats))
+    }
+
+    pub fn chunk_document(&self, doc: &Document) -> Result<Vec<Chunk>> {
+        Ok(self.chunk_document_with_stats(doc)?.0)
+    }
+
+    pub fn chunk_document_with_stats(&self, doc: &Document) -> Result<(Vec<Chunk>, ChunkStats)> {
+        let syntax = doc.syntax();
+        let lang = self.language_for(syntax);
+        let roots: Vec<&str> = match self.root_kinds.get(syntax) {
+            Some(kinds) => kinds.iter().map(String::as_str).collect(),
+            None => ROOT_KINDS.to_vec(),
+        };
+        chunk_document(doc, lang, &self.options, &self.queries_for(syntax), &roots)
+    }
+
+    fn language_for(&self, ext: &str) -> Option<&Language> {
+        self.languages.get(ext).or_else(|| LANGUAGE_MAP.get(ext))
+    }
+
+    // Built-in queries with any registered overrides applied
+    fn queries_for(&self, ext: &str) -> (Option<String>, Option<String>) {
+        let (container, function) = get_queries_from_extension(ext);
+        let Some((custom_container, custom_function)) = self.queries.get(ext) else {
+            return (container, function);
+        };
+
+        let pick = |custom: &Option<String>, builtin: Option<String>| match custom {
+            Some(q) if q.trim().is_empty() => None,
+            Some(q) => Some(q.clone()),
+            None => builtin,
+        };
+        (
+            pick(custom_container, container),
+            pick(custom_function, function),
+        )
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ChunkerBuilder {
+    options: ChunkOptions,
+    queries: Vec<(String, Option<String>, Option<String>)>,
+    languages: Vec<(String, Language)>,
+    root_kinds: Vec<(String, Vec<String>)>,
 }
 
-pub fn chunk_all_documents(docs: &[Document]) -> (Vec<Chunk>, HashMap<DocumentID, usize>) {
-    let chunks: Vec<Chunk> = docs.par_iter().flat_map(chunk_document).collect();
+impl ChunkerBuilder {
+    pub fn overlap_chars(mut self, overlap_chars: usize) -> Self {
+        self.options.overlap_chars = overlap_chars;
+        self
+    }
+
+    pub fn context_lines(mut self, context_lines: usize) -> Self {
+        self.options.context_lines = context_lines;
+        self
+    }
+
+    pub fn max_chunk_chars(mut self, max_chunk_chars: usize) -> Self {
+        self.options.max_chunk_chars = Some(max_chunk_chars);
+        self
+    }
+
+    pub fn min_chunk_chars(mut self, min_chunk_chars: usize) -> Self {
+        self.options.min_chunk_chars = Some(min_chunk_chars);
+        self
+    }
+
+    pub fn heading_split_level(mut self, level: usize) -> Self {
+        self.options.heading_split_level = Some(level);
+        self
+    }
+
+    pub fn attach_leading_comments(mut self, attach: bool) -> Self {
+        self.options.attach_leading_comments = attach;
+        self
+    }
+
+    pub fn emit_nested_functions(mut self, emit: bool) -> Self {
+        self.options.emit_nested_functions = emit;
+        self
+    }
+
+    /// Chunks definitions nested up to `depth` bodies below the root as if they
+    /// were top-level (default `0`), e.g. `1` for functions in a C++ namespace
+    /// or classes in a Python class. They keep their container's chunk as
+    /// `parent_id` when it has one.
+    pub fn max_nesting_depth(mut self, depth: usize) -> Self {
+        self.options.max_nesting_depth = depth;
+        self
+    }
+
+    pub fn target_chars(mut self, target_chars: usize) -> Self {
+        self.options.target_chars = Some(target_chars);
+        self
+    }
+
+    /// Prose mode for naive chunking: paragraphs longer than `target_chars`
+    /// are split on sentence boundaries instead of kept whole.
+    pub fn sentence_target_chars(mut self, target_chars: usize) -> Self {
+        self.options.sentence_target_chars = Some(target_chars);
+        self
+    }
+
+    /// Whole sentences repeated at the start of each prose-mode chunk from the
+    /// end of the previous one (default 0).
+    pub fn sentence_overlap(mut self, sentences: usize) -> Self {
+        self.options.sentence_overlap = sentences;
+        self
+    }
 
-    let id_to_idx: HashMap<ChunkID, usize> =
-        chunks.iter().enumerate().map(|(i, c)| (c.id, i)).collect();
+    pub fn token_counter(mut self, counter: Arc<dyn TokenCounter>) -> Self {
+        self.options.token_counter = Some(counter);
+        self
+    }
+
+    /// Replaces the default `"\n\n"` and `"\r\n\r\n"` paragraph breaks used by
+    /// naive chunking, e.g. with a record separator.
+    pub fn paragraph_delimiters<S: Into<String>>(
+        mut self,
+        delimiters: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.options.paragraph_delimiters = delimiters.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Removes comments such as license headers and commented-out code from the
+    /// text of tree-sitter chunks, keeping doc comments unless
+    /// [`attach_leading_comments`](Self::attach_leading_comments) is off. The
+    /// byte and line ranges still cover the original span, so the text no
+    /// longer equals that slice of the source.
+    pub fn strip_comments(mut self, strip: bool) -> Self {
+        self.options.strip_comments = strip;
+        self
+    }
+
+    pub fn container_mode(mut self, mode: ContainerMode) -> Self {
+        self.options.container_mode = mode;
+        self
+    }
+
+    /// Drops chunks of these raw kinds (e.g. `"mod_item"`, `"use_declaration"`)
+    /// before they are returned, so they are never embedded or indexed.
+    pub fn exclude_raw_kinds<S: Into<String>>(
+        mut self,
+        kinds: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.options.exclude_raw_kinds = kinds.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn error_on_empty(mut self, strict: bool) -> Self {
+        self.options.error_on_empty = strict;
+        self
+    }
 
-    (chunks, id_to_idx)
+    /// Whether chunk text is trimmed of surrounding whitespace (the default).
+    /// With `false` it keeps leading indentation and trailing newlines, and
+    /// [`Chunk::char_count`] and the byte range count them too.
+    pub fn trim(mut self, trim: bool) -> Self {
+        self.options.trim = trim;
+        self
+    }
+
+    /// Same semantics as `Chunker::with_query`; queries are validated in `build`.
+    pub fn query(mut self, ext: &str, container: Option<String>, function: Option<String>) -> Self {
+        self.queries.push((ext.to_string(), container, function));
+        self
+    }
+
+    /// Same semantics as `Chunker::with_language`.
+    pub fn language(mut self, ext: &str, language: Language) -> Self {
+        self.languages.push((ext.to_string(), language));
+        self
+    }
+
+    /// Same semantics as `Chunker::with_root_kinds`.
+    pub fn root_kinds<S: Into<String>>(
+        mut self,
+        ext: &str,
+        kinds: impl IntoIterator<Item = S>,
+    ) -> Self {
+        let kinds = kinds.into_iter().map(Into::into).collect();
+        self.root_kinds.push((ext.to_string(), kinds));
+        self
+    }
+
+    pub fn build(self) -> Result<Chunker> {
+        let mut chunker = Chunker::with_options(self.options);
+        // Grammars first, so queries for a registered extension compile against it
+        for (ext, language) in self.languages {
+            chunker.with_language(&ext, language);
+        }
+        for (ext, kinds) in self.root_kinds {
+            chunker.with_root_kinds(&ext, kinds);
+        }
+        for (ext, container, function) in self.queries {
+            chunker.with_query(&ext, container, function)?;
+        }
+        Ok(chunker)
+    }
+}
+
+#[deprecated(note = "use `Chunker::new().chunk_all_documents(docs)`")]
+pub fn chunk_all_documents(docs: &[Document]) -> Result<(Vec<Chunk>, HashMap<ChunkID, usize>)> {
+    Chunker::new().chunk_all_documents(docs)
+}
+
+#[deprecated(note = "use `Chunker::with_options(opts).chunk_all_documents(docs)`")]
+pub fn chunk_all_documents_with(
+    docs: &[Document],
+    opts: &ChunkOptions,
+) -> Result<(Vec<Chunk>, HashMap<ChunkID, usize>)> {
+    Chunker::with_options(opts.clone()).chunk_all_documents(docs)
 }
 
-fn chunk_document(doc: &Document) -> Vec<Chunk> {
-    if let Some(lang) = LANGUAGE_MAP.get(&doc.ext.as_str()) {
-        chunk_with_treesitter(&doc, lang)
+/// Positions in `chunks` of every chunk cut from document `doc_id`, in order. Pairs with the
+/// chunk ID map returned by [`Chunker::chunk_all_documents`] for going from a hit back to the
+/// rest of its document.
+pub fn chunks_for_document(chunks: &[Chunk], doc_id: &DocumentID) -> Vec<usize> {
+    chunks
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| c.doc_id == *doc_id)
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// Chunk counts per kind and per file extension, plus the distribution of
+/// chunk sizes. Thousands of tiny chunks or a p95 pinned at `max_chunk_chars`
+/// usually mean the limits are off.
+pub fn corpus_stats(chunks: &[Chunk]) -> CorpusStats {
+    let mut stats = CorpusStats {
+        total_chunks: chunks.len(),
+        ..CorpusStats::default()
+    };
+    for chunk in chunks {
+        *stats
+            .by_raw_kind
+            .entry(chunk.raw_kind.to_string())
+            .or_default() += 1;
+        let ext = std::path::Path::new(&chunk.path)
+            .extension()
+            .map(|e| e.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        *stats.by_extension.entry(ext).or_default() += 1;
+    }
+
+    let mut sizes: Vec<usize> = chunks.iter().map(|c| c.char_count).collect();
+    sizes.sort_unstable();
+    if let (Some(&min), Some(&max)) = (sizes.first(), sizes.last()) {
+        let rank = |p: f64| sizes[((p * sizes.len() as f64).ceil() as usize).max(1) - 1];
+        stats.char_counts = SizeHistogram {
+            min,
+            max,
+            mean: sizes.iter().sum::<usize>() as f64 / sizes.len() as f64,
+            p50: rank(0.5),
+            p95: rank(0.95),
+        };
+    }
+    stats
+}
+
+fn chunk_document(
+    doc: &Document,
+    lang: Option<&Language>,
+    opts: &ChunkOptions,
+    queries: &(Option<String>, Option<String>),
+    roots: &[&str],
+) -> Result<(Vec<Chunk>, ChunkStats)> {
+    // Every strategy finds something in non-blank text, so only blank documents end up empty
+    if opts.error_on_empty && doc.text.trim().is_empty() {
+        return Err(RAGError::NoChunks { doc_id: doc.id });
+    }
+
+    // function bodies inside containers, for `ContainerMode::Skeleton`
+    let mut elided_bodies = vec![];
+    let (mut chunks, strategy, parse_failed) = if let Some(lang) = lang {
+        match chunk_with_treesitter(doc, lang, opts, queries, roots) {
+            Ok(Some((chunks, strategy, bodies))) => {
+                elided_bodies = bodies;
+                (chunks, strategy, false)
+            }
+            Ok(None) => {
+                log::debug!(
+                    "{} has syntax errors; splitting it into paragraphs",
+                    doc.path
+                );
+                let chunks = naive_chunk_document(&doc.text, doc.id, opts);
+                (chunks, ChunkStrategy::Naive, true)
+            }
+            Err(RAGError::ParsingFailed { extension }) => {
+                log::warn!(
+                    "no usable grammar for .{extension}; splitting {} into paragraphs",
+                    doc.path
+                );
+                let chunks = naive_chunk_document(&doc.text, doc.id, opts);
+                (chunks, ChunkStrategy::Naive, true)
+            }
+            Err(e) => return Err(e),
+        }
+    } else if matches!(doc.syntax(), "md" | "markdown") {
+        (chunk_markdown(doc, opts), ChunkStrategy::Markdown, false)
+    } else if doc.syntax() == "toml" {
+        (chunk_toml(doc, opts), ChunkStrategy::Toml, false)
     } else {
-        naive_chunk_document(&doc.text, doc.id)
+        let chunks = naive_chunk_document(&doc.text, doc.id, opts);
+        (chunks, ChunkStrategy::Naive, false)
+    };
+
+    let comments: Vec<Splice> = match lang {
+        Some(lang) if opts.strip_comments && strategy != ChunkStrategy::Naive => {
+            comment_ranges(&doc.text, lang, opts.attach_leading_comments)
+                .into_iter()
+                .map(|(s, e)| (s, e, ""))
+                .collect()
+        }
+        _ => vec![],
+    };
+    if !comments.is_empty() || !elided_bodies.is_empty() {
+        let mut outline = comments.clone();
+        outline.extend(elided_bodies);
+        outline.sort_by_key(|&(s, e, _)| (s, std::cmp::Reverse(e)));
+        // IDs hash the text, so edited chunks get new ones that their children must follow
+        let mut renamed: HashMap<ChunkID, ChunkID> = HashMap::new();
+        for chunk in &mut chunks {
+            // a function keeps its own body
+            let splices = if chunk.kind == ChunkKind::Function {
+                &comments
+            } else {
+                &outline
+            };
+            if let Some(text) = splice(&doc.text, chunk.start_byte, chunk.end_byte, splices) {
+                let id = compute_chunk_id(&chunk.doc_id, chunk.start_byte, &text);
+                renamed.insert(chunk.id, id);
+                chunk.char_count = text.len();
+                chunk.id = id;
+                chunk.text = text;
+            }
+        }
+        for chunk in &mut chunks {
+            if let Some(parent) = chunk.parent_id.and_then(|p| renamed.get(&p)) {
+                chunk.parent_id = Some(*parent);
+            }
+        }
+    }
+
+    // after splicing, which re-slices the source, so merged texts keep their blank-line joins
+    if let Some(min) = opts.min_chunk_chars {
+        chunks = merge_small_chunks(chunks, min, opts);
+    }
+
+    if !opts.exclude_raw_kinds.is_empty() {
+        let (excluded, kept): (Vec<Chunk>, Vec<Chunk>) = chunks
+            .into_iter()
+            .partition(|c| opts.exclude_raw_kinds.contains(c.raw_kind.as_ref()));
+        let excluded: HashSet<ChunkID> = excluded.iter().map(|c| c.id).collect();
+        chunks = kept;
+        for chunk in &mut chunks {
+            if chunk.parent_id.is_some_and(|p| excluded.contains(&p)) {
+                chunk.parent_id = None;
+            }
+        }
+    }
+
+    for chunk in &mut chunks {
+        chunk.path.clone_from(&doc.path);
+    }
+
+    let mut by_position: Vec<usize> = (0..chunks.len()).collect();
+    by_position.sort_by_key(|&i| (chunks[i].start_byte, std::cmp::Reverse(chunks[i].end_byte)));
+    for (order, i) in by_position.into_iter().enumerate() {
+        chunks[i].doc_order = order;
+    }
+
+    if let Some(counter) = &opts.token_counter {
+        for chunk in &mut chunks {
+            chunk.token_count = Some(counter.count(&chunk.text));
+        }
     }
+
+    let stats = ChunkStats {
+        strategy,
+        chunk_count: chunks.len(),
+        parse_failed,
+    };
+    Ok((chunks, stats))
+}
+
+// Coalesces runs of same-type chunks from one document that follow each other in the
+// source until each reaches `min`, joining their texts with a blank line. Tree-sitter emits
+// chunks tier by tier, so they are put in source order first; a chunk nested in or between
+// two others then sits between them and keeps them apart. Runs per document, so the result
+// doesn't depend on how rayon schedules documents.
+fn merge_small_chunks(mut chunks: Vec<Chunk>, min: usize, opts: &ChunkOptions) -> Vec<Chunk> {
+    chunks.sort_by_key(|c| (c.start_byte, std::cmp::Reverse(c.end_byte)));
+    let mut merged: Vec<Chunk> = Vec::with_capacity(chunks.len());
+    // merged chunks get new IDs that their children must follow
+    let mut renamed: HashMap<ChunkID, ChunkID> = HashMap::new();
+
+    for chunk in chunks {
+        match merged.last_mut() {
+            Some(last)
+                if opts.measure(&last.text) < min
+                    && last.doc_id == chunk.doc_id
+                    && last.raw_kind == chunk.raw_kind
+                    && last.parent_id == chunk.parent_id
+                    && last.end_byte <= chunk.start_byte =>
+            {
+                last.text = format!("{}\n\n{}", last.text, chunk.text);
+                last.char_count = last.text.len();
+                last.end_line = chunk.end_line;
+                last.end_byte = chunk.end_byte;
+                let id = compute_chunk_id(&last.doc_id, last.start_byte, &last.text);
+                renamed.insert(last.id, id);
+                renamed.insert(chunk.id, id);
+                last.id = id;
+            }
+            _ => merged.push(chunk),
+        }
+    }
+
+    if !renamed.is_empty() {
+        for chunk in &mut merged {
+            // a parent merged more than once was renamed once per merge
+            while let Some(&id) = chunk.parent_id.as_ref().and_then(|p| renamed.get(p)) {
+                chunk.parent_id = Some(id);
+            }
+        }
+    }
+    merged
 }
 
 lazy_static! {
+    /// Grammars compiled into this build, keyed by file extension. Each comes from a `lang-*`
+    /// feature, all of them on by default; files whose grammar was left out are chunked like
+    /// any other unsupported file.
     pub static ref LANGUAGE_MAP: HashMap<&'static str, Language> = {
+        #[allow(unused_mut)] // stays empty with every `lang-*` feature off
         let mut m = HashMap::new();
+        #[cfg(feature = "lang-rust")]
         m.insert("rs", tree_sitter_rust::LANGUAGE.into());
+        #[cfg(feature = "lang-cpp")]
         m.insert("cpp", tree_sitter_cpp::LANGUAGE.into());
+        #[cfg(feature = "lang-cpp")]
         m.insert("hpp", tree_sitter_cpp::LANGUAGE.into());
+        #[cfg(feature = "lang-c")]
         m.insert("c", tree_sitter_c::LANGUAGE.into());
+        #[cfg(feature = "lang-c")]
         m.insert("h", tree_sitter_c::LANGUAGE.into());
+        #[cfg(feature = "lang-csharp")]
+        m.insert("cs", tree_sitter_c_sharp::LANGUAGE.into());
+        #[cfg(feature = "lang-javascript")]
         m.insert("js", tree_sitter_javascript::LANGUAGE.into());
+        #[cfg(feature = "lang-python")]
         m.insert("py", tree_sitter_python::LANGUAGE.into());
+        #[cfg(feature = "lang-cuda")]
         m.insert("cu", tree_sitter_cuda::LANGUAGE.into());
+        #[cfg(feature = "lang-go")]
+        m.insert("go", tree_sitter_go::LANGUAGE.into());
+        #[cfg(feature = "lang-java")]
+        m.insert("java", tree_sitter_java::LANGUAGE.into());
+        #[cfg(feature = "lang-typescript")]
+        m.insert("ts", tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into());
+        #[cfg(feature = "lang-typescript")]
+        m.insert("tsx", tree_sitter_typescript::LANGUAGE_TSX.into());
+        #[cfg(feature = "lang-html")]
+        m.insert("html", tree_sitter_html::LANGUAGE.into());
+        #[cfg(feature = "lang-html")]
+        m.insert("htm", tree_sitter_html::LANGUAGE.into());
+        #[cfg(feature = "lang-css")]
+        m.insert("css", tree_sitter_css::LANGUAGE.into());
+        #[cfg(feature = "lang-scss")]
+        m.insert("scss", tree_sitter_scss::language());
+        #[cfg(feature = "lang-bash")]
+        m.insert("sh", tree_sitter_bash::LANGUAGE.into());
+        #[cfg(feature = "lang-bash")]
+        m.insert("bash", tree_sitter_bash::LANGUAGE.into());
+        #[cfg(feature = "lang-json")]
+        m.insert("json", tree_sitter_json::LANGUAGE.into());
+        #[cfg(feature = "lang-php")]
+        m.insert("php", tree_sitter_php::LANGUAGE_PHP.into());
+        #[cfg(feature = "lang-yaml")]
+        m.insert("yaml", tree_sitter_yaml::LANGUAGE.into());
+        #[cfg(feature = "lang-yaml")]
+        m.insert("yml", tree_sitter_yaml::LANGUAGE.into());
+        #[cfg(feature = "lang-xml")]
+        m.insert("xml", tree_sitter_xml::LANGUAGE_XML.into());
         m
     };
 }
 
-fn chunk_with_treesitter(doc: &Document, lang: &Language) -> Vec<Chunk> {
+/// Kinds of the root node of every built-in grammar's syntax tree. A query match whose parent
+/// is one of these is a top-level definition; [`Chunker::with_root_kinds`] replaces the list
+/// for one extension.
+pub const ROOT_KINDS: &[&str] = &[
+    "source_file",
+    "compilation_unit",
+    "module",
+    "program",
+    "translation_unit",
+    "document",
+    "fragment",
+    "stylesheet",
+    "stream",
+];
+
+// Languages whose top-level statements are chunked alongside the queried definitions
+const SCRIPT_EXTENSIONS: [&str; 2] = ["sh", "bash"];
+
+// The chunks, how they were found, and the function bodies `ContainerMode::Skeleton` elides
+type TreeChunks = (Vec<Chunk>, ChunkStrategy, Vec<Splice>);
+
+fn chunk_with_treesitter(
+    doc: &Document,
+    lang: &Language,
+    opts: &ChunkOptions,
+    queries: &(Option<String>, Option<String>),
+    roots: &[&str],
+) -> Result<Option<TreeChunks>> {
     let mut chunks = vec![];
+    let mut bodies = vec![];
+    let ids = ChunkIds::new(&doc.id);
+    let b_text = doc.text.as_bytes();
+    // the other modes cut function bodies out of their containers
+    let emit_nested = opts.emit_nested_functions || opts.container_mode != ContainerMode::Full;
 
     let mut parser = Parser::new();
-    parser.set_language(lang).expect("Bad language for parser");
-    let tree = match parser.parse(&doc.text, None) {
-        Some(t) => t,
-        None => {
-            return naive_chunk_document(&doc.text, doc.id);
-        }
+    // A grammar built for an ABI this tree-sitter doesn't support
+    parser
+        .set_language(lang)
+        .map_err(|_| RAGError::ParsingFailed {
+            extension: doc.syntax().to_string(),
+        })?;
+    // `None` tells the caller to fall back to paragraphs; error-recovered trees chunk poorly
+    let Some(tree) = parser.parse(&doc.text, None) else {
+        return Ok(None);
     };
     let root = tree.root_node();
+    if root.has_error() {
+        return Ok(None);
+    }
+
+    // Container and function queries, with any user overrides already applied
+    let (container_query_str, function_query_str) = queries;
 
-    // Get both container and function queries
-    let (container_query_str, function_query_str) = get_queries_from_extension(&doc.ext);
+    // tree-sitter node id -> id of the (first) chunk emitted for that container
+    let mut containers: HashMap<usize, ChunkID> = HashMap::new();
+    // tree depth of the node behind each chunk, for `dedup_by_range`
+    let mut depths: Vec<usize> = vec![];
 
-    // Process container-level chunks (classes, structs, etc.)
-    if let Some(container_str) = container_query_str {
-        if let Ok(query) = Query::new(lang, &container_str) {
+    // Containers (classes, structs, etc.) first, then functions
+    for (tier, query_str) in [container_query_str, function_query_str]
+        .into_iter()
+        .enumerate()
+    {
+        let Some(query_str) = query_str else {
+            continue;
+        };
+        let is_function_tier = tier == 1;
+
+        if let Ok(query) = Query::new(lang, query_str) {
             let mut cursor = QueryCursor::new();
-            let b_text = doc.text.as_bytes();
             let mut qmatches = cursor.matches(&query, root, b_text);
+            // Helper captures such as `@type_decl` only constrain the match; when a query
+            // names its chunks `@chunk`, nothing else becomes a chunk
+            let chunk_capture = query.capture_index_for_name("chunk");
 
             while let Some(m) = qmatches.next() {
                 for capture in m.captures {
+                    if chunk_capture.is_some_and(|index| index != capture.index) {
+                        continue;
+                    }
                     let node = capture.node;
 
-                    let is_top_level = node
-                        .parent()
-                        .map(|p| {
-                            p.kind() == "source_file"
-                                || p.kind() == "module"
-                                || p.kind() == "program"
-                        })
-                        .unwrap_or(false);
+                    let nesting = nesting_depth(&node, roots, opts.max_nesting_depth);
+                    let is_top_level = nesting == Some(0);
+
+                    let container = is_function_tier
+                        .then(|| enclosing_container(&node, &containers))
+                        .flatten();
+                    if opts.container_mode == ContainerMode::Skeleton
+                        && !is_top_level
+                        && container.is_some()
+                        && let Some(body) = node.child_by_field_name("body")
+                    {
+                        let elided = if doc.text[body.byte_range()].starts_with('{') {
+                            "{ ... }"
+                        } else {
+                            "..."
+                        };
+                        // a body no longer than the placeholder stays, so no chunk outgrows its span
+                        if body.byte_range().len() > elided.len() {
+                            bodies.push((body.start_byte(), body.end_byte(), elided));
+                        }
+                    }
+
+                    let parent_id = if is_top_level {
+                        None
+                    } else if nesting.is_some() || emit_nested && container.is_some() {
+                        container
+                    } else {
+                        continue;
+                    };
+
+                    let mut new_chunks = node_chunks(doc, &node, opts, &ids)?;
+                    if let Some(first) = new_chunks.first().filter(|_| !is_function_tier) {
+                        containers.insert(node.id(), first.id);
+                    }
+                    for chunk in &mut new_chunks {
+                        chunk.parent_id = parent_id;
+                    }
+                    depths.resize(depths.len() + new_chunks.len(), node_depth(&node));
+                    chunks.extend(new_chunks);
+                }
+            }
+        }
+    }
+
+    let mut chunks = dedup_by_range(chunks, &depths);
+
+    if opts.container_mode == ContainerMode::ChildrenOnly {
+        let parents: HashSet<ChunkID> = chunks.iter().filter_map(|c| c.parent_id).collect();
+        chunks.retain(|c| !parents.contains(&c.id));
+        for chunk in &mut chunks {
+            chunk.parent_id = None;
+        }
+    }
+
+    if SCRIPT_EXTENSIONS.contains(&doc.syntax()) {
+        let body = script_body_chunks(doc, &root, &chunks);
+        chunks.extend(body);
+        chunks.sort_by_key(|c| c.start_byte);
+    }
+
+    if chunks.is_empty() {
+        // no query matched (a header, a JSON array...); bounded like the naive path's fallback
+        if let Some(max) = opts.max_chunk_chars
+            && opts.measure(&doc.text) > max
+        {
+            let chunks = recursive_chunks(&doc.text, 0, doc.text.len(), doc.id, max, opts);
+            return Ok(Some((chunks, ChunkStrategy::WholeDocument, bodies)));
+        }
+        let (start_byte, end_byte) = opts.content_range(&doc.text, 0);
+        let text = &doc.text[start_byte..end_byte];
+        chunks.push(Chunk {
+            id: compute_chunk_id(&doc.id, start_byte, text),
+            doc_id: doc.id,
+            text: text.to_string(),
+            path: String::new(),
+            kind: ChunkKind::WholeDocument,
+            raw_kind: "document".into(),
+            char_count: text.len(),
+            start_line: 1,
+            end_line: doc.text.lines().count().max(1),
+            start_byte,
+            end_byte,
+            doc_order: 0,
+            parent_id: None,
+            token_count: None,
+        });
+        return Ok(Some((chunks, ChunkStrategy::WholeDocument, bodies)));
+    }
+
+    Ok(Some((chunks, ChunkStrategy::TreeSitter, bodies)))
+}
+
+// Paragraphs of the top-level statements not already inside a chunk. Paragraphs break on blank
+// lines between statements, never inside one, so heredocs and multi-line strings stay whole.
+fn script_body_chunks(doc: &Document, root: &Node, chunks: &[Chunk]) -> Vec<Chunk> {
+    let covered = |node: &Node| {
+        chunks
+            .iter()
+            .any(|c| c.start_byte <= node.start_byte() && node.end_byte() <= c.end_byte)
+    };
+
+    let mut paragraphs: Vec<(usize, usize, usize)> = vec![];
+    let mut cursor = root.walk();
+    let mut open = false;
+    for node in root.named_children(&mut cursor) {
+        if covered(&node) {
+            open = false;
+            continue;
+        }
+        let (start, end) = (node.start_byte(), node.end_byte());
+        match paragraphs.last_mut() {
+            Some(last) if open && count_newlines(&doc.text[last.1..start]) < 2 => last.1 = end,
+            _ => paragraphs.push((start, end, node.start_position().row + 1)),
+        }
+        open = true;
+    }
+
+    paragraphs
+        .into_iter()
+        .map(|(start_byte, end_byte, start_line)| {
+            let text = &doc.text[start_byte..end_byte];
+            Chunk {
+                id: compute_chunk_id(&doc.id, start_byte, text),
+                doc_id: doc.id,
+                text: text.to_string(),
+                path: String::new(),
+                kind: ChunkKind::Paragraph,
+                raw_kind: "paragraph".into(),
+                char_count: text.len(),
+                start_line,
+                end_line: start_line + count_newlines(text),
+                start_byte,
+                end_byte,
+                doc_order: 0,
+                parent_id: None,
+                token_count: None,
+            }
+        })
+        .collect()
+}
+
+fn node_depth(node: &Node) -> usize {
+    let mut depth = 0;
+    let mut current = node.parent();
+    while let Some(parent) = current {
+        depth += 1;
+        current = parent.parent();
+    }
+    depth
+}
+
+// Several queries can match the same span (e.g. a wrapper node and its only child). Keeps one
+// chunk per byte range: the one from the deepest, most specific node, or the first emitted on a
+// tie, so the surviving `raw_kind` doesn't depend on match order. Children of a dropped
+// container are re-parented onto the chunk that replaced it.
+fn dedup_by_range(chunks: Vec<Chunk>, depths: &[usize]) -> Vec<Chunk> {
+    let mut keep: HashMap<(usize, usize), usize> = HashMap::with_capacity(chunks.len());
+    for (idx, chunk) in chunks.iter().enumerate() {
+        keep.entry((chunk.start_byte, chunk.end_byte))
+            .and_modify(|kept| {
+                if depths[idx] > depths[*kept] {
+                    *kept = idx;
+                }
+            })
+            .or_insert(idx);
+    }
+    if keep.len() == chunks.len() {
+        return chunks;
+    }
+
+    let replaced: HashMap<ChunkID, ChunkID> = chunks
+        .iter()
+        .map(|c| (c.id, chunks[keep[&(c.start_byte, c.end_byte)]].id))
+        .filter(|(from, to)| from != to)
+        .collect();
+    chunks
+        .into_iter()
+        .enumerate()
+        .filter(|(idx, c)| keep[&(c.start_byte, c.end_byte)] == *idx)
+        .map(|(_, mut c)| {
+            if let Some(parent) = c.parent_id.and_then(|p| replaced.get(&p)) {
+                c.parent_id = Some(*parent);
+            }
+            c
+        })
+        .collect()
+}
+
+fn node_chunks(
+    doc: &Document,
+    node: &Node,
+    opts: &ChunkOptions,
+    ids: &ChunkIds,
+) -> Result<Vec<Chunk>> {
+    let extraction_error = || RAGError::ChunkExtraction {
+        doc_id: doc.id,
+        node_kind: node.kind().to_string(),
+    };
+
+    let node_text = node
+        .utf8_text(doc.text.as_bytes())
+        .map_err(|_| extraction_error())?;
+    if node_text.trim().is_empty() {
+        return Ok(vec![]);
+    }
+    let kind = ChunkKind::of_node(node);
+
+    let node_start = if opts.attach_leading_comments {
+        attached_start(&doc.text, node)
+    } else {
+        node.start_byte()
+    };
+    let mut start = leading_context_start(&doc.text, node_start, opts.context_lines);
+    if !opts.trim {
+        start = indent_start(&doc.text, start);
+    }
+    let raw_text = doc
+        .text
+        .get(start..node.end_byte())
+        .ok_or_else(extraction_error)?;
+    let start_row =
+        node.start_position().row + 1 - count_newlines(&doc.text[start..node.start_byte()]);
+
+    Ok(match opts.max_chunk_chars {
+        Some(max) if opts.measure(raw_text.trim()) > max => {
+            // split the chunk's span so the pieces concatenate back to the unsplit chunk text
+            let (trim_start, trim_end) = opts.content_range(raw_text, start);
+
+            let mut ranges = vec![];
+            split_range(
+                &doc.text,
+                *node,
+                trim_start,
+                trim_end,
+                max,
+                opts,
+                &mut ranges,
+            );
+
+            let mut row = start_row + count_newlines(&doc.text[start..trim_start]);
+            absorb_blank_ranges(&doc.text, ranges)
+                .into_iter()
+                .map(|(s, e)| {
+                    let text = &doc.text[s..e];
+                    let (start_line, end_line) = content_lines(text, row);
+                    row += count_newlines(text);
+                    Chunk {
+                        id: ids.id(s, text),
+                        doc_id: doc.id,
+                        text: text.to_string(),
+                        path: String::new(),
+                        kind: kind.clone(),
+                        raw_kind: node.kind().into(),
+                        char_count: text.len(),
+                        start_line,
+                        end_line,
+                        start_byte: s,
+                        end_byte: e,
+                        doc_order: 0,
+                        parent_id: None,
+                        token_count: None,
+                    }
+                })
+                .collect()
+        }
+        _ => {
+            let (start_line, end_line) = content_lines(raw_text, start_row);
+            let (start_byte, end_byte) = opts.content_range(raw_text, start);
+            let id = ids.id(start_byte, raw_text);
+
+            vec![Chunk {
+                id,
+                doc_id: doc.id,
+                text: doc.text[start_byte..end_byte].to_string(),
+                path: String::new(),
+                kind,
+                raw_kind: node.kind().into(),
+                char_count: raw_text.len(),
+                start_line,
+                end_line,
+                start_byte,
+                end_byte,
+                doc_order: 0,
+                parent_id: None,
+                token_count: None,
+            }]
+        }
+    })
+}
+
+// Parent of a definition, looking through Python's decorator wrapper, the root object of a
+// JSON document and the root element of an XML one, whose children are chunked like top-level
+// definitions, and the body of a C# or PHP namespace block, whose types are as top-level as
+// those after `namespace X;`
+fn definition_parent<'t>(node: &Node<'t>) -> Option<Node<'t>> {
+    let parent = node.parent()?;
+    let is_json_root =
+        parent.kind() == "object" && parent.parent().is_some_and(|p| p.kind() == "document");
+    if parent.kind() == "decorated_definition" || is_json_root {
+        return parent.parent();
+    }
+    let xml_root = parent.parent().filter(|p| p.kind() == "element");
+    if parent.kind() == "content"
+        && let Some(root) = xml_root.and_then(|e| e.parent())
+        && root.kind() == "document"
+    {
+        return Some(root);
+    }
+    match parent.parent() {
+        Some(namespace)
+            if matches!(
+                (namespace.kind(), parent.kind()),
+                ("namespace_declaration", "declaration_list")
+                    | ("namespace_definition", "compound_statement")
+            ) =>
+        {
+            definition_parent(&namespace)
+        }
+        _ => Some(parent),
+    }
+}
+
+// How many bodies (a namespace's, a class's...) `node` sits inside below a node of a `roots`
+// kind, or `None` if that takes more than `max` or there is no such root
+fn nesting_depth(node: &Node, roots: &[&str], max: usize) -> Option<usize> {
+    let mut current = *node;
+    for depth in 0..=max {
+        let parent = definition_parent(&current)?;
+        if roots.contains(&parent.kind()) {
+            return Some(depth);
+        }
+        let owner = parent.parent()?;
+        if owner.child_by_field_name("body") != Some(parent) {
+            return None;
+        }
+        current = owner;
+    }
+    None
+}
+
+fn enclosing_container(node: &Node, containers: &HashMap<usize, ChunkID>) -> Option<ChunkID> {
+    let mut current = node.parent();
+    while let Some(ancestor) = current {
+        if let Some(id) = containers.get(&ancestor.id()) {
+            return Some(*id);
+        }
+        current = ancestor.parent();
+    }
+    None
+}
+
+// Start of the decorators, attributes and comments directly above `node` (no blank line between)
+fn attached_start(text: &str, node: &Node) -> usize {
+    let mut anchor = match node.parent() {
+        Some(p) if p.kind() == "decorated_definition" => p,
+        _ => *node,
+    };
+
+    while let Some(prev) = prev_non_blank_sibling(text, &anchor) {
+        if !matches!(
+            prev.kind(),
+            "comment"
+                | "line_comment"
+                | "block_comment"
+                | "Comment"
+                | "js_comment"
+                | "attribute_item"
+        ) {
+            break;
+        }
+
+        // some grammars include the trailing newline in line comments
+        let gap = &text[prev.end_byte()..anchor.start_byte()];
+        let newlines = count_newlines(gap) + usize::from(text[..prev.end_byte()].ends_with('\n'));
+        let line_start = text[..prev.start_byte()].rfind('\n').map_or(0, |i| i + 1);
+        let owns_line = text[line_start..prev.start_byte()].trim().is_empty();
+        if newlines > 1 || !gap.trim().is_empty() || !owns_line {
+            break;
+        }
+
+        anchor = prev;
+    }
+
+    anchor.start_byte()
+}
+
+// Previous sibling, passing over the whitespace-only text nodes XML keeps between elements
+fn prev_non_blank_sibling<'t>(text: &str, node: &Node<'t>) -> Option<Node<'t>> {
+    let mut prev = node.prev_sibling()?;
+    while prev.kind() == "CharData" && text[prev.byte_range()].trim().is_empty() {
+        prev = prev.prev_sibling()?;
+    }
+    Some(prev)
+}
+
+const COMMENT_KINDS: [&str; 5] = [
+    "comment",
+    "line_comment",
+    "block_comment",
+    "Comment",
+    "js_comment",
+];
+
+// Byte ranges of every comment node in `text`, sorted. Parses again rather than threading the
+// tree out of `chunk_with_treesitter`; only documents chunked with `strip_comments` pay for it.
+fn comment_ranges(text: &str, lang: &Language, keep_docs: bool) -> Vec<(usize, usize)> {
+    let mut parser = Parser::new();
+    let Some(tree) = parser.set_language(lang).ok().and(parser.parse(text, None)) else {
+        return vec![];
+    };
+
+    let mut ranges = vec![];
+    let mut cursor = tree.walk();
+    'walk: loop {
+        let node = cursor.node();
+        let is_comment = COMMENT_KINDS.contains(&node.kind());
+        if is_comment && !(keep_docs && is_doc_comment(&text[node.byte_range()])) {
+            ranges.push((node.start_byte(), node.end_byte()));
+        }
+        if !is_comment && cursor.goto_first_child() {
+            continue;
+        }
+        while !cursor.goto_next_sibling() {
+            if !cursor.goto_parent() {
+                break 'walk;
+            }
+        }
+    }
+    ranges
+}
+
+fn is_doc_comment(comment: &str) -> bool {
+    (comment.starts_with("///") && !comment.starts_with("////"))
+        || comment.starts_with("//!")
+        || (comment.starts_with("/**") && !comment.starts_with("/**/"))
+        || comment.starts_with("/*!")
+}
+
+// A byte range of the source to replace in chunk text, and its replacement
+type Splice = (usize, usize, &'static str);
+
+// text[start..end] with the splices wholly inside it applied, or `None` if there are none.
+// Splices must be sorted by start; one inside an earlier one is skipped. An empty replacement
+// removes a comment: alone on its lines it takes those lines with it, trailing code it takes
+// the spaces before it. The result is trimmed like any chunk text.
+fn splice(text: &str, start: usize, end: usize, splices: &[Splice]) -> Option<String> {
+    let first = splices.partition_point(|&(s, _, _)| s < start);
+    let inside = splices[first..]
+        .iter()
+        .take_while(|&&(s, _, _)| s < end)
+        .filter(|&&(_, e, _)| e <= end);
+
+    let mut out = String::new();
+    let mut pos = start;
+    for &(s, e, replacement) in inside {
+        if s < pos {
+            continue;
+        }
+        if !replacement.is_empty() {
+            out.push_str(&text[pos..s]);
+            out.push_str(replacement);
+            pos = e;
+            continue;
+        }
+        // some grammars include the trailing newline in line comments
+        let body_end = if text[..e].ends_with('\n') { e - 1 } else { e };
+        let line_start = text[..s].rfind('\n').map_or(0, |i| i + 1).max(pos);
+        let line_end = text[body_end..end].find('\n').map_or(end, |i| body_end + i);
+        let (cut_start, cut_end) = if text[line_start..s].trim().is_empty()
+            && text[body_end..line_end].trim().is_empty()
+        {
+            (line_start, (line_end + 1).min(end))
+        } else {
+            let before = text[pos..s].trim_end_matches([' ', '\t']);
+            (pos + before.len(), body_end)
+        };
+        out.push_str(&text[pos..cut_start.max(pos)]);
+        pos = pos.max(cut_end);
+    }
+    if pos == start {
+        return None;
+    }
+    out.push_str(&text[pos..end]);
+    Some(out.trim().to_string())
+}
+
+// Partitions text[start..end] into contiguous ranges of at most `max` (see `ChunkOptions::measure`),
+// cutting between child nodes where possible and falling back to lines, words, then chars.
+fn split_range(
+    text: &str,
+    node: Node,
+    start: usize,
+    end: usize,
+    max: usize,
+    opts: &ChunkOptions,
+    out: &mut Vec<(usize, usize)>,
+) {
+    if opts.measure(&text[start..end]) <= max {
+        out.push((start, end));
+        return;
+    }
+
+    let mut cursor = node.walk();
+    let children: Vec<Node> = node
+        .children(&mut cursor)
+        .filter(|c| c.end_byte() > start && c.start_byte() < end)
+        .collect();
+
+    match children.as_slice() {
+        [] => return split_lines(text, start, end, max, opts, out),
+        [only] => return split_range(text, *only, start, end, max, opts, out),
+        _ => {}
+    }
+
+    // each child owns the gap before it; the last one also owns the tail of the range
+    let mut seg_start = start;
+    let mut acc_start = start;
+    for (i, child) in children.iter().enumerate() {
+        let seg_end = if i + 1 == children.len() {
+            end
+        } else {
+            child.end_byte().clamp(seg_start, end)
+        };
+
+        if opts.measure(&text[seg_start..seg_end]) > max {
+            if acc_start < seg_start {
+                out.push((acc_start, seg_start));
+            }
+            split_range(text, *child, seg_start, seg_end, max, opts, out);
+            acc_start = seg_end;
+        } else if opts.measure(&text[acc_start..seg_end]) > max {
+            out.push((acc_start, seg_start));
+            acc_start = seg_start;
+        }
+        seg_start = seg_end;
+    }
+
+    if acc_start < end {
+        out.push((acc_start, end));
+    }
+}
+
+fn split_lines(
+    text: &str,
+    start: usize,
+    end: usize,
+    max: usize,
+    opts: &ChunkOptions,
+    out: &mut Vec<(usize, usize)>,
+) {
+    let mut acc_start = start;
+    let mut pos = start;
+    for line in text[start..end].split_inclusive('\n') {
+        let line_end = pos + line.len();
+        if pos > acc_start && opts.measure(&text[acc_start..line_end]) > max {
+            out.push((acc_start, pos));
+            acc_start = pos;
+        }
+        // a single line over the budget is cut on words, then chars
+        if opts.measure(&text[acc_start..line_end]) > max {
+            out.extend(recursive_split(
+                text,
+                acc_start,
+                line_end,
+                max,
+                0,
+                &RECURSIVE_SEPARATORS[3..],
+                opts,
+            ));
+            acc_start = line_end;
+        }
+        pos = line_end;
+    }
+
+    if acc_start < end {
+        out.push((acc_start, end));
+    }
+}
+
+// Folds whitespace-only ranges into their neighbour so no sub-chunk is empty
+fn absorb_blank_ranges(text: &str, ranges: Vec<(usize, usize)>) -> Vec<(usize, usize)> {
+    let mut merged: Vec<(usize, usize)> = Vec::with_capacity(ranges.len());
+    let mut pending_start: Option<usize> = None;
+
+    for (s, e) in ranges {
+        if text[s..e].trim().is_empty() {
+            match merged.last_mut() {
+                Some(last) => last.1 = e,
+                None => pending_start = pending_start.or(Some(s)),
+            }
+        } else {
+            merged.push((pending_start.take().unwrap_or(s), e));
+        }
+    }
+
+    merged
+}
+
+// Absolute byte range of the trimmed content of `text`, which sits at `offset` in its document
+fn trimmed_range(text: &str, offset: usize) -> (usize, usize) {
+    let start = text.len() - text.trim_start().len();
+    let end = text.trim_end().len().max(start);
+    (offset + start, offset + end)
+}
+
+fn count_newlines(text: &str) -> usize {
+    text.bytes().filter(|&b| b == b'\n').count()
+}
+
+// 1-based inclusive lines spanned by the non-whitespace content of `text`, which starts on `line`
+fn content_lines(text: &str, line: usize) -> (usize, usize) {
+    let leading = text.len() - text.trim_start().len();
+    let first = line + count_newlines(&text[..leading]);
+    (first, first + count_newlines(text.trim()))
+}
 
-                    if !is_top_level {
-                        continue;
-                    }
+// Start of the line containing `start` when only indentation precedes it there, else `start`
+fn indent_start(text: &str, start: usize) -> usize {
+    let line_start = text[..start].rfind('\n').map_or(0, |i| i + 1);
+    if text[line_start..start]
+        .trim_start_matches([' ', '\t'])
+        .is_empty()
+    {
+        line_start
+    } else {
+        start
+    }
+}
 
-                    let raw_text = node.utf8_text(doc.text.as_bytes()).ok().expect(":D");
-                    if raw_text.trim().is_empty() {
-                        continue;
-                    }
+// Byte offset of the start of the line `lines` lines above the one containing `start`
+fn leading_context_start(text: &str, start: usize, lines: usize) -> usize {
+    if lines == 0 {
+        return start;
+    }
+    let mut pos = text[..start].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    for _ in 0..lines {
+        if pos == 0 {
+            break;
+        }
+        pos = text[..pos - 1].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    }
+    pos
+}
 
-                    let id = compute_chunk_id(&doc.id, raw_text);
+// Last `n` chars of `text`, sliced on a char boundary
+fn overlap_tail(text: &str, n: usize) -> &str {
+    if n == 0 {
+        return "";
+    }
+    let start = text
+        .char_indices()
+        .rev()
+        .nth(n - 1)
+        .map(|(i, _)| i)
+        .unwrap_or(0);
+    &text[start..]
+}
 
-                    chunks.push(Chunk {
-                        id,
-                        doc_id: doc.id,
-                        text: raw_text.trim().to_string(),
-                        chunk_type: node.kind(),
-                        char_count: raw_text.len(),
-                    });
-                }
-            }
+// One chunk per ATX heading section; the heading line leads the chunk text
+fn chunk_markdown(doc: &Document, opts: &ChunkOptions) -> Vec<Chunk> {
+    let split_level = opts.heading_split_level.unwrap_or(6);
+
+    // (byte offset, 1-based line) where each section begins
+    let mut starts = vec![(0, 1)];
+    let mut in_fence = false;
+    let mut offset = 0;
+    for (i, line) in doc.text.split_inclusive('\n').enumerate() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+        } else if !in_fence
+            && offset > 0
+            && atx_heading_level(line).is_some_and(|level| level <= split_level)
+        {
+            starts.push((offset, i + 1));
         }
+        offset += line.len();
     }
 
-    // Process function-level chunks
-    if let Some(function_str) = function_query_str {
-        if let Ok(query) = Query::new(lang, &function_str) {
-            let mut cursor = QueryCursor::new();
-            let b_text = doc.text.as_bytes();
-            let mut qmatches = cursor.matches(&query, root, b_text);
+    let mut chunks = vec![];
+    for (i, &(start, line)) in starts.iter().enumerate() {
+        let end = starts.get(i + 1).map_or(doc.text.len(), |next| next.0);
+        let raw = &doc.text[start..end];
+        if raw.trim().is_empty() {
+            continue;
+        }
 
-            while let Some(m) = qmatches.next() {
-                for capture in m.captures {
-                    let node = capture.node;
+        let (start_byte, end_byte) = opts.content_range(raw, start);
+        let (start_line, end_line) = content_lines(raw, line);
+        let text = &doc.text[start_byte..end_byte];
+        chunks.push(Chunk {
+            id: compute_chunk_id(&doc.id, start_byte, text),
+            doc_id: doc.id,
+            text: text.to_string(),
+            path: String::new(),
+            kind: ChunkKind::Paragraph,
+            raw_kind: "md_section".into(),
+            char_count: text.len(),
+            start_line,
+            end_line,
+            start_byte,
+            end_byte,
+            doc_order: 0,
+            parent_id: None,
+            token_count: None,
+        });
+    }
 
-                    let is_top_level = node
-                        .parent()
-                        .map(|p| {
-                            p.kind() == "source_file"
-                                || p.kind() == "module"
-                                || p.kind() == "program"
-                        })
-                        .unwrap_or(false);
+    if chunks.is_empty() {
+        return naive_chunk_document(&doc.text, doc.id, opts);
+    }
 
-                    if !is_top_level {
-                        continue;
-                    }
+    chunks
+}
 
-                    let raw_text = node.utf8_text(doc.text.as_bytes()).ok().expect(":D");
-                    if raw_text.trim().is_empty() {
-                        continue;
-                    }
+fn atx_heading_level(line: &str) -> Option<usize> {
+    let trimmed = line.trim_start_matches(' ');
+    if line.len() - trimmed.len() > 3 {
+        return None;
+    }
 
-                    let id = compute_chunk_id(&doc.id, raw_text);
+    let level = trimmed.bytes().take_while(|&b| b == b'#').count();
+    let rest = &trimmed[level..];
+    let is_heading =
+        (1..=6).contains(&level) && (rest.is_empty() || rest.starts_with([' ', '\t', '\r', '\n']));
+    is_heading.then_some(level)
+}
 
-                    chunks.push(Chunk {
-                        id,
-                        doc_id: doc.id,
-                        text: raw_text.trim().to_string(),
-                        chunk_type: node.kind(),
-                        char_count: raw_text.len(),
-                    });
-                }
+// One chunk per TOML table: the root keys before the first header, then every `[table]` and
+// `[[array.of.tables]]` section, led by its header
+fn chunk_toml(doc: &Document, opts: &ChunkOptions) -> Vec<Chunk> {
+    // (byte offset, 1-based line, raw kind) where each section begins
+    let mut starts = vec![(0, 1, "toml_table")];
+    let mut scanner = TomlScanner::default();
+    // where the comment lines directly above the current line begin
+    let mut comments: Option<(usize, usize)> = None;
+    let mut offset = 0;
+    for (i, line) in doc.text.split_inclusive('\n').enumerate() {
+        let trimmed = line.trim_start();
+        if scanner.at_top_level() && trimmed.starts_with('[') {
+            let kind = if trimmed.starts_with("[[") {
+                "toml_array_table"
+            } else {
+                "toml_table"
+            };
+            let (start, start_line) = match comments {
+                Some(above) if opts.attach_leading_comments => above,
+                _ => (offset, i + 1),
+            };
+            // a header opening the file takes over the empty root section
+            if starts.last().is_some_and(|&(last, _, _)| last == start) {
+                starts.pop();
             }
+            starts.push((start, start_line, kind));
+            comments = None;
+        } else if scanner.at_top_level() && trimmed.starts_with('#') {
+            comments.get_or_insert((offset, i + 1));
+        } else {
+            comments = None;
+            scanner.scan(line.as_bytes());
         }
+        offset += line.len();
     }
 
-    if chunks.is_empty() {
-        let id = compute_chunk_id(&doc.id, &doc.text.to_string());
+    let mut chunks = vec![];
+    for (i, &(start, line, kind)) in starts.iter().enumerate() {
+        let end = starts.get(i + 1).map_or(doc.text.len(), |next| next.0);
+        let raw = &doc.text[start..end];
+        if raw.trim().is_empty() {
+            continue;
+        }
+
+        let (start_byte, end_byte) = opts.content_range(raw, start);
+        let (start_line, end_line) = content_lines(raw, line);
+        let text = &doc.text[start_byte..end_byte];
         chunks.push(Chunk {
-            id,
+            id: compute_chunk_id(&doc.id, start_byte, text),
             doc_id: doc.id,
-            text: doc.text.trim().to_string(),
-            chunk_type: "document",
-            char_count: doc.text.len(),
+            text: text.to_string(),
+            path: String::new(),
+            kind: ChunkKind::Other(kind.to_string()),
+            raw_kind: kind.into(),
+            char_count: text.len(),
+            start_line,
+            end_line,
+            start_byte,
+            end_byte,
+            doc_order: 0,
+            parent_id: None,
+            token_count: None,
         });
     }
 
+    if chunks.is_empty() {
+        return naive_chunk_document(&doc.text, doc.id, opts);
+    }
+
     chunks
 }
 
-fn naive_chunk_document(doc_text: &str, doc_id: DocumentID) -> Vec<Chunk> {
+// Whether a TOML line starts inside a value spanning lines (an array, inline table or
+// multi-line string), where a leading `[` or `#` is data rather than a header or comment
+#[derive(Default)]
+struct TomlScanner {
+    depth: usize,
+    multiline: Option<&'static [u8]>,
+}
+
+impl TomlScanner {
+    fn at_top_level(&self) -> bool {
+        self.depth == 0 && self.multiline.is_none()
+    }
+
+    fn scan(&mut self, line: &[u8]) {
+        let mut i = 0;
+        while i < line.len() {
+            let rest = &line[i..];
+            if let Some(delim) = self.multiline {
+                if rest.starts_with(delim) {
+                    self.multiline = None;
+                    i += delim.len();
+                } else {
+                    // only basic strings have escapes
+                    i += 1 + usize::from(rest[0] == b'\\' && delim == b"\"\"\"");
+                }
+                continue;
+            }
+            match rest[0] {
+                b'#' => return,
+                b'"' | b'\'' if rest.starts_with(b"\"\"\"") || rest.starts_with(b"'''") => {
+                    self.multiline = Some(if rest[0] == b'"' { b"\"\"\"" } else { b"'''" });
+                    i += 3;
+                }
+                quote @ (b'"' | b'\'') => {
+                    i += 1;
+                    while i < line.len() && line[i] != quote {
+                        i += 1 + usize::from(line[i] == b'\\' && quote == b'"');
+                    }
+                    i += 1;
+                }
+                b'[' | b'{' => {
+                    self.depth += 1;
+                    i += 1;
+                }
+                b']' | b'}' => {
+                    self.depth = self.depth.saturating_sub(1);
+                    i += 1;
+                }
+                _ => i += 1,
+            }
+        }
+    }
+}
+
+fn naive_chunk_document(doc_text: &str, doc_id: DocumentID, opts: &ChunkOptions) -> Vec<Chunk> {
     let mut chunks = vec![];
-    for para in doc_text.split("\n\n").filter(|p| !p.trim().is_empty()) {
-        let id = compute_chunk_id(&doc_id, &para.to_string());
-        let tcount = para.len();
+    let mut prev: Option<(&str, usize, usize)> = None;
+    let (mut line, mut pos) = (1, 0);
+    for (para_offset, para_end) in paragraph_ranges(doc_text, &opts.paragraph_delimiters) {
+        // ranges only move forward, so line numbers can be tracked incrementally
+        line += count_newlines(&doc_text[pos..para_offset]);
+        pos = para_offset;
+        let (para, para_line) = (&doc_text[para_offset..para_end], line);
+        if para.trim().is_empty() {
+            continue;
+        }
+
+        let (mut start_line, end_line) = content_lines(para, para_line);
+        let mut start_byte = para_offset;
+        let end_byte = para_offset + para.len();
+        // the overlap is re-sliced from the source so the chunk stays a contiguous span
+        if let Some((p, p_line, p_offset)) = prev.filter(|_| opts.overlap_chars > 0) {
+            let tail = overlap_tail(p, opts.overlap_chars);
+            start_line = content_lines(tail, p_line + count_newlines(&p[..p.len() - tail.len()])).0;
+            start_byte = p_offset + p.len() - tail.len();
+        }
+        let text = doc_text[start_byte..end_byte].to_string();
+        prev = Some((para, para_line, para_offset));
+
+        let id = compute_chunk_id(&doc_id, start_byte, &text);
+        let tcount = text.len();
         chunks.push(Chunk {
             id,
             doc_id,
-            text: para.to_string(),
-            chunk_type: "paragraph",
+            text,
+            path: String::new(),
+            kind: ChunkKind::Paragraph,
+            raw_kind: "paragraph".into(),
             char_count: tcount,
+            start_line,
+            end_line,
+            start_byte,
+            end_byte,
+            doc_order: 0,
+            parent_id: None,
+            token_count: None,
         });
     }
 
     if chunks.is_empty() {
-        let id = compute_chunk_id(&doc_id, &doc_text.to_string());
+        let (start_byte, end_byte) = opts.content_range(doc_text, 0);
+        let text = &doc_text[start_byte..end_byte];
         chunks.push(Chunk {
-            id,
+            id: compute_chunk_id(&doc_id, start_byte, text),
+            doc_id,
+            text: text.to_string(),
+            path: String::new(),
+            kind: ChunkKind::WholeDocument,
+            raw_kind: "document".into(),
+            char_count: text.len(),
+            start_line: 1,
+            end_line: doc_text.lines().count().max(1),
+            start_byte,
+            end_byte,
+            doc_order: 0,
+            parent_id: None,
+            token_count: None,
+        });
+    }
+
+    if let Some(target) = opts.sentence_target_chars {
+        chunks = chunks
+            .into_iter()
+            .flat_map(|c| {
+                if c.kind == ChunkKind::Paragraph && opts.measure(&c.text) > target {
+                    let sentences = sentence_ranges(doc_text, c.start_byte, c.end_byte);
+                    let ranges = group_sentences(doc_text, &sentences, target, opts);
+                    chunks_from_ranges(doc_text, c.start_byte, ranges, doc_id, opts)
+                } else {
+                    vec![c]
+                }
+            })
+            .collect();
+    }
+
+    if let (Some(target), [only]) = (opts.target_chars, chunks.as_slice())
+        && opts.measure(&only.text) > target
+    {
+        chunks = recursive_chunks(doc_text, 0, doc_text.len(), doc_id, target, opts);
+    }
+
+    // A file without blank lines (minified code, one-line logs) is a single paragraph, which
+    // must not reach the embedder whole
+    match opts.max_chunk_chars {
+        Some(max) if chunks.iter().any(|c| opts.measure(&c.text) > max) => chunks
+            .into_iter()
+            .flat_map(|c| {
+                if opts.measure(&c.text) > max {
+                    recursive_chunks(doc_text, c.start_byte, c.end_byte, doc_id, max, opts)
+                } else {
+                    vec![c]
+                }
+            })
+            .collect(),
+        _ => chunks,
+    }
+}
+
+// Spans between occurrences of any of `delimiters`, taking the earliest match (the longest on a
+// tie). Empty delimiters are ignored. Each delimiter's next match is cached and only searched
+// for again once the scan passes it, so the text is scanned once per delimiter.
+fn paragraph_ranges(text: &str, delimiters: &[String]) -> Vec<(usize, usize)> {
+    let delimiters: Vec<&str> = delimiters
+        .iter()
+        .map(String::as_str)
+        .filter(|d| !d.is_empty())
+        .collect();
+    let mut next: Vec<Option<usize>> = delimiters.iter().map(|d| text.find(d)).collect();
+    let mut ranges = vec![];
+    let mut start = 0;
+    loop {
+        for (d, found) in delimiters.iter().zip(&mut next) {
+            if found.is_some_and(|at| at < start) {
+                *found = text[start..].find(d).map(|i| start + i);
+            }
+        }
+        let hit = delimiters
+            .iter()
+            .zip(&next)
+            .filter_map(|(d, found)| found.map(|at| (at, d.len())))
+            .min_by_key(|&(at, len)| (at, std::cmp::Reverse(len)));
+        match hit {
+            Some((at, len)) => {
+                ranges.push((start, at));
+                start = at + len;
+            }
+            None => {
+                ranges.push((start, text.len()));
+                return ranges;
+            }
+        }
+    }
+}
+
+const RECURSIVE_SEPARATORS: [&str; 5] = ["\n\n", "\n", ". ", " ", ""];
+
+fn recursive_chunks(
+    doc_text: &str,
+    start: usize,
+    end: usize,
+    doc_id: DocumentID,
+    target: usize,
+    opts: &ChunkOptions,
+) -> Vec<Chunk> {
+    let ranges = recursive_split(
+        doc_text,
+        start,
+        end,
+        target,
+        opts.overlap_chars,
+        &RECURSIVE_SEPARATORS,
+        opts,
+    );
+    chunks_from_ranges(doc_text, start, ranges, doc_id, opts)
+}
+
+// Paragraph chunks for the non-blank ranges of `doc_text` that begin at or after `start` in
+// ascending order, trimmed of surrounding whitespace unless `trim` is off
+fn chunks_from_ranges(
+    doc_text: &str,
+    start: usize,
+    ranges: Vec<(usize, usize)>,
+    doc_id: DocumentID,
+    opts: &ChunkOptions,
+) -> Vec<Chunk> {
+    let (mut pos, mut line) = (start, 1 + count_newlines(&doc_text[..start]));
+    let mut chunks = vec![];
+    for (s, e) in ranges {
+        let (content_start, content_end) = trimmed_range(&doc_text[s..e], s);
+        if content_start == content_end {
+            continue;
+        }
+
+        // ranges only move forward, so line numbers can be tracked incrementally
+        line += count_newlines(&doc_text[pos..content_start]);
+        pos = content_start;
+
+        let (start_byte, end_byte) = opts.content_range(&doc_text[s..e], s);
+        let text = &doc_text[start_byte..end_byte];
+        chunks.push(Chunk {
+            id: compute_chunk_id(&doc_id, start_byte, text),
             doc_id,
-            text: doc_text.trim().to_string(),
-            chunk_type: "document",
-            char_count: doc_text.len(),
+            text: text.to_string(),
+            path: String::new(),
+            kind: ChunkKind::Paragraph,
+            raw_kind: "paragraph".into(),
+            char_count: text.len(),
+            start_line: line,
+            end_line: line + count_newlines(&doc_text[content_start..content_end]),
+            start_byte,
+            end_byte,
+            doc_order: 0,
+            parent_id: None,
+            token_count: None,
         });
     }
 
     chunks
 }
 
+// Splits text[start..end] like LangChain's RecursiveCharacterTextSplitter: cut on the first
+// separator present, merge the pieces back up to `target`, and recurse with the finer
+// separators into any piece that is still too long. Separators stay attached to the preceding
+// piece, so every returned range is a contiguous slice of the source.
+fn recursive_split(
+    text: &str,
+    start: usize,
+    end: usize,
+    target: usize,
+    overlap: usize,
+    separators: &[&str],
+    opts: &ChunkOptions,
+) -> Vec<(usize, usize)> {
+    let span = &text[start..end];
+    let sep_idx = separators
+        .iter()
+        .position(|sep| sep.is_empty() || span.contains(sep))
+        .unwrap_or(separators.len().saturating_sub(1));
+    let (sep, finer) = match separators.get(sep_idx) {
+        Some(sep) => (*sep, &separators[sep_idx + 1..]),
+        None => return vec![(start, end)],
+    };
+
+    let pieces: Vec<(usize, usize)> = if sep.is_empty() {
+        span.char_indices()
+            .map(|(i, c)| (start + i, start + i + c.len_utf8()))
+            .collect()
+    } else {
+        let mut pieces = vec![];
+        let mut piece_start = start;
+        for (i, _) in span.match_indices(sep) {
+            let piece_end = start + i + sep.len();
+            pieces.push((piece_start, piece_end));
+            piece_start = piece_end;
+        }
+        if piece_start < end {
+            pieces.push((piece_start, end));
+        }
+        pieces
+    };
+
+    let mut out = vec![];
+    let mut fitting: Vec<(usize, usize)> = vec![];
+    for (s, e) in pieces {
+        if opts.measure(&text[s..e]) <= target {
+            fitting.push((s, e));
+            continue;
+        }
+
+        out.extend(merge_pieces(text, &fitting, target, overlap, opts));
+        fitting.clear();
+        if finer.is_empty() {
+            out.push((s, e));
+        } else {
+            out.extend(recursive_split(text, s, e, target, overlap, finer, opts));
+        }
+    }
+    out.extend(merge_pieces(text, &fitting, target, overlap, opts));
+
+    out
+}
+
+// Abbreviations whose trailing period doesn't end a sentence, compared lowercased
+const ABBREVIATIONS: [&str; 16] = [
+    "e.g.", "i.e.", "etc.", "vs.", "cf.", "al.", "approx.", "fig.", "no.", "mr.", "mrs.", "ms.",
+    "dr.", "prof.", "st.", "jr.",
+];
+
+// Sentences of text[start..end]: each ends after `.`, `!` or `?` (and any closing quotes or
+// brackets) where whitespace and then a capital letter follow, unless the period closes an
+// abbreviation. Trailing whitespace stays with the sentence so the ranges tile the span.
+fn sentence_ranges(text: &str, start: usize, end: usize) -> Vec<(usize, usize)> {
+    let span = &text[start..end];
+    let mut ranges = vec![];
+    let mut sentence_start = 0;
+    let mut chars = span.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if !matches!(c, '.' | '!' | '?') {
+            continue;
+        }
+        let mut stop = i + c.len_utf8();
+        while let Some(&(j, closing)) = chars.peek() {
+            if !matches!(closing, '"' | '\'' | ')' | ']' | '”' | '’') {
+                break;
+            }
+            stop = j + closing.len_utf8();
+            chars.next();
+        }
+        let after = &span[stop..];
+        let next = after.trim_start();
+        let capital_follows =
+            next.len() < after.len() && next.chars().next().is_some_and(char::is_uppercase);
+        let word = span[sentence_start..stop]
+            .rsplit(char::is_whitespace)
+            .next()
+            .unwrap_or("");
+        if !capital_follows || (c == '.' && ABBREVIATIONS.contains(&word.to_lowercase().as_str())) {
+            continue;
+        }
+        let next_start = span.len() - next.len();
+        ranges.push((start + sentence_start, start + next_start));
+        sentence_start = next_start;
+    }
+    if sentence_start < span.len() {
+        ranges.push((start + sentence_start, end));
+    }
+    ranges
+}
+
+// Groups consecutive sentences into ranges of up to `target`, each but the first starting
+// with the previous range's last `sentence_overlap` sentences when that still moves forward
+fn group_sentences(
+    text: &str,
+    sentences: &[(usize, usize)],
+    target: usize,
+    opts: &ChunkOptions,
+) -> Vec<(usize, usize)> {
+    let mut out = vec![];
+    let mut first = 0;
+    while first < sentences.len() {
+        let mut last = first;
+        let mut total = opts.measure(&text[sentences[first].0..sentences[first].1]);
+        while let Some(&(s, e)) = sentences.get(last + 1) {
+            let len = opts.measure(&text[s..e]);
+            if total + len > target {
+                break;
+            }
+            total += len;
+            last += 1;
+        }
+        out.push((sentences[first].0, sentences[last].1));
+        if last + 1 == sentences.len() {
+            break;
+        }
+        first = (last + 1)
+            .saturating_sub(opts.sentence_overlap)
+            .max(first + 1);
+    }
+    out
+}
+
+// Greedily joins contiguous pieces up to `target`, carrying up to `overlap` worth of trailing
+// pieces into the next range. Piece sizes are summed, which is exact for bytes and close enough
+// for token counts.
+fn merge_pieces(
+    text: &str,
+    pieces: &[(usize, usize)],
+    target: usize,
+    overlap: usize,
+    opts: &ChunkOptions,
+) -> Vec<(usize, usize)> {
+    let mut out = vec![];
+    let mut window: VecDeque<(usize, usize, usize)> = VecDeque::new();
+    let mut total = 0;
+
+    for &(s, e) in pieces {
+        let len = opts.measure(&text[s..e]);
+        if total + len > target && !window.is_empty() {
+            out.push((window[0].0, window[window.len() - 1].1));
+            while total > overlap || (total + len > target && total > 0) {
+                let (_, _, front_len) = window.pop_front().unwrap();
+                total -= front_len;
+            }
+        }
+        window.push_back((s, e, len));
+        total += len;
+    }
+
+    if let (Some(first), Some(last)) = (window.front(), window.back()) {
+        out.push((first.0, last.1));
+    }
+
+    out
+}
+
 // Returns (container_query, function_query)
 fn get_queries_from_extension(extension: &str) -> (Option<String>, Option<String>) {
     match extension {
@@ -312,7 +2335,195 @@ fn get_queries_from_extension(extension: &str) -> (Option<String>, Option<String
                 .to_string(),
             ),
         ),
-        "html" => (
+        "go" => (
+            // Container-level chunks
+            Some(
+                r#"
+                ;; Go type declarations (structs, interfaces, aliases)
+                (type_declaration) @chunk
+                "#
+                .to_string(),
+            ),
+            // Function-level chunks
+            Some(
+                r#"
+                ;; Go functions and methods
+                (function_declaration) @chunk
+                (method_declaration) @chunk
+                "#
+                .to_string(),
+            ),
+        ),
+        "java" => (
+            // Container-level chunks
+            Some(
+                r#"
+                ;; Java types
+                (class_declaration) @chunk
+                (interface_declaration) @chunk
+                (enum_declaration) @chunk
+                (record_declaration) @chunk
+                "#
+                .to_string(),
+            ),
+            // Function-level chunks
+            Some(
+                r#"
+                ;; Java methods live in class bodies, so the top-level filter skips them
+                (method_declaration) @chunk
+                (constructor_declaration) @chunk
+                "#
+                .to_string(),
+            ),
+        ),
+        "cs" => (
+            // Container-level chunks
+            Some(
+                r#"
+                ;; C# types
+                (class_declaration) @chunk
+                (interface_declaration) @chunk
+                (struct_declaration) @chunk
+                "#
+                .to_string(),
+            ),
+            // Function-level chunks
+            Some(
+                r#"
+                ;; C# methods live in type bodies, so the top-level filter skips them
+                (method_declaration) @chunk
+                (constructor_declaration) @chunk
+                "#
+                .to_string(),
+            ),
+        ),
+        "php" => (
+            // Container-level chunks
+            Some(
+                r#"
+                ;; PHP classes and interfaces
+                (class_declaration) @chunk
+                (interface_declaration) @chunk
+                "#
+                .to_string(),
+            ),
+            // Function-level chunks
+            Some(
+                r#"
+                ;; PHP functions and methods (constructors are `__construct` methods)
+                (function_definition) @chunk
+                (method_declaration) @chunk
+                "#
+                .to_string(),
+            ),
+        ),
+        "ts" | "tsx" => (
+            // Container-level chunks
+            Some(
+                r#"
+                ;; TypeScript types, bare or exported
+                (class_declaration) @chunk
+                (abstract_class_declaration) @chunk
+                (interface_declaration) @chunk
+                (enum_declaration) @chunk
+                (type_alias_declaration) @chunk
+                (export_statement
+                    declaration: [
+                        (class_declaration)
+                        (abstract_class_declaration)
+                        (interface_declaration)
+                        (enum_declaration)
+                        (type_alias_declaration)
+                    ]
+                ) @chunk
+                "#
+                .to_string(),
+            ),
+            // Function-level chunks
+            Some(
+                r#"
+                ;; TypeScript functions, bare or exported
+                (function_declaration) @chunk
+                (lexical_declaration (variable_declarator value: (arrow_function))) @chunk
+                (export_statement
+                    declaration: [
+                        (function_declaration)
+                        (lexical_declaration (variable_declarator value: (arrow_function)))
+                    ]
+                ) @chunk
+                "#
+                .to_string(),
+            ),
+        ),
+        "css" => (
+            // Container-level chunks
+            Some(
+                r#"
+                ;; Stylesheet rule sets and at-rule blocks
+                (rule_set) @chunk
+                (media_statement) @chunk
+                (keyframes_statement) @chunk
+                "#
+                .to_string(),
+            ),
+            None,
+        ),
+        "scss" => (
+            // Container-level chunks
+            Some(
+                r#"
+                ;; Rule sets and at-rule blocks as in CSS, plus mixins and functions
+                (rule_set) @chunk
+                (media_statement) @chunk
+                (keyframes_statement) @chunk
+                (mixin_statement) @chunk
+                (function_statement) @chunk
+                "#
+                .to_string(),
+            ),
+            None,
+        ),
+        "sh" | "bash" => (
+            None,
+            // Function-level chunks; the rest of the script is split into paragraphs
+            Some(
+                r#"
+                (function_definition) @chunk
+                "#
+                .to_string(),
+            ),
+        ),
+        "json" => (
+            // Each key of the root object, so the key name leads its chunk
+            Some(
+                r#"
+                (document (object (pair) @chunk))
+                "#
+                .to_string(),
+            ),
+            None,
+        ),
+        "yaml" | "yml" => (
+            // Each `---`-delimited document of the stream
+            Some(
+                r#"
+                (stream (document) @chunk)
+                "#
+                .to_string(),
+            ),
+            None,
+        ),
+        "xml" => (
+            // Each child of the root element, so its tag leads its chunk
+            Some(
+                r#"
+                (document (element (content (element) @chunk)))
+                "#
+                .to_string(),
+            ),
+            None,
+        ),
+        "html" | "htm" => (
             Some(
                 r#"
                 ;; HTML fallback: treat top-level elements as chunks
@@ -0,0 +1,132 @@
+//! The little-endian binary layout shared by the on-disk index and embedding cache.
+//!
+//! Every file starts with an 8-byte magic, a `u32` format version and the name
+//! of the hash behind its document and chunk IDs; integers are written as
+//! `u64`, strings as a length followed by UTF-8 bytes.
+
+use std::path::Path;
+
+use crate::id_hash;
+use crate::{RAGError, Result};
+
+pub(crate) fn put_header(out: &mut Vec<u8>, magic: &[u8; 8], version: u32) {
+    out.extend_from_slice(magic);
+    out.extend_from_slice(&version.to_le_bytes());
+    put_str(out, id_hash::ALGORITHM);
+}
+
+pub(crate) fn put_u64(out: &mut Vec<u8>, value: usize) {
+    out.extend_from_slice(&(value as u64).to_le_bytes());
+}
+
+pub(crate) fn put_str(out: &mut Vec<u8>, value: &str) {
+    put_u64(out, value.len());
+    out.extend_from_slice(value.as_bytes());
+}
+
+pub(crate) fn put_f32s(out: &mut Vec<u8>, values: &[f32]) {
+    for value in values {
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+pub(crate) fn write_file(path: &Path, bytes: &[u8]) -> Result<()> {
+    std::fs::write(path, bytes)
+        .map_err(|e| RAGError::Serialization(format!("{}: {}", path.display(), e)))
+}
+
+pub(crate) fn read_file(path: &Path) -> Result<Vec<u8>> {
+    std::fs::read(path).map_err(|e| RAGError::Deserialization(format!("{}: {}", path.display(), e)))
+}
+
+pub(crate) struct Reader<'b> {
+    bytes: &'b [u8],
+}
+
+impl<'b> Reader<'b> {
+    pub(crate) fn new(bytes: &'b [u8]) -> Self {
+        Self { bytes }
+    }
+
+    /// Checks the magic, version and ID hash written by [`put_header`]; `what`
+    /// names the kind of file in error messages. A file from a build with the
+    /// other hash is refused, since none of its IDs would ever match.
+    pub(crate) fn header(&mut self, magic: &[u8; 8], version: u32, what: &str) -> Result<()> {
+        if self.take(magic.len())? != magic {
+            return Err(RAGError::Deserialization(format!("not an {what} file")));
+        }
+        let found = u32::from_le_bytes(self.array()?);
+        if found != version {
+            return Err(RAGError::Deserialization(format!(
+                "unsupported {what} format version {found} (expected {version})"
+            )));
+        }
+        let hash = self.string()?;
+        if hash != id_hash::ALGORITHM {
+            return Err(RAGError::Deserialization(format!(
+                "{what} IDs were hashed with {hash}, but this build hashes with {}",
+                id_hash::ALGORITHM
+            )));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn take(&mut self, len: usize) -> Result<&'b [u8]> {
+        if self.bytes.len() < len {
+            return Err(RAGError::Deserialization(
+                "unexpected end of file".to_string(),
+            ));
+        }
+        let (head, tail) = self.bytes.split_at(len);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    pub(crate) fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    pub(crate) fn usize(&mut self) -> Result<usize> {
+        usize::try_from(u64::from_le_bytes(self.array()?))
+            .map_err(|e| RAGError::Deserialization(e.to_string()))
+    }
+
+    pub(crate) fn string(&mut self) -> Result<String> {
+        let len = self.usize()?;
+        String::from_utf8(self.take(len)?.to_vec())
+            .map_err(|e| RAGError::Deserialization(e.to_string()))
+    }
+
+    pub(crate) fn flag(&mut self) -> Result<bool> {
+        match self.take(1)?[0] {
+            0 => Ok(false),
+            1 => Ok(true),
+            other => Err(RAGError::Deserialization(format!(
+                "invalid flag byte {other}"
+            ))),
+        }
+    }
+
+    pub(crate) fn f32s(&mut self, len: usize) -> Result<Vec<f32>> {
+        (0..len)
+            .map(|_| Ok(f32::from_le_bytes(self.array()?)))
+            .collect()
+    }
+
+    /// Bytes not yet consumed.
+    pub(crate) fn remaining(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Fails if anything is left over, which means the header lied about the contents.
+    pub(crate) fn finish(self) -> Result<()> {
+        if self.bytes.is_empty() {
+            Ok(())
+        } else {
+            Err(RAGError::Deserialization(format!(
+                "{} trailing bytes",
+                self.bytes.len()
+            )))
+        }
+    }
+}
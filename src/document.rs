@@ -1,104 +1,1002 @@
-use jwalk::WalkDir;
-use rayon::iter::{IntoParallelRefIterator, ParallelBridge, ParallelIterator};
-use sha2::Digest;
-use std::{path::Path, time::Duration};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::Match;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use jwalk::WalkDirGeneric;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use crate::chunking::LANGUAGE_MAP;
+use crate::id_hash::IdHasher;
+use crate::{RAGError, Result};
 
 pub type DocumentID = [u8; 32];
 fn normalized_path_for_id(relative: &Path) -> String {
     relative.to_string_lossy().replace('\\', "/")
 }
 fn compute_document_id(path: &str, content: &str) -> DocumentID {
-    let mut hash = sha2::Sha256::new();
+    let mut hash = IdHasher::new();
     hash.update(path.as_bytes());
     hash.update(content.as_bytes());
-    hash.finalize().into()
+    hash.finalize()
+}
+
+/// How [`DocumentCollector`] derives a [`DocumentID`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IdScheme {
+    /// Hash of the relative path and the contents, so any edit yields a new ID.
+    /// Good for change detection and content-addressed caches.
+    #[default]
+    PathAndContent,
+    /// Hash of the relative path alone, so a document keeps its ID across
+    /// edits. Different contents at the same path deliberately share an ID,
+    /// so anything keyed on it (including chunk IDs and embedding caches) must
+    /// be refreshed when the file changes.
+    PathOnly,
+}
+
+impl IdScheme {
+    pub fn document_id(self, path: &str, content: &str) -> DocumentID {
+        match self {
+            IdScheme::PathAndContent => compute_document_id(path, content),
+            IdScheme::PathOnly => {
+                let mut hash = IdHasher::new();
+                hash.update(path.as_bytes());
+                // Keeps the ID distinct from the path-and-content ID of an empty file
+                hash.update([0]);
+                hash.finalize()
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Document {
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex"))]
     pub id: DocumentID,
     pub path: String,
     pub text: String,
     pub ext: String,
+    /// For a file without an extension, the [`LANGUAGE_MAP`] key guessed from its shebang or
+    /// contents (`"py"` for `#!/usr/bin/env python3`). Chunking treats it like `ext`.
+    pub language: Option<String>,
     pub size: u64,
+    /// Modification time on disk, `None` for in-memory documents.
+    pub modified: Option<SystemTime>,
 }
 
-pub fn grab_all_documents(root: &Path) -> Vec<Document> {
-    WalkDir::new(root)
-        .parallelism(jwalk::Parallelism::RayonDefaultPool {
-            busy_timeout: Duration::new(100, 0),
+impl Document {
+    /// Loads a single file, recording it under its file name so its ID matches
+    /// the one the walker gives it when collecting the file's parent directory.
+    pub fn from_path(path: &Path) -> Result<Document> {
+        let name = path.file_name().map(Path::new).unwrap_or(path);
+        let bytes = std::fs::read(path).map_err(|source| RAGError::FileRead {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let text = String::from_utf8(bytes).map_err(|source| RAGError::InvalidUtf8 {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Ok(Document {
+            modified: std::fs::metadata(path).and_then(|m| m.modified()).ok(),
+            ..Document::from_str(&normalized_path_for_id(name), text)
         })
-        .into_iter()
-        .par_bridge()
-        .filter_map(|entry| {
-            let entry = entry.ok()?;
-            load_document(root, &entry)
-        })
-        .collect()
+    }
+
+    /// Wraps text that never touched disk, such as an editor buffer. `virtual_path`
+    /// stands in for the root-relative path and also determines `ext`.
+    pub fn from_str(virtual_path: &str, text: String) -> Document {
+        let path = normalized_path_for_id(Path::new(virtual_path));
+        let ext = Path::new(&path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("")
+            .to_string();
+        let language = if ext.is_empty() {
+            detect_language(&text)
+        } else {
+            None
+        };
+        Document {
+            id: compute_document_id(&path, &text),
+            size: text.len() as u64,
+            path,
+            text,
+            ext,
+            language,
+            modified: None,
+        }
+    }
+
+    /// The key chunking picks a grammar by: `ext`, or the detected `language` when the
+    /// file has no extension.
+    pub fn syntax(&self) -> &str {
+        match &self.language {
+            Some(language) if self.ext.is_empty() => language,
+            _ => &self.ext,
+        }
+    }
 }
 
-/*pub fn grab_all_documents(root: &Path) -> Vec<Document> {
-    let paths: Vec<_> = WalkDir::new(root)
-        .into_iter()
-        .filter_map(|e| e.ok())
-        .filter(|e| e.file_type().is_file())
-        .filter_map(|e| {
-            let binding = e.path();
-            let rel = binding.strip_prefix(root).ok()?;
-            Some(rel.to_string_lossy().into_owned())
+// Interpreter named by a `#!` line, or failing that a few telltale lines near the top
+fn detect_language(text: &str) -> Option<String> {
+    let detected = match text.strip_prefix("#!") {
+        Some(shebang) => shebang_language(shebang.lines().next().unwrap_or("")),
+        None => content_language(text),
+    }?;
+    LANGUAGE_MAP
+        .contains_key(detected)
+        .then(|| detected.to_string())
+}
+
+fn shebang_language(line: &str) -> Option<&'static str> {
+    let mut words = line.split_whitespace();
+    let mut program = words.next()?.rsplit('/').next()?;
+    // `#!/usr/bin/env -S python3 -u`
+    if program == "env" {
+        program = words.find(|w| !w.starts_with('-'))?;
+    }
+    // `python3.12` is still Python
+    match program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.') {
+        "python" | "pypy" => Some("py"),
+        "sh" | "bash" | "dash" | "ksh" | "zsh" | "ash" => Some("sh"),
+        "node" | "nodejs" => Some("js"),
+        "php" => Some("php"),
+        _ => None,
+    }
+}
+
+fn content_language(text: &str) -> Option<&'static str> {
+    let mut lines = text
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .take(20);
+    lines.find_map(|line| {
+        let lower = line.to_ascii_lowercase();
+        if lower.starts_with("<!doctype html") || lower.starts_with("<html") {
+            Some("html")
+        } else if line.starts_with("#include ") {
+            Some("c")
+        } else if line.starts_with("package ") && !line.ends_with(';') {
+            Some("go")
+        } else if line.starts_with("def ") && line.ends_with(':')
+            || line.starts_with("import ") && !line.ends_with(';')
+            || line.starts_with("from ") && line.contains(" import ")
+        {
+            Some("py")
+        } else {
+            None
+        }
+    })
+}
+
+// Fails only if `root` itself can't be read; unreadable files below it are logged and skipped
+pub fn grab_all_documents(root: &Path) -> Result<Vec<Document>> {
+    DocumentCollector::new(root).collect()
+}
+
+/// Re-walks `root` but only reads files modified since `previous` was recorded.
+/// See [`DocumentCollector::collect_changed`].
+pub fn grab_changed_documents(
+    root: &Path,
+    previous: &HashMap<String, SystemTime>,
+) -> Result<(Vec<Document>, HashMap<String, SystemTime>)> {
+    DocumentCollector::new(root).collect_changed(previous)
+}
+
+/// Like [`grab_all_documents`], but also reports the files that were skipped.
+/// See [`DocumentCollector::collect_with_warnings`].
+pub fn grab_all_documents_with_warnings(
+    root: &Path,
+) -> Result<(Vec<Document>, Vec<IngestWarning>)> {
+    DocumentCollector::new(root).collect_with_warnings()
+}
+
+/// Streams the documents under `root` one at a time instead of loading them all
+/// up front. See [`DocumentCollector::documents`].
+pub fn documents_iter(root: &Path) -> impl Iterator<Item = Result<Document>> + use<> {
+    DocumentCollector::new(root).documents()
+}
+
+/// How the bytes passed to [`grab_all_documents_from_archive`] are packed.
+#[cfg(feature = "archive")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Tar,
+    /// A gzip-compressed tarball (`.tar.gz`, `.tgz`).
+    TarGz,
+}
+
+/// Loads every text file in an archive without extracting it. Documents are
+/// recorded under their in-archive path, with any leading `./` dropped, and
+/// take their size and mtime from the entry header. Binary and non-UTF-8
+/// entries are skipped as the walker skips them; a corrupt archive is an error.
+#[cfg(feature = "archive")]
+pub fn grab_all_documents_from_archive(
+    reader: impl std::io::Read,
+    format: ArchiveFormat,
+) -> Result<Vec<Document>> {
+    match format {
+        ArchiveFormat::Tar => read_tar(tar::Archive::new(reader)),
+        ArchiveFormat::TarGz => read_tar(tar::Archive::new(flate2::read::GzDecoder::new(reader))),
+    }
+}
+
+#[cfg(feature = "archive")]
+fn read_tar(mut archive: tar::Archive<impl std::io::Read>) -> Result<Vec<Document>> {
+    use std::io::Read;
+
+    let mut documents = Vec::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let path: PathBuf = entry
+            .path()?
+            .components()
+            .filter(|c| !matches!(c, std::path::Component::CurDir))
+            .collect();
+        let relative = normalized_path_for_id(&path);
+
+        // the header's size is untrusted, so a corrupt one mustn't drive the allocation;
+        // read_to_end grows the buffer if the entry really is larger
+        let mut bytes = Vec::with_capacity(entry.size().min(1 << 20) as usize);
+        entry
+            .read_to_end(&mut bytes)
+            .map_err(|source| RAGError::FileRead {
+                path: path.clone(),
+                source,
+            })?;
+        if looks_binary(&bytes) {
+            log::debug!("Skipping {relative}: looks like a binary file");
+            continue;
+        }
+        let Ok(text) = String::from_utf8(bytes) else {
+            log::debug!("Skipping {relative}: not UTF-8");
+            continue;
+        };
+
+        let header = entry.header();
+        documents.push(Document {
+            size: header.size()?,
+            modified: header
+                .mtime()
+                .ok()
+                .map(|secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs)),
+            ..Document::from_str(&relative, text)
+        });
+    }
+    Ok(documents)
+}
+
+/// Walks a directory tree and loads every readable UTF-8 file as a [`Document`].
+///
+/// `.gitignore` and `.ignore` files are honoured by default. Ignored,
+/// excluded and [skipped](DocumentCollector::skip_dirs) directories are
+/// pruned during the walk, so nothing below them is ever read.
+#[derive(Debug, Clone)]
+pub struct DocumentCollector {
+    root: PathBuf,
+    respect_ignore_files: bool,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    generated: Vec<String>,
+    skip_dirs: HashSet<String>,
+    max_file_bytes: Option<u64>,
+    binary_detection: bool,
+    threads: usize,
+    sort_by_path: bool,
+    busy_timeout: Duration,
+    follow_links: bool,
+    max_depth: Option<usize>,
+    id_scheme: IdScheme,
+}
+
+/// Ignore-file matchers inherited by each directory, outermost first.
+type IgnoreStack = Arc<Vec<Arc<Gitignore>>>;
+type WalkState = (DirState, ());
+
+/// Walk state handed down from each directory to its subdirectories.
+#[derive(Debug, Clone, Default)]
+struct DirState {
+    ignores: IgnoreStack,
+    // Real paths of the directories being walked, only tracked when following links
+    ancestors: Arc<Vec<PathBuf>>,
+}
+
+const IGNORE_FILES: [&str; 2] = [".gitignore", ".ignore"];
+
+/// A file the collector found but did not turn into a [`Document`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IngestWarning {
+    pub path: PathBuf,
+    pub reason: SkipReason,
+}
+
+impl IngestWarning {
+    fn new(path: PathBuf, reason: SkipReason) -> Self {
+        Self { path, reason }
+    }
+}
+
+/// Why an [`IngestWarning`]'s file was skipped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SkipReason {
+    /// Not UTF-8, and with the `encoding` feature not decodable either.
+    NonUtf8,
+    /// Larger than [`DocumentCollector::max_file_bytes`].
+    TooLarge,
+    /// Failed [`looks_binary`].
+    Binary,
+    /// The file, or the directory holding it, couldn't be read.
+    ReadFailed(String),
+    MetadataFailed(String),
+}
+
+/// Lockfiles and generated sources skipped by default; see
+/// [`DocumentCollector::generated_globs`].
+pub const DEFAULT_GENERATED_GLOBS: &[&str] = &[
+    "Cargo.lock",
+    "package-lock.json",
+    "npm-shrinkwrap.json",
+    "yarn.lock",
+    "pnpm-lock.yaml",
+    "poetry.lock",
+    "Pipfile.lock",
+    "composer.lock",
+    "Gemfile.lock",
+    "go.sum",
+    "*.min.js",
+    "*.min.css",
+    "*.js.map",
+    "*.pb.go",
+    "*_pb2.py",
+    "*.pb.h",
+    "*.pb.cc",
+];
+
+/// Directory names never descended into by default; see
+/// [`DocumentCollector::skip_dirs`].
+pub const DEFAULT_SKIP_DIRS: &[&str] = &[
+    ".git",
+    ".hg",
+    ".svn",
+    "node_modules",
+    "vendor",
+    "dist",
+    "__pycache__",
+    ".venv",
+    ".tox",
+    ".mypy_cache",
+    ".pytest_cache",
+];
+
+impl DocumentCollector {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            respect_ignore_files: true,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            generated: DEFAULT_GENERATED_GLOBS
+                .iter()
+                .map(|g| g.to_string())
+                .collect(),
+            skip_dirs: DEFAULT_SKIP_DIRS.iter().map(|d| d.to_string()).collect(),
+            max_file_bytes: None,
+            binary_detection: true,
+            threads: 0,
+            sort_by_path: true,
+            busy_timeout: Duration::from_secs(100),
+            follow_links: false,
+            max_depth: None,
+            id_scheme: IdScheme::default(),
+        }
+    }
+
+    /// Honour `.gitignore` and `.ignore` files found while walking (default `true`).
+    pub fn respect_ignore_files(mut self, respect: bool) -> Self {
+        self.respect_ignore_files = respect;
+        self
+    }
+
+    /// Only load files whose root-relative path matches one of the include globs.
+    pub fn include(mut self, glob: impl Into<String>) -> Self {
+        self.include.push(glob.into());
+        self
+    }
+
+    /// Skip files and whole directories whose root-relative path matches the glob.
+    pub fn exclude(mut self, glob: impl Into<String>) -> Self {
+        self.exclude.push(glob.into());
+        self
+    }
+
+    /// Replace the globs for generated files that are skipped even when not
+    /// gitignored (default [`DEFAULT_GENERATED_GLOBS`]). Each is matched against
+    /// both the file name and the root-relative path; pass an empty list to
+    /// read every file.
+    pub fn generated_globs<S: Into<String>>(mut self, globs: impl IntoIterator<Item = S>) -> Self {
+        self.generated = globs.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Replace the names of directories that are pruned from the walk even
+    /// when not gitignored (default [`DEFAULT_SKIP_DIRS`]). A directory is
+    /// skipped, with everything below it, when its own name is in the set; the
+    /// root is always walked. Pass an empty list to descend everywhere.
+    pub fn skip_dirs<S: Into<String>>(mut self, names: impl IntoIterator<Item = S>) -> Self {
+        self.skip_dirs = names.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Skip files larger than `bytes` without reading them; lockfiles and
+    /// minified bundles rarely chunk into anything useful.
+    pub fn max_file_bytes(mut self, bytes: u64) -> Self {
+        self.max_file_bytes = Some(bytes);
+        self
+    }
+
+    /// Skip files that look binary even when they happen to be valid UTF-8
+    /// (default `true`). See [`looks_binary`] for the heuristic.
+    pub fn binary_detection(mut self, enabled: bool) -> Self {
+        self.binary_detection = enabled;
+        self
+    }
+
+    /// Worker threads for walking and reading. `0` (the default) shares rayon's
+    /// global pool, `1` does everything on the calling thread, so even
+    /// [`documents`](Self::documents) yields a deterministic order, and any
+    /// other `n` uses a dedicated pool of `n` threads.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// Sort what [`collect`](Self::collect) and its variants return by
+    /// [`Document::path`] (default `true`), so repeated runs over the same tree
+    /// give identical vectors however the walk was scheduled. Turn it off to
+    /// keep walk order.
+    pub fn sort_by_path(mut self, sort: bool) -> Self {
+        self.sort_by_path = sort;
+        self
+    }
+
+    /// How long the walk waits for a busy shared pool before giving up
+    /// (default 100 seconds). Raise it for slow network filesystems.
+    pub fn busy_timeout(mut self, timeout: Duration) -> Self {
+        self.busy_timeout = timeout;
+        self
+    }
+
+    /// Descend into symlinked directories and read symlinked files (default
+    /// `false`). Links leading back into a directory already being walked are
+    /// skipped, and a file reachable through several links is read only once.
+    pub fn follow_links(mut self, follow: bool) -> Self {
+        self.follow_links = follow;
+        self
+    }
+
+    /// Don't descend more than `depth` levels below the root; `Some(1)` reads
+    /// only the files directly inside it.
+    pub fn max_depth(mut self, depth: Option<usize>) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    /// How document IDs are derived (default [`IdScheme::PathAndContent`]).
+    pub fn id_scheme(mut self, scheme: IdScheme) -> Self {
+        self.id_scheme = scheme;
+        self
+    }
+
+    pub fn collect(&self) -> Result<Vec<Document>> {
+        Ok(self.collect_with_warnings()?.0)
+    }
+
+    /// Like [`collect`](Self::collect), but also reports every file that was
+    /// found and then skipped, and why. Files left out by ignore files, globs
+    /// or the depth limit are not reported.
+    pub fn collect_with_warnings(&self) -> Result<(Vec<Document>, Vec<IngestWarning>)> {
+        let mut documents = Vec::new();
+        let mut warnings = Vec::new();
+        for read in self.map_files(|file| file.and_then(|file| self.read_document(&file)))? {
+            match read {
+                Ok(document) => documents.push(document),
+                Err(warning) => warnings.push(warning),
+            }
+        }
+        if self.sort_by_path {
+            documents.sort_unstable_by(|a, b| a.path.cmp(&b.path));
+            warnings.sort_unstable_by(|a, b| a.path.cmp(&b.path));
+        }
+        Ok((documents, warnings))
+    }
+
+    /// Like [`collect`](Self::collect), but yields each document as soon as it
+    /// is read, so a caller can chunk and embed it and drop the text before the
+    /// next one arrives. Directories are still walked in parallel; files are
+    /// read on the thread driving the iterator, in walk order. The only error,
+    /// an unreadable root or an invalid glob, is yielded as the sole item.
+    pub fn documents(&self) -> impl Iterator<Item = Result<Document>> + use<> {
+        let parallelism = match self.threads {
+            0 => jwalk::Parallelism::RayonDefaultPool {
+                busy_timeout: self.busy_timeout,
+            },
+            1 => jwalk::Parallelism::Serial,
+            threads => jwalk::Parallelism::RayonNewPool(threads),
+        };
+        let (files, error) = match self.walk(parallelism) {
+            Ok(files) => (Some(files), None),
+            Err(e) => (None, Some(Err(e))),
+        };
+        let collector = self.clone();
+        error.into_iter().chain(
+            files
+                .into_iter()
+                .flatten()
+                .filter_map(move |file| file.and_then(|file| collector.read_document(&file)).ok())
+                .map(Ok),
+        )
+    }
+
+    /// Like [`collect`](Self::collect), but only reads files whose modification
+    /// time is newer than the one recorded in `previous`. Returns the changed
+    /// documents together with the mtimes of every file seen, ready to pass back
+    /// in on the next run.
+    pub fn collect_changed(
+        &self,
+        previous: &HashMap<String, SystemTime>,
+    ) -> Result<(Vec<Document>, HashMap<String, SystemTime>)> {
+        let seen = self.map_files(|file| {
+            let file = file.ok()?;
+            let modified = file.meta.modified().ok();
+            let unchanged = modified
+                .zip(previous.get(&file.relative))
+                .is_some_and(|(now, before)| now <= *before);
+            let document = if unchanged {
+                None
+            } else {
+                self.read_document(&file).ok()
+            };
+            Some((file.relative, modified, document))
+        })?;
+
+        let mut documents = Vec::new();
+        let mut mtimes = HashMap::with_capacity(seen.len());
+        for (relative, modified, document) in seen.into_iter().flatten() {
+            if let Some(modified) = modified {
+                mtimes.insert(relative, modified);
+            }
+            documents.extend(document);
+        }
+        if self.sort_by_path {
+            documents.sort_unstable_by(|a, b| a.path.cmp(&b.path));
+        }
+        Ok((documents, mtimes))
+    }
+
+    // Walks the tree, then applies `f` to every file on the configured workers, keeping walk order
+    fn map_files<T: Send>(&self, f: impl Fn(Walked) -> T + Sync + Send) -> Result<Vec<T>> {
+        match self.threads {
+            0 => {
+                let parallelism = jwalk::Parallelism::RayonDefaultPool {
+                    busy_timeout: self.busy_timeout,
+                };
+                let files: Vec<Walked> = self.walk(parallelism)?.collect();
+                Ok(files.into_par_iter().map(f).collect())
+            }
+            1 => Ok(self.walk(jwalk::Parallelism::Serial)?.map(f).collect()),
+            threads => {
+                let pool = Arc::new(
+                    rayon::ThreadPoolBuilder::new()
+                        .num_threads(threads)
+                        .build()
+                        .map_err(std::io::Error::other)?,
+                );
+                let files: Vec<Walked> = self
+                    .walk(jwalk::Parallelism::RayonExistingPool {
+                        pool: Arc::clone(&pool),
+                        busy_timeout: Some(self.busy_timeout),
+                    })?
+                    .collect();
+                Ok(pool.install(|| files.into_par_iter().map(f).collect()))
+            }
+        }
+    }
+
+    // Fails only if `root` itself can't be read; unreadable files below it are logged and skipped
+    fn walk(
+        &self,
+        parallelism: jwalk::Parallelism,
+    ) -> Result<impl Iterator<Item = Walked> + use<>> {
+        let root = self.root.as_path();
+        let root_error = |source| RAGError::FileRead {
+            path: root.to_path_buf(),
+            source,
+        };
+        if std::fs::metadata(root).map_err(root_error)?.is_dir() {
+            std::fs::read_dir(root).map_err(root_error)?;
+        }
+
+        let include = build_glob_set(&self.include)?;
+        let exclude = build_glob_set(&self.exclude)?;
+        let generated = build_glob_set(&self.generated)?;
+        let skip_dirs = self.skip_dirs.clone();
+        let respect_ignore_files = self.respect_ignore_files;
+        let follow_links = self.follow_links;
+        let walk_root = root.to_path_buf();
+        let file_root = root.to_path_buf();
+
+        let mut walker = WalkDirGeneric::<WalkState>::new(root)
+            .sort(matches!(parallelism, jwalk::Parallelism::Serial))
+            .parallelism(parallelism)
+            .follow_links(follow_links);
+        if let Some(depth) = self.max_depth {
+            walker = walker.max_depth(depth);
+        }
+
+        let mut seen_real_paths = HashSet::new();
+        Ok(walker
+            .process_read_dir(move |depth, dir, state, children| {
+                // The root entry itself is never filtered
+                if depth.is_none() {
+                    return;
+                }
+                if respect_ignore_files {
+                    push_ignore_files(dir, &mut state.ignores);
+                }
+                if follow_links && let Ok(real) = dir.canonicalize() {
+                    Arc::make_mut(&mut state.ancestors).push(real);
+                }
+                children.retain(|child| {
+                    let child = match child {
+                        Ok(child) => child,
+                        Err(e) if e.loop_ancestor().is_some() => {
+                            log::debug!("Skipping symlink cycle: {}", e);
+                            return false;
+                        }
+                        Err(_) => return true,
+                    };
+                    let path = child.path();
+                    let is_dir = child.file_type.is_dir();
+                    if is_dir
+                        && child.path_is_symlink()
+                        && path
+                            .canonicalize()
+                            .is_ok_and(|real| state.ancestors.contains(&real))
+                    {
+                        log::debug!("Skipping symlink cycle at {}", path.display());
+                        return false;
+                    }
+                    if is_dir
+                        && child
+                            .file_name
+                            .to_str()
+                            .is_some_and(|name| skip_dirs.contains(name))
+                    {
+                        log::debug!("Skipping directory {}", path.display());
+                        return false;
+                    }
+                    if is_ignored(&state.ignores, &path, is_dir) {
+                        return false;
+                    }
+                    let Ok(relative) = path.strip_prefix(&walk_root) else {
+                        return true;
+                    };
+                    if exclude.as_ref().is_some_and(|set| set.is_match(relative)) {
+                        return false;
+                    }
+                    if !is_dir
+                        && let Some(set) = &generated
+                        && (set.is_match(relative) || set.is_match(&child.file_name))
+                    {
+                        log::debug!("Skipping generated file {}", relative.display());
+                        return false;
+                    }
+                    is_dir || include.as_ref().is_none_or(|set| set.is_match(relative))
+                });
+            })
+            .into_iter()
+            .filter_map(move |entry| {
+                let entry = match entry {
+                    Ok(e) => e,
+                    Err(e) => {
+                        log::warn!("Failed to walk {}: {}", file_root.display(), e);
+                        let path = e.path().unwrap_or(&file_root).to_path_buf();
+                        return Some(Err(IngestWarning::new(
+                            path,
+                            SkipReason::ReadFailed(e.to_string()),
+                        )));
+                    }
+                };
+                walked_file(&file_root, &entry)
+            })
+            // The same file reached through different links is only read once
+            .filter(move |file| match file {
+                Ok(file) if follow_links => file
+                    .path
+                    .canonicalize()
+                    .map_or(true, |real| seen_real_paths.insert(real)),
+                _ => true,
+            }))
+    }
+
+    fn read_document(&self, file: &WalkedFile) -> std::result::Result<Document, IngestWarning> {
+        let mut doc = self
+            .load_document(file)
+            .map_err(|reason| IngestWarning::new(file.path.clone(), reason))?;
+        if self.id_scheme != IdScheme::PathAndContent {
+            doc.id = self.id_scheme.document_id(&doc.path, &doc.text);
+        }
+        Ok(doc)
+    }
+
+    fn load_document(&self, file: &WalkedFile) -> std::result::Result<Document, SkipReason> {
+        let path = &file.path;
+        let size = file.meta.len();
+        if let Some(max) = self.max_file_bytes
+            && size > max
+        {
+            log::debug!(
+                "Skipping {}: {} bytes exceeds limit of {}",
+                path.display(),
+                size,
+                max
+            );
+            return Err(SkipReason::TooLarge);
+        }
+
+        let bytes = match std::fs::read(path) {
+            Ok(b) => b,
+            Err(e) => {
+                log::warn!("Failed to read {}: {}", path.display(), e);
+                return Err(SkipReason::ReadFailed(e.to_string()));
+            }
+        };
+        #[cfg(feature = "pdf")]
+        if Path::new(&file.relative)
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("pdf"))
+        {
+            let text = extract_pdf_text(path, &bytes).ok_or_else(|| {
+                SkipReason::ReadFailed("no text could be extracted from the PDF".to_string())
+            })?;
+            return Ok(Document {
+                ext: "pdf".to_string(),
+                modified: file.meta.modified().ok(),
+                ..Document::from_str(&file.relative, text)
+            });
+        }
+        // A UTF-16 byte order mark explains the NUL bytes, so decode before the binary check
+        #[cfg(feature = "encoding")]
+        let bytes = match decode_utf16(path, &bytes) {
+            Some(text) => text.into_bytes(),
+            None => bytes,
+        };
+        if self.binary_detection && looks_binary(&bytes) {
+            log::debug!("Skipping {}: looks like a binary file", path.display());
+            return Err(SkipReason::Binary);
+        }
+        let text = match String::from_utf8(bytes) {
+            Ok(text) => text,
+            #[cfg(feature = "encoding")]
+            Err(e) => decode_legacy(path, e.as_bytes()).ok_or(SkipReason::NonUtf8)?,
+            #[cfg(not(feature = "encoding"))]
+            Err(_) => {
+                log::debug!("Skipping {}: not UTF-8", path.display());
+                return Err(SkipReason::NonUtf8);
+            }
+        };
+
+        Ok(Document {
+            modified: file.meta.modified().ok(),
+            ..Document::from_str(&file.relative, text)
         })
-        .collect();
+    }
+}
 
-    paths
-        .par_iter()
-        .filter_map(|relative| load_document(root, Path::new(relative)))
-        .collect()
-}*/
+// pdf-extract panics on some malformed files; treat that like any other extraction failure
+#[cfg(feature = "pdf")]
+fn extract_pdf_text(path: &Path, bytes: &[u8]) -> Option<String> {
+    match std::panic::catch_unwind(|| pdf_extract::extract_text_from_mem(bytes)) {
+        Ok(Ok(text)) => Some(text),
+        Ok(Err(e)) => {
+            log::warn!("Failed to extract text from {}: {}", path.display(), e);
+            None
+        }
+        Err(_) => {
+            log::warn!(
+                "Failed to extract text from {}: extractor panicked",
+                path.display()
+            );
+            None
+        }
+    }
+}
+
+#[cfg(feature = "encoding")]
+fn decode_utf16(path: &Path, bytes: &[u8]) -> Option<String> {
+    let (encoding, _) = encoding_rs::Encoding::for_bom(bytes)?;
+    if encoding == encoding_rs::UTF_8 {
+        return None;
+    }
+    let (text, _, had_errors) = encoding.decode(bytes);
+    if had_errors {
+        log::warn!(
+            "Skipping {}: invalid {} despite its byte order mark",
+            path.display(),
+            encoding.name()
+        );
+        return None;
+    }
+    Some(text.into_owned())
+}
 
-fn load_document(root: &Path, entry: &jwalk::DirEntry<((), ())>) -> Option<Document> {
+// Guesses a legacy single- or multi-byte encoding such as Latin-1 or Shift_JIS
+#[cfg(feature = "encoding")]
+fn decode_legacy(path: &Path, bytes: &[u8]) -> Option<String> {
+    let mut detector = chardetng::EncodingDetector::new();
+    detector.feed(bytes, true);
+    let (encoding, confident) = detector.guess_assess(None, false);
+    if !confident {
+        log::warn!(
+            "Skipping {}: not UTF-8 and its encoding can't be determined",
+            path.display()
+        );
+        return None;
+    }
+    let (text, _, had_errors) = encoding.decode(bytes);
+    if had_errors {
+        log::warn!("Skipping {}: not valid {}", path.display(), encoding.name());
+        return None;
+    }
+    log::debug!("Decoded {} as {}", path.display(), encoding.name());
+    Some(text.into_owned())
+}
+
+// `None` for anything but a file
+fn walked_file(root: &Path, entry: &jwalk::DirEntry<WalkState>) -> Option<Walked> {
     if !entry.file_type.is_file() {
         return None;
     }
 
     let path = entry.path();
     let relative = path.strip_prefix(root).ok()?;
-    let relative_str = normalized_path_for_id(relative);
-
-    let text = match std::fs::read_to_string(&path) {
-        Ok(t) => t,
-        Err(e) if e.kind() == std::io::ErrorKind::InvalidData => return None, // not UTF-8
-        Err(e) => {
-            log::warn!("Failed to read {}: {}", path.display(), e);
-            return None;
-        }
+    // A file given directly as the root is recorded by its name, as `Document::from_path` does
+    let relative = if relative.as_os_str().is_empty() {
+        Path::new(path.file_name()?)
+    } else {
+        relative
     };
-
-    let ext = path
-        .extension()
-        .and_then(|e| e.to_str())
-        .unwrap_or("")
-        .to_string();
-
-    let id = compute_document_id(&relative_str, &text);
+    let relative = normalized_path_for_id(relative);
 
     let meta = match entry.metadata() {
         Ok(m) => m,
         Err(e) => {
             log::warn!("Failed to get metadata {}: {}", path.display(), e);
-            return None;
+            let reason = SkipReason::MetadataFailed(e.to_string());
+            return Some(Err(IngestWarning::new(path, reason)));
         }
     };
 
-    let size = meta.len();
+    Some(Ok(WalkedFile {
+        path,
+        relative,
+        meta,
+    }))
+}
 
-    Some(Document {
-        id,
-        path: relative_str,
-        text,
-        ext,
-        size,
-    })
+// A file found by the walk, or why one couldn't be
+type Walked = std::result::Result<WalkedFile, IngestWarning>;
+
+struct WalkedFile {
+    path: PathBuf,
+    relative: String,
+    meta: std::fs::Metadata,
+}
+
+const BINARY_SAMPLE_BYTES: usize = 8 * 1024;
+const MAX_NON_PRINTABLE_RATIO: f64 = 0.3;
+
+/// Samples the first 8 KiB: any null byte, or more than 30% control
+/// characters other than common whitespace, marks the content as binary.
+pub fn looks_binary(bytes: &[u8]) -> bool {
+    let sample = &bytes[..bytes.len().min(BINARY_SAMPLE_BYTES)];
+    if sample.is_empty() {
+        return false;
+    }
+    if sample.contains(&0) {
+        return true;
+    }
+    let non_printable = sample
+        .iter()
+        .filter(|&&b| (b < 0x20 && !matches!(b, b'\t' | b'\n' | b'\r' | 0x0c)) || b == 0x7f)
+        .count();
+    non_printable as f64 / sample.len() as f64 > MAX_NON_PRINTABLE_RATIO
+}
+
+fn build_glob_set(patterns: &[String]) -> Result<Option<GlobSet>> {
+    if patterns.is_empty() {
+        return Ok(None);
+    }
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = Glob::new(pattern).map_err(|source| RAGError::InvalidGlob {
+            pattern: pattern.clone(),
+            source,
+        })?;
+        builder.add(glob);
+    }
+    builder
+        .build()
+        .map(Some)
+        .map_err(|source| RAGError::InvalidGlob {
+            pattern: patterns.join(", "),
+            source,
+        })
 }
+
+fn push_ignore_files(dir: &Path, ignores: &mut IgnoreStack) {
+    let mut builder = GitignoreBuilder::new(dir);
+    let mut found = false;
+    for name in IGNORE_FILES {
+        let path = dir.join(name);
+        if !path.is_file() {
+            continue;
+        }
+        found = true;
+        if let Some(e) = builder.add(&path) {
+            log::warn!("Failed to parse {}: {}", path.display(), e);
+        }
+    }
+    if !found {
+        return;
+    }
+    match builder.build() {
+        Ok(gitignore) => Arc::make_mut(ignores).push(Arc::new(gitignore)),
+        Err(e) => log::warn!("Failed to build ignore rules for {}: {}", dir.display(), e),
+    }
+}
+
+// Innermost ignore file wins, so a nested `!pattern` can re-include a path
+fn is_ignored(ignores: &[Arc<Gitignore>], path: &Path, is_dir: bool) -> bool {
+    for gitignore in ignores.iter().rev() {
+        match gitignore.matched(path, is_dir) {
+            Match::Ignore(_) => return true,
+            Match::Whitelist(_) => return false,
+            Match::None => {}
+        }
+    }
+    false
+}
+
+/*pub fn grab_all_documents(root: &Path) -> Vec<Document> {
+    let paths: Vec<_> = WalkDir::new(root)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter_map(|e| {
+            let binding = e.path();
+            let rel = binding.strip_prefix(root).ok()?;
+            Some(rel.to_string_lossy().into_owned())
+        })
+        .collect();
+
+    paths
+        .par_iter()
+        .filter_map(|relative| load_document(root, Path::new(relative)))
+        .collect()
+}*/
+
 /*fn load_document(root: &Path, relative: &Path) -> Option<Document> {
     let path = root.join(relative);
 
@@ -1,33 +1,596 @@
-use fastembed::{InitOptions, TextEmbedding};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
 
-use crate::chunking::Chunk;
+use fastembed::{EmbeddingModel, InitOptions, TextEmbedding};
+use rayon::prelude::*;
 
-pub struct Embedder {
-    model: TextEmbedding,
+use crate::chunking::{Chunk, ChunkID};
+use crate::codec::{Reader, put_f32s, put_header, put_u64, read_file, write_file};
+use crate::tokenizer::{TokenCounter, WhitespaceTokenCounter};
+use crate::util;
+use crate::{RAGError, Result};
+
+const CACHE_MAGIC: &[u8; 8] = b"WUBRAGEC";
+const CACHE_FORMAT_VERSION: u32 = 2;
+
+/// An embedding backend: anything that can turn texts into fixed-size vectors,
+/// e.g. the bundled local model, a remote service, or a mock in tests.
+pub trait Embed: Send + Sync {
+    /// One vector of length [`dim`](Embed::dim) per input text, in order.
+    fn embed(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>>;
+
+    fn dim(&self) -> usize;
+
+    /// Longest input, in the model's tokens, that is embedded without being
+    /// truncated; `None` if the backend has no limit (the default).
+    fn max_tokens(&self) -> Option<usize> {
+        None
+    }
+
+    /// Names the model behind the vectors, so an index can tell whether a
+    /// query came from the same one; empty if the backend doesn't say (the
+    /// default).
+    fn model_id(&self) -> &str {
+        ""
+    }
+}
+
+/// Which model produced an index's embeddings, recorded by
+/// [`RagPipeline`](crate::pipeline::RagPipeline) and saved with the index.
+/// Compare it to the [`Embedder::meta`] of the model embedding queries with
+/// [`Index::check_meta`](crate::indexing::Index::check_meta).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EmbeddingMeta {
+    /// [`Embed::model_id`] of the backend.
+    pub model_id: String,
+    pub dim: usize,
+    /// Whether the embeddings were scaled to unit length.
+    pub normalized: bool,
+}
+
+impl fmt::Display for EmbeddingMeta {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let model = if self.model_id.is_empty() {
+            "an unnamed model"
+        } else {
+            &self.model_id
+        };
+        let scale = if self.normalized {
+            "normalized"
+        } else {
+            "unnormalized"
+        };
+        write!(f, "{model} ({}-d, {scale})", self.dim)
+    }
+}
+
+/// The bundled local model, run in-process through fastembed.
+pub struct FastEmbed {
+    model: Mutex<TextEmbedding>, // fastembed needs `&mut` to embed
+    model_id: String,
+    dim: usize,
+    max_tokens: usize,
+}
+
+impl FastEmbed {
+    pub fn new(model: EmbeddingModel) -> Result<Self> {
+        let info = TextEmbedding::get_model_info(&model)
+            .map_err(|e| RAGError::ModelInit(e.to_string()))?;
+        let (model_id, dim) = (info.model_code.clone(), info.dim);
+        let options = InitOptions::new(model).with_show_download_progress(true);
+        let max_tokens = options.max_length;
+        let model =
+            TextEmbedding::try_new(options).map_err(|e| RAGError::ModelInit(e.to_string()))?;
+
+        Ok(Self {
+            model: Mutex::new(model),
+            model_id,
+            dim,
+            max_tokens,
+        })
+    }
+}
+
+impl Embed for FastEmbed {
+    fn embed(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+        self.model
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .embed(texts.to_vec(), None)
+            .map_err(|e| RAGError::Embedding(e.to_string()))
+    }
+
+    fn dim(&self) -> usize {
+        self.dim
+    }
+
+    fn max_tokens(&self) -> Option<usize> {
+        Some(self.max_tokens)
+    }
+
+    fn model_id(&self) -> &str {
+        &self.model_id
+    }
+}
+
+/// An [`Embedder`] can stand in wherever a backend is expected: texts are
+/// prefixed, batched and normalized as in [`Embedder::embed_chunks`], but the
+/// cache is bypassed since plain texts have no chunk ID.
+impl<E: Embed> Embed for Embedder<E> {
+    fn embed(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+        self.embed_texts(texts)
+    }
+
+    fn dim(&self) -> usize {
+        self.backend.dim()
+    }
+
+    fn max_tokens(&self) -> Option<usize> {
+        self.backend.max_tokens()
+    }
+
+    fn model_id(&self) -> &str {
+        self.backend.model_id()
+    }
+}
+
+/// Embeddings keyed by [`ChunkID`]. Chunk IDs hash the chunk's document, offset
+/// and content, so an unchanged chunk can reuse its embedding across runs.
+#[derive(Debug, Clone, Default)]
+pub struct EmbeddingCache {
+    entries: HashMap<ChunkID, Vec<f32>>,
+}
+
+impl EmbeddingCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, id: &ChunkID) -> Option<&Vec<f32>> {
+        self.entries.get(id)
+    }
+
+    pub fn insert(&mut self, id: ChunkID, embedding: Vec<f32>) {
+        self.entries.insert(id, embedding);
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Writes the cache in the same binary layout as [`Index::save`](crate::indexing::Index::save).
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let dim = self.entries.values().next().map_or(0, Vec::len);
+        let mut out = Vec::new();
+        put_header(&mut out, CACHE_MAGIC, CACHE_FORMAT_VERSION);
+        put_u64(&mut out, dim);
+        put_u64(&mut out, self.entries.len());
+        for (id, embedding) in &self.entries {
+            if embedding.len() != dim {
+                return Err(RAGError::DimensionMismatch {
+                    expected: dim,
+                    got: embedding.len(),
+                });
+            }
+            out.extend_from_slice(id);
+            put_f32s(&mut out, embedding);
+        }
+        write_file(path, &out)
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let bytes = read_file(path)?;
+        let mut reader = Reader::new(&bytes);
+        reader
+            .header(CACHE_MAGIC, CACHE_FORMAT_VERSION, "embedding cache")
+            .map_err(|e| RAGError::Deserialization(format!("{}: {}", path.display(), e)))?;
+        let dim = reader.usize()?;
+        let count = reader.usize()?;
+        let mut entries = HashMap::with_capacity(count);
+        for _ in 0..count {
+            let id = reader.array()?;
+            entries.insert(id, reader.f32s(dim)?);
+        }
+        reader.finish()?;
+        Ok(Self { entries })
+    }
+
+    /// Loads `path` if it exists, otherwise starts empty.
+    pub fn load_or_default(path: &Path) -> Result<Self> {
+        if path.exists() {
+            Self::load(path)
+        } else {
+            Ok(Self::new())
+        }
+    }
+}
+
+/// Embeds chunks through a pluggable [`Embed`] backend, the local model by default.
+/// With the `tokio` feature the backend can instead be an [`AsyncEmbed`].
+pub struct Embedder<E = FastEmbed> {
+    backend: E,
+    cache: Option<Mutex<EmbeddingCache>>,
+    batch_size: usize,
+    parallel_batches: usize, // 0 = one per rayon thread
+    normalize: bool,
+    error_on_truncation: bool,
+    query_prefix: String,
+    passage_prefix: String,
+    progress: Option<Box<ProgressFn>>,
+}
+
+/// Called with `(done, total)` texts after each batch.
+pub type ProgressFn = dyn Fn(usize, usize) + Send + Sync;
+
+const DEFAULT_BATCH_SIZE: usize = 256;
+
+impl Default for Embedder {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Embedder {
     pub fn new() -> Self {
-        let model = TextEmbedding::try_new(
-            InitOptions::new(fastembed::EmbeddingModel::AllMiniLML6V2)
-                .with_show_download_progress(true),
-        )
-        .expect("model init failed");
+        Self::try_new().expect("model init failed")
+    }
+
+    pub fn try_new() -> Result<Self> {
+        Ok(Self::with_backend(FastEmbed::new(
+            EmbeddingModel::AllMiniLML6V2,
+        )?))
+    }
+}
+
+impl<E> Embedder<E> {
+    pub fn with_backend(backend: E) -> Self {
+        Self {
+            backend,
+            cache: None,
+            batch_size: DEFAULT_BATCH_SIZE,
+            parallel_batches: 0,
+            normalize: true,
+            error_on_truncation: false,
+            query_prefix: String::new(),
+            passage_prefix: String::new(),
+            progress: None,
+        }
+    }
+
+    /// Maximum number of texts handed to the backend at once (default 256).
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// How many batches are embedded at once on the rayon pool (default `0`:
+    /// one per pool thread; `1` embeds them one after another). Each batch in
+    /// flight holds its embeddings and whatever the backend allocates for it,
+    /// so this and [`with_batch_size`](Self::with_batch_size) bound peak memory.
+    pub fn with_parallel_batches(mut self, parallel_batches: usize) -> Self {
+        self.parallel_batches = parallel_batches;
+        self
+    }
+
+    /// Whether embeddings are scaled to unit length before they are returned or
+    /// cached (default `true`). Unit vectors make cosine similarity a plain dot
+    /// product; see [`util::cosine_normalized`](crate::util::cosine_normalized).
+    pub fn with_normalize(mut self, normalize: bool) -> Self {
+        self.normalize = normalize;
+        self
+    }
+
+    /// Whether [`embed_chunks`](Self::embed_chunks) fails with
+    /// `RAGError::Embedding` on chunks the backend would truncate, instead of
+    /// logging a warning and embedding only their head (default `false`). See
+    /// [`truncated_chunks`](Self::truncated_chunks).
+    pub fn with_error_on_truncation(mut self, strict: bool) -> Self {
+        self.error_on_truncation = strict;
+        self
+    }
+
+    /// Prepended to the text given to [`embed_query`](Self::embed_query), for
+    /// models trained with instructions such as E5's `"query: "`.
+    pub fn with_query_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.query_prefix = prefix.into();
+        self
+    }
+
+    /// Prepended to each chunk's text by [`embed_chunks`](Self::embed_chunks),
+    /// e.g. `"passage: "`; the chunks themselves are left untouched. Cached
+    /// embeddings are keyed by chunk only, so clear the cache after changing it.
+    pub fn with_passage_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.passage_prefix = prefix.into();
+        self
+    }
+
+    /// Reports `(done, total)` after every batch. Batches may run in parallel,
+    /// but the callback always runs on the thread calling
+    /// [`embed_chunks`](Self::embed_chunks), once per batch in input order.
+    /// With a cache attached, `total` counts only the cache misses.
+    pub fn with_progress(
+        mut self,
+        progress: impl Fn(usize, usize) + Send + Sync + 'static,
+    ) -> Self {
+        self.progress = Some(Box::new(progress));
+        self
+    }
 
-        Self { model }
+    /// Consult `cache` before calling the backend and record every new embedding in it.
+    pub fn with_cache(mut self, cache: EmbeddingCache) -> Self {
+        self.cache = Some(Mutex::new(cache));
+        self
     }
 
-    pub fn embed_chunks(&mut self, chunks: &[Chunk]) -> Vec<Vec<f32>> {
-        let batch_size = 256;
+    /// The attached cache, e.g. to [`save`](EmbeddingCache::save) it after embedding.
+    pub fn cache(&self) -> Option<MutexGuard<'_, EmbeddingCache>> {
+        self.cache
+            .as_ref()
+            .map(|c| c.lock().unwrap_or_else(|poisoned| poisoned.into_inner()))
+    }
+
+    pub fn backend(&self) -> &E {
+        &self.backend
+    }
+
+    fn check_truncation(&self, chunks: &[Chunk], max_tokens: Option<usize>) -> Result<()> {
+        let truncated = overlong_chunks(chunks, max_tokens);
+        if truncated.is_empty() {
+            return Ok(());
+        }
+
+        let listing: Vec<String> = truncated
+            .iter()
+            .map(|c| {
+                let id: String = c.id.iter().map(|b| format!("{b:02x}")).collect();
+                format!("{}:{} ({id})", c.path, c.start_line)
+            })
+            .collect();
+        let message = format!(
+            "{} chunks exceed the model's {}-token input and would be truncated: {}",
+            truncated.len(),
+            max_tokens.unwrap_or_default(),
+            listing.join(", ")
+        );
+        if self.error_on_truncation {
+            return Err(RAGError::Embedding(message));
+        }
+        log::warn!("{message}");
+        Ok(())
+    }
+
+    // Cache misses among `chunks`, each once, or every chunk without a cache
+    fn uncached<'c>(&self, chunks: &'c [Chunk]) -> Vec<&'c Chunk> {
+        let Some(cache) = self.cache() else {
+            return chunks.iter().collect();
+        };
+        let mut queued = HashSet::new();
+        chunks
+            .iter()
+            .filter(|c| cache.get(&c.id).is_none() && queued.insert(c.id))
+            .collect()
+    }
 
-        let mut all = Vec::with_capacity(chunks.len());
+    // Stores fresh embeddings in the cache, if any, and lines everything up with `chunks`
+    fn collect_embeddings(
+        &self,
+        chunks: &[Chunk],
+        embedded: &[&Chunk],
+        embeddings: Vec<Vec<f32>>,
+    ) -> Vec<Vec<f32>> {
+        let Some(mut cache) = self.cache() else {
+            return embeddings;
+        };
+        for (chunk, embedding) in embedded.iter().zip(embeddings) {
+            cache.insert(chunk.id, embedding);
+        }
+        chunks
+            .iter()
+            .map(|c| cache.get(&c.id).unwrap().clone())
+            .collect()
+    }
 
-        for batch in chunks.chunks(batch_size) {
-            let texts: Vec<&str> = batch.iter().map(|c| c.text.as_str()).collect();
-            let embeddings = self.model.embed(texts, None).expect("batch failed");
-            all.extend(embeddings);
+    // Pending batches (`0`: one per rayon thread)
+    fn concurrency(&self) -> usize {
+        match self.parallel_batches {
+            0 => rayon::current_num_threads(),
+            n => n,
         }
+    }
+
+    fn finish_batch(&self, mut embeddings: Vec<Vec<f32>>, texts: usize) -> Result<Vec<Vec<f32>>> {
+        if embeddings.len() != texts {
+            return Err(RAGError::Embedding(format!(
+                "backend returned {} embeddings for {texts} texts",
+                embeddings.len()
+            )));
+        }
+        if self.normalize {
+            embeddings.iter_mut().for_each(|e| util::l2_normalize(e));
+        }
+        Ok(embeddings)
+    }
+}
+
+// Chunks longer than `max_tokens`; see `Embedder::truncated_chunks`
+fn overlong_chunks(chunks: &[Chunk], max_tokens: Option<usize>) -> Vec<&Chunk> {
+    let Some(max) = max_tokens else {
+        return vec![];
+    };
+    chunks
+        .iter()
+        .filter(|c| {
+            let tokens = c
+                .token_count
+                .unwrap_or_else(|| WhitespaceTokenCounter.count(&c.text));
+            tokens > max
+        })
+        .collect()
+}
+
+impl<E: Embed> Embedder<E> {
+    pub fn dim(&self) -> usize {
+        self.backend.dim()
+    }
+
+    /// Describes the embeddings this embedder produces, to store with an index.
+    pub fn meta(&self) -> EmbeddingMeta {
+        EmbeddingMeta {
+            model_id: self.backend.model_id().to_string(),
+            dim: self.backend.dim(),
+            normalized: self.normalize,
+        }
+    }
+
+    /// One embedding per chunk, in the same order as `chunks`. With a cache
+    /// attached, only chunks missing from it reach the backend.
+    pub fn embed_chunks(&self, chunks: &[Chunk]) -> Result<Vec<Vec<f32>>> {
+        self.check_truncation(chunks, self.backend.max_tokens())?;
+
+        let misses = self.uncached(chunks);
+        let texts: Vec<&str> = misses.iter().map(|c| c.text.as_str()).collect();
+        let embeddings = self.embed_texts(&texts)?;
+        Ok(self.collect_embeddings(chunks, &misses, embeddings))
+    }
+
+    /// Embeds only the chunks matching `predicate`, such as those of one edited
+    /// document, and pairs each embedding with its chunk's ID, ready for
+    /// [`Index::add`](crate::indexing::Index::add). The other chunks never reach
+    /// the backend; caching, truncation checks and progress work as in
+    /// [`embed_chunks`](Self::embed_chunks).
+    pub fn embed_chunks_where(
+        &self,
+        chunks: &[Chunk],
+        predicate: impl Fn(&Chunk) -> bool,
+    ) -> Result<Vec<(ChunkID, Vec<f32>)>> {
+        let selected: Vec<Chunk> = chunks.iter().filter(|c| predicate(c)).cloned().collect();
+        let embeddings = self.embed_chunks(&selected)?;
+        Ok(selected.iter().map(|c| c.id).zip(embeddings).collect())
+    }
+
+    /// Chunks longer than the backend's [`max_tokens`](Embed::max_tokens), whose
+    /// tails would be silently cut off and never become searchable. Sizes come
+    /// from [`Chunk::token_count`] when the chunker counted tokens, and otherwise
+    /// from a whitespace word count, which undercounts subword tokens: chunks
+    /// it flags are certainly too long, but some overlong ones may slip through.
+    pub fn truncated_chunks<'c>(&self, chunks: &'c [Chunk]) -> Vec<&'c Chunk> {
+        overlong_chunks(chunks, self.backend.max_tokens())
+    }
+
+    /// Embeds a search query the same way as [`embed_chunks`](Self::embed_chunks)
+    /// embeds chunk text, normalization included, so the result can be passed
+    /// straight to [`Index::search`](crate::indexing::Index::search). Queries
+    /// bypass the cache and the progress callback.
+    pub fn embed_query(&self, text: &str) -> Result<Vec<f32>> {
+        let mut embeddings = self.embed_batch(&[text], &self.query_prefix)?;
+        Ok(embeddings.pop().expect("embed_batch checks the count"))
+    }
+
+    fn embed_texts(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+        let parallel = self.concurrency();
+        let mut all = Vec::with_capacity(texts.len());
+
+        // Waves of at most `parallel` batches; rayon collects each wave in input order
+        for wave in texts.chunks(self.batch_size.saturating_mul(parallel)) {
+            let batches: Vec<Vec<Vec<f32>>> = wave
+                .par_chunks(self.batch_size)
+                .map(|batch| self.embed_batch(batch, &self.passage_prefix))
+                .collect::<Result<_>>()?;
+            for embeddings in batches {
+                all.extend(embeddings);
+                if let Some(progress) = &self.progress {
+                    progress(all.len(), texts.len());
+                }
+            }
+        }
+
+        Ok(all)
+    }
+
+    fn embed_batch(&self, batch: &[&str], prefix: &str) -> Result<Vec<Vec<f32>>> {
+        let embeddings = if prefix.is_empty() {
+            self.backend.embed(batch)?
+        } else {
+            let prefixed: Vec<String> = batch.iter().map(|t| format!("{prefix}{t}")).collect();
+            let prefixed: Vec<&str> = prefixed.iter().map(String::as_str).collect();
+            self.backend.embed(&prefixed)?
+        };
+        self.finish_batch(embeddings, batch.len())
+    }
+}
+
+/// An embedding backend that is called asynchronously, typically a remote
+/// service where batches spend most of their time waiting on the network.
+/// Embed through it with [`Embedder::embed_chunks_async`]. A blocking model
+/// can be adapted by running its [`Embed::embed`] under
+/// `tokio::task::spawn_blocking`.
+#[cfg(feature = "tokio")]
+pub trait AsyncEmbed: Send + Sync {
+    /// One vector per text, in order.
+    fn embed(&self, texts: &[&str]) -> impl Future<Output = Result<Vec<Vec<f32>>>> + Send;
+
+    fn dim(&self) -> usize;
+
+    /// Input size in tokens beyond which the backend truncates text; see
+    /// [`Embed::max_tokens`].
+    fn max_tokens(&self) -> Option<usize> {
+        None
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<A: AsyncEmbed> Embedder<A> {
+    /// Async counterpart of [`embed_chunks`](Self::embed_chunks): the same
+    /// cache, truncation check, prefixing and normalization, with up to
+    /// [`parallel_batches`](Self::with_parallel_batches) batches awaiting the
+    /// backend at once instead of running on rayon threads.
+    pub async fn embed_chunks_async(&self, chunks: &[Chunk]) -> Result<Vec<Vec<f32>>> {
+        self.check_truncation(chunks, self.backend.max_tokens())?;
+
+        let misses = self.uncached(chunks);
+        let texts: Vec<&str> = misses.iter().map(|c| c.text.as_str()).collect();
+        let embeddings = self.embed_texts_async(&texts).await?;
+        Ok(self.collect_embeddings(chunks, &misses, embeddings))
+    }
+
+    async fn embed_texts_async(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+        let permits = tokio::sync::Semaphore::new(self.concurrency());
+        let done = std::sync::atomic::AtomicUsize::new(0);
+
+        let batches = texts.chunks(self.batch_size).map(|batch| async {
+            let _permit = permits.acquire().await.expect("semaphore is never closed");
+            let embeddings = self.embed_batch_async(batch).await?;
+            if let Some(progress) = &self.progress {
+                let done = done.fetch_add(batch.len(), std::sync::atomic::Ordering::Relaxed);
+                progress(done + batch.len(), texts.len());
+            }
+            Ok::<_, RAGError>(embeddings)
+        });
+        // try_join_all keeps input order however the batches finish
+        let batches = futures_util::future::try_join_all(batches).await?;
+        Ok(batches.into_iter().flatten().collect())
+    }
 
-        all
+    async fn embed_batch_async(&self, batch: &[&str]) -> Result<Vec<Vec<f32>>> {
+        let prefix = &self.passage_prefix;
+        let embeddings = if prefix.is_empty() {
+            self.backend.embed(batch).await?
+        } else {
+            let prefixed: Vec<String> = batch.iter().map(|t| format!("{prefix}{t}")).collect();
+            let prefixed: Vec<&str> = prefixed.iter().map(String::as_str).collect();
+            self.backend.embed(&prefixed).await?
+        };
+        self.finish_batch(embeddings, batch.len())
     }
 }
@@ -16,6 +16,13 @@ pub enum RAGError {
         source: std::string::FromUtf8Error,
     },
 
+    #[error("Invalid glob pattern {pattern}: {source}")]
+    InvalidGlob {
+        pattern: String,
+        #[source]
+        source: globset::Error,
+    },
+
     #[error("Tree-sitter parsing failed for {extension}")]
     ParsingFailed { extension: String },
 
@@ -37,11 +44,35 @@ pub enum RAGError {
     #[error("Dimension mismatch: expected {expected}, got {got}")]
     DimensionMismatch { expected: usize, got: usize },
 
+    #[error(
+        "Embedding model mismatch: index holds embeddings from {expected}, query is from {got}"
+    )]
+    ModelMismatch { expected: String, got: String },
+
+    #[error("Query {query} in batch failed: {source}")]
+    BatchQuery {
+        query: usize,
+        #[source]
+        source: Box<RAGError>,
+    },
+
     #[error("Invalid index: {0}")]
     InvalidIndex(usize),
 
+    #[error("Failed to extract {node_kind} node text from document {doc_id:?}")]
+    ChunkExtraction { doc_id: [u8; 32], node_kind: String },
+
     #[error("No chunks produced for document {doc_id:?}")]
     NoChunks { doc_id: [u8; 32] },
+
+    #[error("Index holds {chunks} chunks but {embeddings} embeddings")]
+    CountMismatch { chunks: usize, embeddings: usize },
+
+    #[error("Chunk ID map disagrees with the chunk at position {idx}")]
+    IdMapMismatch { idx: usize },
+
+    #[error("Embedding {idx} contains NaN or infinite values")]
+    NonFiniteEmbedding { idx: usize },
 }
 
 pub type Result<T> = std::result::Result<T, RAGError>;
@@ -0,0 +1,44 @@
+//! The digest behind every [`DocumentID`](crate::document::DocumentID) and
+//! [`ChunkID`](crate::chunking::ChunkID).
+//!
+//! SHA-256 by default. The `blake3` feature switches to BLAKE3, which has the
+//! same 32-byte output and hashes large repositories several times faster. IDs
+//! from the two builds never match, so an index or embedding cache saved by one
+//! build is treated as entirely new content by the other.
+
+#[cfg(not(feature = "blake3"))]
+use sha2::Digest;
+
+// Recorded in every saved index and cache, whose IDs are only meaningful to a matching build
+pub(crate) const ALGORITHM: &str = if cfg!(feature = "blake3") {
+    "blake3"
+} else {
+    "sha256"
+};
+
+#[derive(Clone)]
+pub(crate) struct IdHasher {
+    #[cfg(not(feature = "blake3"))]
+    inner: sha2::Sha256,
+    #[cfg(feature = "blake3")]
+    inner: blake3::Hasher,
+}
+
+impl IdHasher {
+    pub(crate) fn new() -> Self {
+        Self {
+            #[cfg(not(feature = "blake3"))]
+            inner: sha2::Sha256::new(),
+            #[cfg(feature = "blake3")]
+            inner: blake3::Hasher::new(),
+        }
+    }
+
+    pub(crate) fn update(&mut self, bytes: impl AsRef<[u8]>) {
+        self.inner.update(bytes.as_ref());
+    }
+
+    pub(crate) fn finalize(self) -> [u8; 32] {
+        self.inner.finalize().into()
+    }
+}
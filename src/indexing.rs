@@ -1,52 +1,1366 @@
-use hnsw_rs::{hnsw::Hnsw, prelude::DistCosine};
+use std::borrow::Cow;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::path::Path;
+use std::sync::OnceLock;
 
-use crate::chunking::Chunk;
+use rayon::prelude::*;
 
-pub struct Index<'a> {
+use crate::bm25::Bm25;
+use crate::chunking::{Chunk, ChunkID, ChunkKind};
+use crate::codec::{Reader, put_f32s, put_header, put_str, put_u64, read_file, write_file};
+use crate::document::{Document, DocumentID};
+use crate::embedding::{Embed, EmbeddingMeta};
+use crate::store::Store;
+pub use crate::store::VectorStore;
+use crate::util::{cosine_from_dot, cosine_similarity, dot, norm};
+use crate::{RAGError, Result};
+
+// On-disk layout (see `codec`): header with the ID hash, metric, embedding dimension, the
+// optional `EmbeddingMeta`, chunk count, every chunk, zero padding to a multiple of 4 bytes,
+// then all embeddings back to back. Keeping the embeddings contiguous and aligned lets
+// `load_mmap` use them in place.
+const INDEX_MAGIC: &[u8; 8] = b"WUBRAGIX";
+const INDEX_FORMAT_VERSION: u32 = 8;
+
+/// How [`Index::search`] compares a query against the stored embeddings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Metric {
+    /// Cosine similarity, higher is better.
+    #[default]
+    Cosine,
+    /// Raw inner product; equivalent to cosine for unit-length embeddings but cheaper.
+    DotProduct,
+    /// L2 distance, lower is better.
+    Euclidean,
+}
+
+impl Metric {
+    pub fn score(self, a: &[f32], b: &[f32]) -> f32 {
+        match self {
+            Metric::Cosine => cosine_similarity(a, b),
+            Metric::DotProduct => dot(a, b),
+            Metric::Euclidean => euclidean(a, b),
+        }
+    }
+
+    pub fn higher_is_better(self) -> bool {
+        !matches!(self, Metric::Euclidean)
+    }
+
+    // Orders scores best-first for this metric, with NaN always last
+    fn rank(self, a: f32, b: f32) -> std::cmp::Ordering {
+        use std::cmp::Ordering;
+        match (a.is_nan(), b.is_nan()) {
+            (true, true) => Ordering::Equal,
+            (true, false) => Ordering::Greater,
+            (false, true) => Ordering::Less,
+            _ if self.higher_is_better() => b.total_cmp(&a),
+            _ => a.total_cmp(&b),
+        }
+    }
+
+    // Orders `(position, score)` results best-first, breaking ties by position so equal
+    // scores come back in the same order on every run
+    fn rank_results(self, a: &(usize, f32), b: &(usize, f32)) -> std::cmp::Ordering {
+        self.rank(a.1, b.1).then(a.0.cmp(&b.0))
+    }
+
+    // Whether `score` is at least as good as `threshold`; NaN never is
+    fn meets(self, score: f32, threshold: f32) -> bool {
+        if self.higher_is_better() {
+            score >= threshold
+        } else {
+            score <= threshold
+        }
+    }
+
+    fn to_byte(self) -> u8 {
+        match self {
+            Metric::Cosine => 0,
+            Metric::DotProduct => 1,
+            Metric::Euclidean => 2,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Result<Self> {
+        match byte {
+            0 => Ok(Metric::Cosine),
+            1 => Ok(Metric::DotProduct),
+            2 => Ok(Metric::Euclidean),
+            other => Err(RAGError::Deserialization(format!(
+                "unknown metric tag {other}"
+            ))),
+        }
+    }
+}
+
+/// Compact in-memory representations for [`Index::quantized`].
+///
+/// Quantized embeddings are approximations: every similarity is computed
+/// against the dequantized vector, so scores drift slightly from an exact
+/// index and near-ties can swap places. Clearly separated results are
+/// unaffected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum QuantMode {
+    /// One signed byte per component plus an `f32` scale per embedding, about
+    /// a quarter of the memory of `f32`. The largest component of each
+    /// embedding maps to ±127, so every component is off by at most 1/254 of
+    /// that magnitude; on typical text embeddings cosine scores move by well
+    /// under 0.01.
+    Int8,
+}
+
+fn euclidean(a: &[f32], b: &[f32]) -> f32 {
+    a.iter()
+        .zip(b)
+        .map(|(x, y)| (x - y) * (x - y))
+        .sum::<f32>()
+        .sqrt()
+}
+
+// Rescales to [0, 1]; a constant input (including all zeros) maps to all zeros
+fn min_max_normalize(mut scores: Vec<f32>) -> Vec<f32> {
+    let finite = scores.iter().copied().filter(|s| s.is_finite());
+    let (min, max) = finite.fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), s| {
+        (lo.min(s), hi.max(s))
+    });
+    let range = max - min;
+    for score in &mut scores {
+        *score = if range > 0.0 && score.is_finite() {
+            (*score - min) / range
+        } else {
+            0.0
+        };
+    }
+    scores
+}
+
+// The `k` best of `scored` by `Metric::rank_results`, best first: what sorting everything and
+// truncating gives, but each rayon job only keeps a bounded heap of its `k` best, so selection
+// is O(n log k)
+fn top_k(
+    metric: Metric,
+    scored: impl ParallelIterator<Item = (usize, f32)>,
+    k: usize,
+) -> Vec<(usize, f32)> {
+    if k == 0 {
+        return vec![];
+    }
+    let push = |heap: &mut BinaryHeap<Ranked>, result: Ranked| {
+        if heap.len() < k {
+            heap.push(result);
+        } else if let Some(mut worst) = heap.peek_mut()
+            && result < *worst
+        {
+            *worst = result;
+        }
+    };
+    scored
+        .fold(BinaryHeap::new, |mut heap, result| {
+            push(&mut heap, Ranked(metric, result));
+            heap
+        })
+        .reduce(BinaryHeap::new, |mut a, b| {
+            b.into_iter().for_each(|result| push(&mut a, result));
+            a
+        })
+        .into_sorted_vec()
+        .into_iter()
+        .map(|Ranked(_, result)| result)
+        .collect()
+}
+
+// A `(position, score)` result that orders better-first, so the top of a max-heap of them is
+// the worst one kept
+struct Ranked(Metric, (usize, f32));
+
+impl PartialEq for Ranked {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for Ranked {}
+
+impl PartialOrd for Ranked {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Ranked {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.0.rank_results(&self.1, &other.1)
+    }
+}
+
+/// One hit from [`Index::search`].
+///
+/// Results order by raw score, ties broken towards the lower position, so a
+/// [`BinaryHeap`](std::collections::BinaryHeap) pops the highest score first.
+/// With [`Metric::Euclidean`] the score is a distance, where lower is better.
+#[derive(Debug, Clone, Copy)]
+pub struct SearchResult {
+    /// Position of the chunk in [`Index::chunks`].
+    pub index: usize,
+    pub score: f32,
+    pub chunk_id: ChunkID,
+}
+
+impl PartialEq for SearchResult {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for SearchResult {}
+
+impl PartialOrd for SearchResult {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for SearchResult {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.score
+            .total_cmp(&other.score)
+            .then(other.index.cmp(&self.index))
+    }
+}
+
+/// One indexed document in an [`Index::manifest`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DocumentEntry {
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex"))]
+    pub doc_id: DocumentID,
+    pub path: String,
+    pub chunk_count: usize,
+    /// Summed [`Chunk::char_count`]; overlap and nested chunks count twice.
+    pub total_chars: usize,
+    /// File size, when the manifest was joined with the source documents.
+    pub size: Option<u64>,
+    /// [`Document::syntax`], when the manifest was joined with the source documents.
+    pub language: Option<String>,
+}
+
+/// The retrieved chunks of one document stitched into a single excerpt; see
+/// [`Index::assemble_context`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DocumentContext {
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex"))]
+    pub doc_id: DocumentID,
+    pub path: String,
+    /// Best score among the document's results.
+    pub score: f32,
+    /// Positions of the chunks that went into `text`, in document order.
+    pub chunks: Vec<usize>,
+    /// Chunk texts in document order: adjacent chunks run on, chunks far apart
+    /// are separated by a `…` line, and overlap is only included once.
+    pub text: String,
+}
+
+/// What [`Index::update`] changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct UpdateReport {
+    /// New chunks, each embedded once.
+    pub added: usize,
+    /// Indexed chunks whose ID is no longer present, dropped with their
+    /// embeddings. A chunk that only moved counts here under its old ID.
+    pub removed: usize,
+    /// New chunks that needed no embedding: their ID was already indexed, or a
+    /// dropped chunk had the same text and passed its embedding on.
+    pub kept: usize,
+}
+
+/// Exact nearest-neighbour index over chunk embeddings.
+pub struct Index {
     pub chunks: Vec<Chunk>,
-    hnsw_index: Hnsw<'a, f32, DistCosine>,
+    embeddings: Store,
+    norms: Vec<f32>, // L2 norm of each embedding, so cosine search only measures the query
+    dim: Option<usize>, // shared length of every embedding; unset until the first one arrives
+    id_to_idx: HashMap<ChunkID, usize>,
+    metric: Metric,
+    // which model produced the embeddings, when known
+    meta: Option<EmbeddingMeta>,
+    bm25: OnceLock<Bm25>, // built on the first hybrid search, dropped when chunks change
 }
 
-impl<'a> Index<'a> {
-    pub fn new(chunks: Vec<Chunk>, embeddings: Vec<Vec<f32>>) -> Self {
-        let embedding_dim = embeddings.first().unwrap().len();
+impl Index {
+    /// Fails with [`RAGError::DimensionMismatch`] unless every embedding has
+    /// the same length as the first.
+    pub fn new(chunks: Vec<Chunk>, embeddings: Vec<Vec<f32>>) -> Result<Self> {
+        Self::new_with_metric(chunks, embeddings, Metric::default())
+    }
 
-        let max_elements = embeddings.len();
-        let ef_construction = 200;
-        let max_nb_connection = 16;
+    pub fn new_with_metric(
+        chunks: Vec<Chunk>,
+        embeddings: Vec<Vec<f32>>,
+        metric: Metric,
+    ) -> Result<Self> {
+        Self::with_store(chunks, embeddings, metric, None)
+    }
 
-        let hnsw_index = Hnsw::<f32, DistCosine>::new(
-            max_nb_connection,
-            max_elements,
-            ef_construction,
-            embedding_dim,
-            DistCosine {},
-        );
+    /// Like [`new`](Self::new), but keeps the embeddings in the compact form
+    /// chosen by `mode` and searches them directly, dequantizing on the fly.
+    /// See [`QuantMode`] for the effect on scores. Embeddings added later are
+    /// quantized as well; [`save`](Self::save) writes them dequantized.
+    pub fn quantized(
+        chunks: Vec<Chunk>,
+        embeddings: Vec<Vec<f32>>,
+        mode: QuantMode,
+    ) -> Result<Self> {
+        Self::with_store(chunks, embeddings, Metric::default(), Some(mode))
+    }
+
+    /// Searches embeddings that `store` keeps outside the index, e.g. in a
+    /// file read on demand, instead of holding them in memory; each is read
+    /// once here to measure its norm and again whenever it is scored. Adding
+    /// or removing chunks copies the embeddings into memory first.
+    ///
+    /// Fails with [`RAGError::CountMismatch`] unless `store` holds one
+    /// embedding per chunk. Lengths are taken from [`VectorStore::dim`];
+    /// [`validate`](Self::validate) checks every embedding against it.
+    pub fn with_vector_store(
+        chunks: Vec<Chunk>,
+        store: impl VectorStore + 'static,
+        metric: Metric,
+    ) -> Result<Self> {
+        if store.len() != chunks.len() {
+            return Err(RAGError::CountMismatch {
+                chunks: chunks.len(),
+                embeddings: store.len(),
+            });
+        }
+        let dim = (!store.is_empty()).then(|| store.dim());
+        Ok(Self::from_store(
+            chunks,
+            Store::External(Box::new(store)),
+            dim,
+            metric,
+        ))
+    }
+
+    fn with_store(
+        chunks: Vec<Chunk>,
+        embeddings: Vec<Vec<f32>>,
+        metric: Metric,
+        mode: Option<QuantMode>,
+    ) -> Result<Self> {
+        let dim = embeddings.first().map(Vec::len);
+        if let Some(expected) = dim
+            && let Some(ragged) = embeddings.iter().find(|e| e.len() != expected)
+        {
+            return Err(RAGError::DimensionMismatch {
+                expected,
+                got: ragged.len(),
+            });
+        }
+
+        Ok(Self::from_store(
+            chunks,
+            Store::new(embeddings, mode),
+            dim,
+            metric,
+        ))
+    }
+
+    fn from_store(
+        chunks: Vec<Chunk>,
+        embeddings: Store,
+        dim: Option<usize>,
+        metric: Metric,
+    ) -> Self {
+        let id_to_idx = chunks
+            .iter()
+            .enumerate()
+            .map(|(idx, c)| (c.id, idx))
+            .collect();
+        // measured after quantizing, so they match the vectors search actually sees
+        let norms = (0..embeddings.len())
+            .into_par_iter()
+            .map(|idx| norm(&embeddings.get(idx)))
+            .collect();
+
+        Self {
+            chunks,
+            embeddings,
+            norms,
+            dim,
+            id_to_idx,
+            metric,
+            meta: None,
+            bm25: OnceLock::new(),
+        }
+    }
+
+    pub fn metric(&self) -> Metric {
+        self.metric
+    }
+
+    /// How the embeddings are stored; `None` for full-precision `f32`.
+    pub fn quantization(&self) -> Option<QuantMode> {
+        self.embeddings.mode()
+    }
+
+    /// Length of the stored embeddings, or `None` while the index is empty.
+    pub fn dim(&self) -> Option<usize> {
+        self.dim
+    }
+
+    /// Records which model produced the embeddings; it is saved with the index.
+    /// Fails with [`RAGError::DimensionMismatch`] if `meta.dim` disagrees with
+    /// the stored embeddings.
+    pub fn with_embedding_meta(mut self, meta: EmbeddingMeta) -> Result<Self> {
+        if let Some(expected) = self.dim
+            && expected != meta.dim
+        {
+            return Err(RAGError::DimensionMismatch {
+                expected,
+                got: meta.dim,
+            });
+        }
+        self.meta = Some(meta);
+        Ok(self)
+    }
+
+    /// Which model produced the embeddings, if it was recorded.
+    pub fn embedding_meta(&self) -> Option<&EmbeddingMeta> {
+        self.meta.as_ref()
+    }
+
+    /// Checks that queries embedded as `meta` describes can be compared with
+    /// the stored embeddings. A different dimension fails with
+    /// [`RAGError::DimensionMismatch`]; a different model, or a different
+    /// normalization under a metric other than cosine (which ignores length),
+    /// fails with [`RAGError::ModelMismatch`]. Without recorded meta only the
+    /// dimension is checked.
+    pub fn check_meta(&self, meta: &EmbeddingMeta) -> Result<()> {
+        if let Some(expected) = self.dim
+            && expected != meta.dim
+        {
+            return Err(RAGError::DimensionMismatch {
+                expected,
+                got: meta.dim,
+            });
+        }
+        match &self.meta {
+            Some(own)
+                if own.model_id != meta.model_id
+                    || (own.normalized != meta.normalized && self.metric != Metric::Cosine) =>
+            {
+                Err(RAGError::ModelMismatch {
+                    expected: own.to_string(),
+                    got: meta.to_string(),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
 
-        for (idx, embedding) in embeddings.iter().enumerate() {
-            hnsw_index.insert((embedding.as_slice(), idx));
+    pub(crate) fn check_dim(&self, query: &[f32]) -> Result<()> {
+        match self.dim {
+            Some(expected) if expected != query.len() => Err(RAGError::DimensionMismatch {
+                expected,
+                got: query.len(),
+            }),
+            _ => Ok(()),
         }
+    }
+
+    /// Scores every embedding against `query` and returns the `k` best, best
+    /// first. For [`Metric::Euclidean`] the score is the distance, so lower
+    /// scores come first. A query whose length differs from the stored
+    /// embeddings fails with [`RAGError::DimensionMismatch`].
+    pub fn search(&self, query: &[f32], k: usize) -> Result<Vec<SearchResult>> {
+        Ok(self
+            .top_k(query, k)?
+            .into_iter()
+            .map(|(index, score)| SearchResult {
+                index,
+                score,
+                chunk_id: self.chunks[index].id,
+            })
+            .collect())
+    }
+
+    /// [`search`](Self::search) for a query embedded as `meta` describes,
+    /// rejected up front by [`check_meta`](Self::check_meta) if it came from
+    /// an incompatible model.
+    pub fn search_with_meta(
+        &self,
+        query: &[f32],
+        k: usize,
+        meta: &EmbeddingMeta,
+    ) -> Result<Vec<SearchResult>> {
+        self.check_meta(meta)?;
+        self.search(query, k)
+    }
+
+    /// [`search`](Self::search) as `(position, score)` pairs, the shape it
+    /// returned before [`SearchResult`].
+    #[deprecated(note = "use `search`, which returns `SearchResult`s")]
+    pub fn search_raw(&self, query: &[f32], k: usize) -> Result<Vec<(usize, f32)>> {
+        self.top_k(query, k)
+    }
+
+    fn top_k(&self, query: &[f32], k: usize) -> Result<Vec<(usize, f32)>> {
+        self.search_filtered(query, k, |_| true)
+    }
+
+    /// Like [`search`](Self::search), but only chunks passing `predicate` are
+    /// scored at all.
+    pub fn search_filtered(
+        &self,
+        query: &[f32],
+        k: usize,
+        predicate: impl Fn(&Chunk) -> bool + Sync,
+    ) -> Result<Vec<(usize, f32)>> {
+        self.check_dim(query)?;
+        let metric = self.metric;
+        let query_norm = norm(query);
+        let scored = (0..self.embeddings.len())
+            .into_par_iter()
+            .filter(|&idx| predicate(&self.chunks[idx]))
+            .map(|idx| (idx, self.score_at(query, query_norm, idx)));
+        Ok(top_k(metric, scored, k))
+    }
+
+    // Scores a candidate set (e.g. from an approximate index) exactly, best first
+    #[cfg_attr(not(feature = "hnsw"), allow(dead_code))]
+    pub(crate) fn rescore(
+        &self,
+        query: &[f32],
+        positions: &[usize],
+        k: usize,
+    ) -> Vec<(usize, f32)> {
+        let query_norm = norm(query);
+        let mut scored: Vec<(usize, f32)> = positions
+            .iter()
+            .map(|&idx| (idx, self.score_at(query, query_norm, idx)))
+            .collect();
+        scored.sort_unstable_by(|a, b| self.metric.rank_results(a, b));
+        scored.truncate(k);
+        scored
+    }
+
+    fn score_at(&self, query: &[f32], query_norm: f32, idx: usize) -> f32 {
+        match self.metric {
+            Metric::Cosine => self.cosine_at(query, query_norm, idx),
+            Metric::DotProduct => self.embeddings.dot(idx, query),
+            Metric::Euclidean => euclidean(query, &self.embeddings.get(idx)),
+        }
+    }
+
+    fn cosine_at(&self, query: &[f32], query_norm: f32, idx: usize) -> f32 {
+        cosine_from_dot(self.embeddings.dot(idx, query), query_norm, self.norms[idx])
+    }
 
-        Self { chunks, hnsw_index }
+    /// Restricts the search to chunks whose [`Chunk::raw_kind`] is `raw_kind`.
+    pub fn search_by_type(
+        &self,
+        query: &[f32],
+        k: usize,
+        raw_kind: &str,
+    ) -> Result<Vec<(usize, f32)>> {
+        self.search_filtered(query, k, |c| c.raw_kind == raw_kind)
     }
 
-    pub fn search(&self, query: &[f32], k: usize, ef_search: usize) -> Vec<(usize, f32)> {
-        let neighbors = self.hnsw_index.search(query, k, ef_search);
+    /// Ranks by cosine similarity multiplied by the weight of each chunk's
+    /// [`Chunk::raw_kind`] in `weights`, e.g. `"struct_item" => 1.5` to favour
+    /// definitions over stray comment paragraphs. Kinds left out weigh `1.0`.
+    /// The similarity is first shifted into `[0, 1]` as `(1 + cos) / 2`, so a
+    /// weight above `1.0` favours its kind even when no chunk points the query's
+    /// way; the returned score is the weighted, shifted one.
+    pub fn search_weighted(
+        &self,
+        query: &[f32],
+        k: usize,
+        weights: &HashMap<&str, f32>,
+    ) -> Result<Vec<(usize, f32)>> {
+        self.check_dim(query)?;
+        let query_norm = norm(query);
+        let scored = (0..self.embeddings.len()).into_par_iter().map(|idx| {
+            let weight = weights
+                .get(self.chunks[idx].raw_kind.as_ref())
+                .copied()
+                .unwrap_or(1.0);
+            let similarity = (1.0 + self.cosine_at(query, query_norm, idx)) / 2.0;
+            (idx, weight * similarity)
+        });
+        Ok(top_k(Metric::Cosine, scored, k))
+    }
+
+    /// Restricts the search to chunks whose document path starts with `prefix`.
+    pub fn search_in_path(
+        &self,
+        query: &[f32],
+        k: usize,
+        prefix: &str,
+    ) -> Result<Vec<(usize, f32)>> {
+        self.search_filtered(query, k, |c| c.path.starts_with(prefix))
+    }
+
+    /// Runs [`search`](Self::search) for every query in parallel, returning
+    /// `(position, score)` pairs. Every query
+    /// must match the index dimension; the first that doesn't is reported as a
+    /// [`RAGError::BatchQuery`] wrapping the [`RAGError::DimensionMismatch`].
+    pub fn search_batch(&self, queries: &[Vec<f32>], k: usize) -> Result<Vec<Vec<(usize, f32)>>> {
+        if let Some(expected) = self.dim
+            && let Some((query, q)) = queries
+                .iter()
+                .enumerate()
+                .find(|(_, q)| q.len() != expected)
+        {
+            return Err(RAGError::BatchQuery {
+                query,
+                source: Box::new(RAGError::DimensionMismatch {
+                    expected,
+                    got: q.len(),
+                }),
+            });
+        }
+
+        queries.par_iter().map(|q| self.top_k(q, k)).collect()
+    }
 
-        let results = neighbors
+    /// Returns the best-scoring chunk from each of up to `k` distinct documents,
+    /// so one long file can't fill every slot.
+    pub fn search_unique_docs(&self, query: &[f32], k: usize) -> Result<Vec<(usize, f32)>> {
+        let mut seen = HashSet::new();
+        Ok(self
+            .top_k(query, self.chunks.len())?
             .into_iter()
-            .map(|neighbor| {
-                let idx = neighbor.d_id;
-                let distance = neighbor.distance;
-                let similarity = 1.0 - distance;
-                (idx, similarity)
+            .filter(|&(idx, _)| seen.insert(self.chunks[idx].doc_id))
+            .take(k)
+            .collect())
+    }
+
+    /// Blends BM25 keyword relevance of `query_text` with cosine similarity of
+    /// `query_emb`. Both are min-max normalized to `[0, 1]` over the whole index
+    /// and combined as `alpha * vector + (1 - alpha) * keyword`, so `alpha = 1.0`
+    /// is pure vector search and `alpha = 0.0` pure BM25.
+    pub fn search_hybrid(
+        &self,
+        query_text: &str,
+        query_emb: &[f32],
+        k: usize,
+        alpha: f32,
+    ) -> Result<Vec<(usize, f32)>> {
+        self.check_dim(query_emb)?;
+        let bm25 = self
+            .bm25
+            .get_or_init(|| Bm25::new(self.chunks.iter().map(|c| c.text.as_str())));
+        let keyword = min_max_normalize(bm25.scores(query_text));
+
+        let query_norm = norm(query_emb);
+        let vector = min_max_normalize(
+            (0..self.embeddings.len())
+                .into_par_iter()
+                .map(|idx| self.cosine_at(query_emb, query_norm, idx))
+                .collect(),
+        );
+
+        let scored = vector
+            .into_par_iter()
+            .zip(keyword)
+            .map(|(v, kw)| alpha * v + (1.0 - alpha) * kw)
+            .enumerate();
+        Ok(top_k(Metric::Cosine, scored, k))
+    }
+
+    /// Positions of the chunks whose text contains `needle` literally, in index
+    /// order: a grep-like fallback for rare identifiers that embeddings don't
+    /// capture. Case-insensitive matching compares lowercased text.
+    pub fn search_substring(&self, needle: &str, case_sensitive: bool) -> Vec<usize> {
+        let needle = if case_sensitive {
+            Cow::Borrowed(needle)
+        } else {
+            Cow::Owned(needle.to_lowercase())
+        };
+        (0..self.chunks.len())
+            .into_par_iter()
+            .filter(|&idx| {
+                let text = &self.chunks[idx].text;
+                if case_sensitive {
+                    text.contains(needle.as_ref())
+                } else {
+                    text.to_lowercase().contains(needle.as_ref())
+                }
             })
+            .collect()
+    }
+
+    /// Maximal Marginal Relevance: picks results one at a time, maximizing
+    /// `lambda * sim(query, c) - (1 - lambda) * max sim(c, selected)` so
+    /// near-duplicates of earlier picks are pushed down. `lambda = 1.0` is plain
+    /// top-k. Similarities are always cosine, whatever the index metric; the
+    /// returned score is the query similarity.
+    pub fn search_mmr(&self, query: &[f32], k: usize, lambda: f32) -> Result<Vec<(usize, f32)>> {
+        self.check_dim(query)?;
+        let query_norm = norm(query);
+        let relevance: Vec<f32> = (0..self.embeddings.len())
+            .into_par_iter()
+            .map(|idx| self.cosine_at(query, query_norm, idx))
             .collect();
 
-        results
+        let mut redundancy = vec![f32::NEG_INFINITY; self.embeddings.len()];
+        let mut selected: Vec<(usize, f32)> = Vec::with_capacity(k);
+        let mut remaining: Vec<usize> = (0..self.embeddings.len()).collect();
+        while selected.len() < k && !remaining.is_empty() {
+            let mmr = |idx: usize| {
+                let penalty = if selected.is_empty() {
+                    0.0
+                } else {
+                    redundancy[idx]
+                };
+                lambda * relevance[idx] - (1.0 - lambda) * penalty
+            };
+            let (pos, _) = remaining
+                .iter()
+                .copied()
+                .enumerate()
+                .min_by(|&(_, a), &(_, b)| Metric::Cosine.rank_results(&(a, mmr(a)), &(b, mmr(b))))
+                .unwrap();
+            let picked = remaining.swap_remove(pos);
+            selected.push((picked, relevance[picked]));
+
+            let picked_embedding = self.embeddings.get(picked);
+            let picked_norm = self.norms[picked];
+            for &idx in &remaining {
+                let sim = self.cosine_at(&picked_embedding, picked_norm, idx);
+                redundancy[idx] = redundancy[idx].max(sim);
+            }
+        }
+        Ok(selected)
+    }
+
+    /// Like [`search`](Self::search), but drops results scoring worse than
+    /// `min_score` (for [`Metric::Euclidean`], farther than it), so an
+    /// irrelevant query can return fewer than `k` results or none at all.
+    pub fn search_threshold(
+        &self,
+        query: &[f32],
+        k: usize,
+        min_score: f32,
+    ) -> Result<Vec<(usize, f32)>> {
+        let metric = self.metric;
+        let mut results = self.top_k(query, k)?;
+        results.retain(|&(_, score)| metric.meets(score, min_score));
+        Ok(results)
+    }
+
+    /// Inserts a chunk, rejecting embeddings whose dimension differs from the
+    /// ones already stored. A chunk whose ID is already present is replaced.
+    pub fn add(&mut self, chunk: Chunk, embedding: Vec<f32>) -> Result<()> {
+        self.check_dim(&embedding)?;
+        self.dim = Some(embedding.len());
+
+        self.bm25.take();
+        let idx = match self.id_to_idx.get(&chunk.id) {
+            Some(&idx) => {
+                self.chunks[idx] = chunk;
+                self.embeddings.set(idx, embedding);
+                idx
+            }
+            None => {
+                let idx = self.chunks.len();
+                self.id_to_idx.insert(chunk.id, idx);
+                self.chunks.push(chunk);
+                self.embeddings.push(embedding);
+                self.norms.push(0.0);
+                idx
+            }
+        };
+        self.norms[idx] = norm(&self.embeddings.get(idx));
+        Ok(())
     }
 
+    /// Removes a chunk in O(1) by swapping the last entry into its slot, so the
+    /// position of that last chunk changes.
+    pub fn remove(&mut self, id: &ChunkID) -> Option<(Chunk, Vec<f32>)> {
+        let idx = self.id_to_idx.remove(id)?;
+        self.bm25.take();
+        let chunk = self.chunks.swap_remove(idx);
+        let embedding = self.embeddings.swap_remove(idx);
+        self.norms.swap_remove(idx);
+        if let Some(moved) = self.chunks.get(idx) {
+            self.id_to_idx.insert(moved.id, idx);
+        }
+        Some((chunk, embedding))
+    }
+
+    /// Replaces the indexed chunks with `new_chunks`, typically a fresh chunking
+    /// of the same sources. A chunk whose ID is already indexed is unchanged: it
+    /// keeps its embedding (its metadata is refreshed). Chunk IDs also hash the
+    /// chunk's offset and its document's ID, which changes with any edit under
+    /// [`IdScheme::PathAndContent`](crate::document::IdScheme::PathAndContent),
+    /// so an edit renames untouched chunks too; those take the embedding of the
+    /// dropped chunk with the same text instead. Chunks missing from
+    /// `new_chunks` are removed and the rest are embedded in a single call to
+    /// `embedder`; pass the [`Embedder`](crate::embedding::Embedder) the index
+    /// was built with so they are prefixed and normalized like the others. If
+    /// embedding fails or yields vectors of the wrong dimension, the index is
+    /// left untouched. Positions of surviving chunks may change, as with
+    /// [`remove`](Self::remove).
+    pub fn update(
+        &mut self,
+        new_chunks: Vec<Chunk>,
+        embedder: &impl Embed,
+    ) -> Result<UpdateReport> {
+        let mut seen = HashSet::with_capacity(new_chunks.len());
+        let (mut kept, mut fresh) = (vec![], vec![]);
+        for chunk in new_chunks {
+            if !seen.insert(chunk.id) {
+                continue;
+            }
+            if self.id_to_idx.contains_key(&chunk.id) {
+                kept.push(chunk);
+            } else {
+                fresh.push(chunk);
+            }
+        }
+        let gone: Vec<ChunkID> = self
+            .chunks
+            .iter()
+            .map(|c| c.id)
+            .filter(|id| !seen.contains(id))
+            .collect();
+
+        // embeddings depend only on the text, so a renamed chunk reuses its old one
+        let by_text: HashMap<&str, usize> = gone
+            .iter()
+            .map(|id| self.id_to_idx[id])
+            .map(|idx| (self.chunks[idx].text.as_str(), idx))
+            .collect();
+        let (mut moved, mut added) = (vec![], vec![]);
+        for chunk in fresh {
+            match by_text.get(chunk.text.as_str()) {
+                Some(&idx) => {
+                    let embedding = self.embeddings.get(idx).into_owned();
+                    moved.push((chunk, embedding));
+                }
+                None => added.push(chunk),
+            }
+        }
+
+        let texts: Vec<&str> = added.iter().map(|c| c.text.as_str()).collect();
+        let embeddings = if texts.is_empty() {
+            vec![]
+        } else {
+            embedder.embed(&texts)?
+        };
+        if embeddings.len() != added.len() {
+            return Err(RAGError::Embedding(format!(
+                "backend returned {} embeddings for {} texts",
+                embeddings.len(),
+                added.len()
+            )));
+        }
+        let expected = self.dim.or(embeddings.first().map(Vec::len));
+        if let Some(expected) = expected
+            && let Some(ragged) = embeddings.iter().find(|e| e.len() != expected)
+        {
+            return Err(RAGError::DimensionMismatch {
+                expected,
+                got: ragged.len(),
+            });
+        }
+
+        for id in &gone {
+            self.remove(id);
+        }
+
+        let report = UpdateReport {
+            added: added.len(),
+            removed: gone.len(),
+            kept: kept.len() + moved.len(),
+        };
+        self.bm25.take();
+        for chunk in kept {
+            let idx = self.id_to_idx[&chunk.id];
+            self.chunks[idx] = chunk;
+        }
+        for (chunk, embedding) in moved.into_iter().chain(added.into_iter().zip(embeddings)) {
+            self.add(chunk, embedding)?;
+        }
+        Ok(report)
+    }
+
+    /// Like [`search`](Self::search), but identifies results by [`ChunkID`],
+    /// which stays valid when the index is modified or rebuilt.
+    pub fn search_ids(&self, query: &[f32], k: usize) -> Result<Vec<(ChunkID, f32)>> {
+        Ok(self
+            .search(query, k)?
+            .into_iter()
+            .map(|r| (r.chunk_id, r.score))
+            .collect())
+    }
+
+    pub fn get(&self, id: &ChunkID) -> Option<&Chunk> {
+        self.id_to_idx.get(id).map(|&idx| &self.chunks[idx])
+    }
+
+    /// Looks a chunk up by position. Positions shift whenever chunks are added
+    /// or removed, so don't hold on to them; prefer [`get`](Self::get).
     pub fn retrieve(&self, idx: usize) -> &Chunk {
         &self.chunks[idx]
     }
+
+    /// The chunk at `idx` together with up to `window` chunks on either side of
+    /// it from the same document, in document order (see [`Chunk::doc_order`]).
+    pub fn retrieve_with_neighbors(&self, idx: usize, window: usize) -> Vec<&Chunk> {
+        let target = &self.chunks[idx];
+        let mut siblings: Vec<&Chunk> = self
+            .chunks
+            .iter()
+            .filter(|c| c.doc_id == target.doc_id)
+            .collect();
+        siblings.sort_by_key(|c| c.doc_order);
+
+        let pos = siblings
+            .iter()
+            .position(|c| c.id == target.id)
+            .expect("a chunk is its own sibling");
+        let end = (pos + window + 1).min(siblings.len());
+        siblings[pos.saturating_sub(window)..end].to_vec()
+    }
+
+    /// Groups search `results` by document into ready-to-paste excerpts,
+    /// documents ordered by their best result. Chunks nested in another
+    /// retrieved chunk are dropped. Documents are filled in order until
+    /// `max_chars` characters are used, so the last one may be cut short and
+    /// later ones left out.
+    pub fn assemble_context(
+        &self,
+        results: &[(usize, f32)],
+        max_chars: usize,
+    ) -> Vec<DocumentContext> {
+        let mut contexts: Vec<DocumentContext> = Vec::new();
+        let mut by_doc: HashMap<DocumentID, usize> = HashMap::new();
+        for &(idx, score) in results {
+            let chunk = &self.chunks[idx];
+            let slot = *by_doc.entry(chunk.doc_id).or_insert_with(|| {
+                contexts.push(DocumentContext {
+                    doc_id: chunk.doc_id,
+                    path: chunk.path.clone(),
+                    score,
+                    chunks: Vec::new(),
+                    text: String::new(),
+                });
+                contexts.len() - 1
+            });
+            let context = &mut contexts[slot];
+            if self.metric.rank(score, context.score).is_lt() {
+                context.score = score;
+            }
+            context.chunks.push(idx);
+        }
+
+        let mut budget = max_chars;
+        contexts.retain_mut(|context| {
+            if budget == 0 {
+                return false;
+            }
+            context.chunks.sort_by_key(|&idx| {
+                let c = &self.chunks[idx];
+                (c.start_byte, std::cmp::Reverse(c.end_byte))
+            });
+            context.chunks.dedup();
+            let mut kept: Vec<usize> = Vec::with_capacity(context.chunks.len());
+            for &idx in &context.chunks {
+                let chunk = &self.chunks[idx];
+                let Some(prev) = kept.last().map(|&p| &self.chunks[p]) else {
+                    context.text.push_str(&chunk.text);
+                    kept.push(idx);
+                    continue;
+                };
+                if chunk.end_byte <= prev.end_byte {
+                    continue; // nested in the previous chunk
+                }
+                let overlap = prev.end_byte.saturating_sub(chunk.start_byte);
+                let verbatim = chunk.text.len() == chunk.end_byte - chunk.start_byte;
+                if overlap > 0 && verbatim && chunk.text.is_char_boundary(overlap) {
+                    context.text.push_str(&chunk.text[overlap..]);
+                } else {
+                    match chunk.start_line.saturating_sub(prev.end_line) {
+                        gap @ 0..=2 => context.text.push_str(&"\n".repeat(gap.max(1))),
+                        _ => context.text.push_str("\n…\n"),
+                    }
+                    context.text.push_str(&chunk.text);
+                }
+                kept.push(idx);
+            }
+            context.chunks = kept;
+
+            if let Some((cut, _)) = context.text.char_indices().nth(budget) {
+                context.text.truncate(cut);
+                budget = 0;
+            } else {
+                budget -= context.text.chars().count();
+            }
+            true
+        });
+        contexts
+    }
+
+    /// Re-slices chunk `idx` out of its document's original text using the
+    /// recorded byte offsets, which also recovers any comments or bodies
+    /// chunking stripped from it. `None` if `source` isn't the text the chunk
+    /// came from, e.g. a file edited since it was indexed: too short for the
+    /// offsets, split mid-character by them, or holding other text there.
+    pub fn chunk_text_from_source<'s>(&self, idx: usize, source: &'s str) -> Option<&'s str> {
+        let chunk = self.chunks.get(idx)?;
+        let text = source.get(chunk.start_byte..chunk.end_byte)?;
+        is_edit_of(&chunk.text, text).then_some(text)
+    }
+
+    /// The stored embedding at `idx`; borrowed unless the index is
+    /// [`quantized`](Self::quantized), in which case it's dequantized.
+    pub fn embedding(&self, idx: usize) -> Cow<'_, [f32]> {
+        self.embeddings.get(idx)
+    }
+
+    /// Every stored embedding in position order, as with [`embedding`](Self::embedding).
+    pub fn embeddings(&self) -> impl ExactSizeIterator<Item = Cow<'_, [f32]>> {
+        (0..self.embeddings.len()).map(|idx| self.embeddings.get(idx))
+    }
+
+    pub fn norms(&self) -> &[f32] {
+        &self.norms
+    }
+
+    pub fn id_to_idx(&self) -> &HashMap<ChunkID, usize> {
+        &self.id_to_idx
+    }
+
+    /// One entry per document with chunks in the index, in order of each
+    /// document's first chunk. A file missing from here was never collected,
+    /// produced no chunks, or had all of them excluded.
+    pub fn manifest(&self) -> Vec<DocumentEntry> {
+        let mut entries: Vec<DocumentEntry> = Vec::new();
+        let mut positions: HashMap<DocumentID, usize> = HashMap::new();
+        for chunk in &self.chunks {
+            let pos = *positions.entry(chunk.doc_id).or_insert_with(|| {
+                entries.push(DocumentEntry {
+                    doc_id: chunk.doc_id,
+                    path: chunk.path.clone(),
+                    chunk_count: 0,
+                    total_chars: 0,
+                    size: None,
+                    language: None,
+                });
+                entries.len() - 1
+            });
+            entries[pos].chunk_count += 1;
+            entries[pos].total_chars += chunk.char_count;
+        }
+        entries
+    }
+
+    /// Like [`manifest`](Self::manifest), filling in size and language from
+    /// `docs`. Documents without chunks are listed too, with a `chunk_count`
+    /// of zero, after the indexed ones.
+    pub fn manifest_with_documents(&self, docs: &[Document]) -> Vec<DocumentEntry> {
+        let mut entries = self.manifest();
+        let mut positions: HashMap<DocumentID, usize> = entries
+            .iter()
+            .enumerate()
+            .map(|(pos, e)| (e.doc_id, pos))
+            .collect();
+        for doc in docs {
+            let pos = *positions.entry(doc.id).or_insert_with(|| {
+                entries.push(DocumentEntry {
+                    doc_id: doc.id,
+                    path: doc.path.clone(),
+                    chunk_count: 0,
+                    total_chars: 0,
+                    size: None,
+                    language: None,
+                });
+                entries.len() - 1
+            });
+            entries[pos].size = Some(doc.size);
+            entries[pos].language = Some(doc.syntax().to_string()).filter(|l| !l.is_empty());
+        }
+        entries
+    }
+
+    /// Checks that every chunk has exactly one embedding, the embeddings share
+    /// one dimension and hold only finite values, and the ID map resolves each
+    /// chunk ID to that chunk's position and nothing else. Meant for indexes
+    /// that were loaded from disk or edited through [`chunks`](Self::chunks),
+    /// where a mismatch would otherwise surface as wrong results or a panic at
+    /// query time.
+    pub fn validate(&self) -> Result<()> {
+        if self.chunks.len() != self.embeddings.len() || self.norms.len() != self.chunks.len() {
+            return Err(RAGError::CountMismatch {
+                chunks: self.chunks.len(),
+                embeddings: self.embeddings.len(),
+            });
+        }
+
+        for (idx, embedding) in self.embeddings().enumerate() {
+            if let Some(expected) = self.dim
+                && embedding.len() != expected
+            {
+                return Err(RAGError::DimensionMismatch {
+                    expected,
+                    got: embedding.len(),
+                });
+            }
+            if !embedding.iter().all(|x| x.is_finite()) {
+                return Err(RAGError::NonFiniteEmbedding { idx });
+            }
+        }
+
+        for (idx, chunk) in self.chunks.iter().enumerate() {
+            if self.id_to_idx.get(&chunk.id) != Some(&idx) {
+                return Err(RAGError::IdMapMismatch { idx });
+            }
+        }
+        // Every chunk maps to itself, so any extra entry is stale
+        if let Some(&idx) = self
+            .id_to_idx
+            .iter()
+            .find(|&(id, &idx)| self.chunks.get(idx).is_none_or(|c| c.id != *id))
+            .map(|(_, idx)| idx)
+        {
+            return Err(RAGError::IdMapMismatch { idx });
+        }
+        Ok(())
+    }
+
+    /// Writes the chunks, their embeddings and the [`EmbeddingMeta`] to
+    /// `path`; the search structures are rebuilt by [`Index::load`].
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let dim = self.dim.unwrap_or(0);
+        let mut out = Vec::new();
+        put_header(&mut out, INDEX_MAGIC, INDEX_FORMAT_VERSION);
+        out.push(self.metric.to_byte());
+        put_u64(&mut out, dim);
+        match &self.meta {
+            Some(meta) => {
+                out.push(1);
+                put_str(&mut out, &meta.model_id);
+                out.push(meta.normalized as u8);
+            }
+            None => out.push(0),
+        }
+        put_u64(&mut out, self.chunks.len());
+        for chunk in &self.chunks {
+            put_chunk(&mut out, chunk);
+        }
+        out.resize(out.len().next_multiple_of(size_of::<f32>()), 0);
+        for embedding in self.embeddings() {
+            put_f32s(&mut out, &embedding);
+        }
+
+        write_file(path, &out)
+    }
+
+    pub fn load(path: &Path) -> Result<Index> {
+        let bytes = read_file(path)?;
+        let (metric, dim, meta, chunks, offset) = read_index_prefix(&bytes, path)?;
+
+        let mut reader = Reader::new(&bytes[offset..]);
+        let embeddings: Vec<Vec<f32>> = (0..chunks.len())
+            .map(|_| reader.f32s(dim))
+            .collect::<Result<_>>()?;
+
+        let mut index = Index::new_with_metric(chunks, embeddings, metric)?;
+        // kept by an index whose chunks were all removed
+        index.dim = (dim > 0).then_some(dim);
+        index.meta = meta;
+        Ok(index)
+    }
+
+    /// Like [`load`](Self::load), but memory-maps the file and searches the
+    /// embeddings in place instead of copying them onto the heap; only the
+    /// chunks are deserialized. Adding or removing chunks copies the
+    /// embeddings into memory first.
+    ///
+    /// The file must not be modified while the index is alive; the map would
+    /// change underneath it.
+    #[cfg(feature = "mmap")]
+    pub fn load_mmap(path: &Path) -> Result<Index> {
+        let file = std::fs::File::open(path)
+            .map_err(|e| RAGError::Deserialization(format!("{}: {}", path.display(), e)))?;
+        // SAFETY: the caller keeps the file unmodified for the lifetime of the index
+        let map = unsafe { memmap2::Mmap::map(&file) }
+            .map_err(|e| RAGError::Deserialization(format!("{}: {}", path.display(), e)))?;
+        let (metric, dim, meta, chunks, offset) = read_index_prefix(&map, path)?;
+
+        let count = chunks.len();
+        let store = Store::mapped(map, offset, dim, count)
+            .map_err(|e| RAGError::Deserialization(format!("{}: {}", path.display(), e)))?;
+        let mut index = Index::from_store(chunks, store, (dim > 0).then_some(dim), metric);
+        index.meta = meta;
+        Ok(index)
+    }
+}
+
+// Whether chunking could have made `edited` from `source`. It only ever drops text
+// (comments, whitespace), joins merged chunks with blank lines and elides bodies to `...`,
+// so what remains without whitespace or `...` appears in `source` in the same order.
+fn is_edit_of(edited: &str, source: &str) -> bool {
+    let mut source = source.chars();
+    edited
+        .split("...")
+        .flat_map(str::chars)
+        .filter(|c| !c.is_whitespace())
+        .all(|c| source.any(|s| s == c))
+}
+
+type IndexPrefix = (Metric, usize, Option<EmbeddingMeta>, Vec<Chunk>, usize);
+
+// Reads everything before the embeddings and returns the metric, the dimension, the
+// embedding meta, the chunks and the offset at which the embeddings start
+fn read_index_prefix(bytes: &[u8], path: &Path) -> Result<IndexPrefix> {
+    let mut reader = Reader::new(bytes);
+    reader
+        .header(INDEX_MAGIC, INDEX_FORMAT_VERSION, "index")
+        .map_err(|e| RAGError::Deserialization(format!("{}: {}", path.display(), e)))?;
+
+    let metric = Metric::from_byte(reader.take(1)?[0])?;
+    let dim = reader.usize()?;
+    let meta = if reader.flag()? {
+        Some(EmbeddingMeta {
+            model_id: reader.string()?,
+            dim,
+            normalized: reader.flag()?,
+        })
+    } else {
+        None
+    };
+    let count = reader.usize()?;
+    // an empty index is saved with the dimension it had, or 0 if it never held an embedding
+    if count > 0 && dim == 0 {
+        return Err(RAGError::EmptyEmbeddings);
+    }
+    // checked up front so a corrupt count can't drive a huge allocation
+    let expected = count
+        .checked_mul(dim)
+        .and_then(|n| n.checked_mul(size_of::<f32>()))
+        .filter(|&n| n <= reader.remaining())
+        .ok_or_else(|| {
+            RAGError::Deserialization(format!(
+                "{}: too short for {count} embeddings of dimension {dim}",
+                path.display()
+            ))
+        })?;
+
+    let chunks = (0..count)
+        .map(|_| read_chunk(&mut reader))
+        .collect::<Result<Vec<_>>>()?;
+    let offset = (bytes.len() - reader.remaining()).next_multiple_of(size_of::<f32>());
+    if bytes.len().checked_sub(offset) != Some(expected) {
+        return Err(RAGError::Deserialization(format!(
+            "{}: expected {expected} bytes of embeddings, found {}",
+            path.display(),
+            bytes.len().saturating_sub(offset)
+        )));
+    }
+    Ok((metric, dim, meta, chunks, offset))
+}
+
+fn put_chunk(out: &mut Vec<u8>, chunk: &Chunk) {
+    out.extend_from_slice(&chunk.id);
+    out.extend_from_slice(&chunk.doc_id);
+    put_str(out, &chunk.path);
+    put_str(out, &chunk.text);
+    put_kind(out, &chunk.kind);
+    put_str(out, &chunk.raw_kind);
+    for value in [
+        chunk.char_count,
+        chunk.start_line,
+        chunk.end_line,
+        chunk.start_byte,
+        chunk.end_byte,
+        chunk.doc_order,
+    ] {
+        put_u64(out, value);
+    }
+    match &chunk.parent_id {
+        Some(parent) => {
+            out.push(1);
+            out.extend_from_slice(parent);
+        }
+        None => out.push(0),
+    }
+    match chunk.token_count {
+        Some(tokens) => {
+            out.push(1);
+            put_u64(out, tokens);
+        }
+        None => out.push(0),
+    }
+}
+
+// A tag byte per variant; `Other` is followed by its name
+fn put_kind(out: &mut Vec<u8>, kind: &ChunkKind) {
+    let tag = match kind {
+        ChunkKind::Function => 0,
+        ChunkKind::Struct => 1,
+        ChunkKind::Class => 2,
+        ChunkKind::Enum => 3,
+        ChunkKind::Trait => 4,
+        ChunkKind::Module => 5,
+        ChunkKind::Paragraph => 6,
+        ChunkKind::WholeDocument => 7,
+        ChunkKind::Other(_) => 8,
+    };
+    out.push(tag);
+    if let ChunkKind::Other(name) = kind {
+        put_str(out, name);
+    }
+}
+
+fn read_kind(reader: &mut Reader) -> Result<ChunkKind> {
+    Ok(match reader.take(1)?[0] {
+        0 => ChunkKind::Function,
+        1 => ChunkKind::Struct,
+        2 => ChunkKind::Class,
+        3 => ChunkKind::Enum,
+        4 => ChunkKind::Trait,
+        5 => ChunkKind::Module,
+        6 => ChunkKind::Paragraph,
+        7 => ChunkKind::WholeDocument,
+        8 => ChunkKind::Other(reader.string()?),
+        other => {
+            return Err(RAGError::Deserialization(format!(
+                "unknown chunk kind tag {other}"
+            )));
+        }
+    })
+}
+
+fn read_chunk(reader: &mut Reader) -> Result<Chunk> {
+    Ok(Chunk {
+        id: reader.array()?,
+        doc_id: reader.array()?,
+        path: reader.string()?,
+        text: reader.string()?,
+        kind: read_kind(reader)?,
+        raw_kind: Cow::Owned(reader.string()?),
+        char_count: reader.usize()?,
+        start_line: reader.usize()?,
+        end_line: reader.usize()?,
+        start_byte: reader.usize()?,
+        end_byte: reader.usize()?,
+        doc_order: reader.usize()?,
+        parent_id: if reader.flag()? {
+            Some(reader.array()?)
+        } else {
+            None
+        },
+        token_count: if reader.flag()? {
+            Some(reader.usize()?)
+        } else {
+            None
+        },
+    })
 }
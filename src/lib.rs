@@ -1,7 +1,17 @@
+#[cfg(feature = "hnsw")]
+pub mod ann;
+mod bm25;
 pub mod chunking;
+mod codec;
 pub mod document;
 pub mod embedding;
 pub mod error;
+mod id_hash;
 pub mod indexing;
+pub mod pipeline;
+#[cfg(feature = "serde")]
+mod serde_hex;
+mod store;
+pub mod tokenizer;
 pub mod util;
 pub use error::{RAGError, Result};
@@ -0,0 +1,96 @@
+use std::path::PathBuf;
+
+use crate::Result;
+use crate::chunking::Chunker;
+use crate::document::{DocumentCollector, IngestWarning};
+use crate::embedding::{Embed, Embedder, FastEmbed};
+use crate::indexing::{Index, Metric};
+
+type MakeEmbedder<E> = Box<dyn FnOnce() -> Result<Embedder<E>>>;
+
+/// Collects documents under a root, chunks and embeds them, and returns a
+/// ready-to-search [`Index`]:
+///
+/// ```no_run
+/// # fn main() -> wubraglib::Result<()> {
+/// let index = wubraglib::pipeline::RagPipeline::new().root("src").build()?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Every stage reports failures as a [`RAGError`](crate::RAGError). Without an
+/// explicit [`embedder`](Self::embedder) the bundled model is loaded when
+/// [`build`](Self::build) runs, so a missing model is an error rather than a panic.
+pub struct RagPipeline<E: Embed = FastEmbed> {
+    collector: DocumentCollector,
+    chunker: Chunker,
+    embedder: MakeEmbedder<E>,
+    metric: Metric,
+}
+
+impl Default for RagPipeline {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RagPipeline {
+    /// A pipeline over the current directory with default chunking and the bundled model.
+    pub fn new() -> Self {
+        Self {
+            collector: DocumentCollector::new("."),
+            chunker: Chunker::new(),
+            embedder: Box::new(Embedder::try_new),
+            metric: Metric::default(),
+        }
+    }
+}
+
+impl<E: Embed + 'static> RagPipeline<E> {
+    /// Collect documents under `root` with default collector settings.
+    pub fn root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.collector = DocumentCollector::new(root);
+        self
+    }
+
+    /// Collect documents with a fully configured collector; replaces [`root`](Self::root).
+    pub fn collector(mut self, collector: DocumentCollector) -> Self {
+        self.collector = collector;
+        self
+    }
+
+    pub fn chunker(mut self, chunker: Chunker) -> Self {
+        self.chunker = chunker;
+        self
+    }
+
+    pub fn embedder<F: Embed + 'static>(self, embedder: Embedder<F>) -> RagPipeline<F> {
+        RagPipeline {
+            collector: self.collector,
+            chunker: self.chunker,
+            embedder: Box::new(move || Ok(embedder)),
+            metric: self.metric,
+        }
+    }
+
+    pub fn metric(mut self, metric: Metric) -> Self {
+        self.metric = metric;
+        self
+    }
+
+    pub fn build(self) -> Result<Index> {
+        Ok(self.build_with_warnings()?.0)
+    }
+
+    /// Like [`build`](Self::build), but also returns the files that were
+    /// skipped while collecting, for callers that show them to a user.
+    pub fn build_with_warnings(self) -> Result<(Index, Vec<IngestWarning>)> {
+        let (docs, warnings) = self.collector.collect_with_warnings()?;
+        let (chunks, _) = self.chunker.chunk_all_documents(&docs)?;
+        let embedder = (self.embedder)()?;
+        let embeddings = embedder.embed_chunks(&chunks)?;
+        let index = Index::new_with_metric(chunks, embeddings, self.metric)?
+            .with_embedding_meta(embedder.meta())?;
+        Ok((index, warnings))
+    }
+}
@@ -0,0 +1,53 @@
+//! Serializes 32-byte IDs as lowercase hex strings in human-readable formats
+//! (JSON, TOML) and as raw bytes everywhere else.
+
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+pub fn serialize<S: Serializer>(id: &[u8; 32], serializer: S) -> Result<S::Ok, S::Error> {
+    if serializer.is_human_readable() {
+        let hex: String = id.iter().map(|b| format!("{b:02x}")).collect();
+        serializer.serialize_str(&hex)
+    } else {
+        id.serialize(serializer)
+    }
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[u8; 32], D::Error> {
+    if !deserializer.is_human_readable() {
+        return <[u8; 32]>::deserialize(deserializer);
+    }
+    let hex = String::deserialize(deserializer)?;
+    if hex.len() != 64 || !hex.is_ascii() {
+        return Err(D::Error::custom(format!(
+            "expected 64 hex characters, got {:?}",
+            hex
+        )));
+    }
+    let mut id = [0u8; 32];
+    for (byte, pair) in id.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        let pair = std::str::from_utf8(pair).map_err(D::Error::custom)?;
+        *byte = u8::from_str_radix(pair, 16).map_err(D::Error::custom)?;
+    }
+    Ok(id)
+}
+
+#[derive(Serialize, Deserialize)]
+struct Hex(#[serde(with = "super::serde_hex")] [u8; 32]);
+
+pub mod option {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        id: &Option<[u8; 32]>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        id.map(Hex).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<[u8; 32]>, D::Error> {
+        Ok(Option::<Hex>::deserialize(deserializer)?.map(|hex| hex.0))
+    }
+}
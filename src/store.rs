@@ -0,0 +1,270 @@
+//! Layouts for the embeddings behind an [`Index`](crate::indexing::Index).
+
+use std::borrow::Cow;
+
+use crate::indexing::QuantMode;
+use crate::util::{dot, dot_i8};
+
+pub(crate) enum Store {
+    F32(Vec<Vec<f32>>),
+    Int8(Int8Store),
+    #[cfg(feature = "mmap")]
+    Mapped(MappedStore),
+    External(Box<dyn VectorStore>),
+}
+
+/// Embeddings an [`Index`](crate::indexing::Index) reads by position instead of
+/// owning, for stores that live outside the heap such as a file read on demand.
+/// See [`Index::with_vector_store`](crate::indexing::Index::with_vector_store).
+pub trait VectorStore: Send + Sync {
+    /// Number of embeddings.
+    fn len(&self) -> usize;
+
+    /// Embedding `idx`, borrowed when the store holds it as `f32`s already.
+    fn get(&self, idx: usize) -> Cow<'_, [f32]>;
+
+    /// Length of every embedding.
+    fn dim(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl VectorStore for Vec<Vec<f32>> {
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+
+    fn get(&self, idx: usize) -> Cow<'_, [f32]> {
+        Cow::Borrowed(&self[idx])
+    }
+
+    fn dim(&self) -> usize {
+        self.first().map_or(0, Vec::len)
+    }
+}
+
+// One flat row of `dim` codes per embedding; embedding `i` is `codes[i] * scales[i]`
+pub(crate) struct Int8Store {
+    dim: usize,
+    codes: Vec<i8>,
+    scales: Vec<f32>,
+}
+
+// `count` embeddings of `dim` little-endian floats starting `offset` bytes into a file map
+#[cfg(feature = "mmap")]
+pub(crate) struct MappedStore {
+    map: memmap2::Mmap,
+    offset: usize,
+    dim: usize,
+    count: usize,
+}
+
+impl Store {
+    pub(crate) fn new(embeddings: Vec<Vec<f32>>, mode: Option<QuantMode>) -> Self {
+        match mode {
+            None => Store::F32(embeddings),
+            Some(QuantMode::Int8) => {
+                let dim = embeddings.first().map_or(0, Vec::len);
+                let mut store = Int8Store {
+                    dim,
+                    codes: Vec::with_capacity(dim * embeddings.len()),
+                    scales: Vec::with_capacity(embeddings.len()),
+                };
+                for embedding in &embeddings {
+                    store.push(embedding);
+                }
+                Store::Int8(store)
+            }
+        }
+    }
+
+    // Validates that the map holds exactly the embeddings region and that it can be viewed
+    // as `f32`s in place
+    #[cfg(feature = "mmap")]
+    pub(crate) fn mapped(
+        map: memmap2::Mmap,
+        offset: usize,
+        dim: usize,
+        count: usize,
+    ) -> std::result::Result<Self, String> {
+        if cfg!(target_endian = "big") {
+            return Err("mapped embeddings need a little-endian host".to_string());
+        }
+        let len = dim * count * size_of::<f32>();
+        match map.get(offset..) {
+            Some(region) if region.len() == len => {
+                if region.as_ptr().align_offset(align_of::<f32>()) != 0 {
+                    return Err(format!("embeddings at offset {offset} are misaligned"));
+                }
+            }
+            _ => {
+                return Err(format!(
+                    "expected {len} bytes of embeddings at offset {offset}"
+                ));
+            }
+        }
+        Ok(Store::Mapped(MappedStore {
+            map,
+            offset,
+            dim,
+            count,
+        }))
+    }
+
+    pub(crate) fn mode(&self) -> Option<QuantMode> {
+        match self {
+            Store::F32(_) => None,
+            Store::Int8(_) => Some(QuantMode::Int8),
+            #[cfg(feature = "mmap")]
+            Store::Mapped(_) => None,
+            Store::External(_) => None,
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            Store::F32(embeddings) => embeddings.len(),
+            Store::Int8(store) => store.scales.len(),
+            #[cfg(feature = "mmap")]
+            Store::Mapped(store) => store.count,
+            Store::External(store) => store.len(),
+        }
+    }
+
+    pub(crate) fn get(&self, idx: usize) -> Cow<'_, [f32]> {
+        match self {
+            Store::F32(embeddings) => Cow::Borrowed(&embeddings[idx]),
+            Store::Int8(store) => Cow::Owned(store.dequantize(idx)),
+            #[cfg(feature = "mmap")]
+            Store::Mapped(store) => Cow::Borrowed(store.row(idx)),
+            Store::External(store) => store.get(idx),
+        }
+    }
+
+    // Dot product of `query` with embedding `idx`, without materializing a quantized one
+    pub(crate) fn dot(&self, idx: usize, query: &[f32]) -> f32 {
+        match self {
+            Store::F32(embeddings) => dot(query, &embeddings[idx]),
+            Store::Int8(store) => dot_i8(query, store.row(idx)) * store.scales[idx],
+            #[cfg(feature = "mmap")]
+            Store::Mapped(store) => dot(query, store.row(idx)),
+            Store::External(store) => dot(query, &store.get(idx)),
+        }
+    }
+
+    // A mapped file or external store is read-only, so edits first copy its embeddings
+    // onto the heap
+    fn make_mut(&mut self) {
+        #[cfg(feature = "mmap")]
+        if let Store::Mapped(store) = self {
+            let embeddings = (0..store.count)
+                .map(|idx| store.row(idx).to_vec())
+                .collect();
+            *self = Store::F32(embeddings);
+        }
+        if let Store::External(store) = self {
+            let embeddings = (0..store.len())
+                .map(|idx| store.get(idx).into_owned())
+                .collect();
+            *self = Store::F32(embeddings);
+        }
+    }
+
+    pub(crate) fn push(&mut self, embedding: Vec<f32>) {
+        self.make_mut();
+        match self {
+            Store::F32(embeddings) => embeddings.push(embedding),
+            Store::Int8(store) => {
+                // an index built empty learns its dimension from the first embedding
+                if store.scales.is_empty() {
+                    store.dim = embedding.len();
+                }
+                store.push(&embedding);
+            }
+            #[cfg(feature = "mmap")]
+            Store::Mapped(_) => unreachable!("made mutable above"),
+            Store::External(_) => unreachable!("made mutable above"),
+        }
+    }
+
+    pub(crate) fn set(&mut self, idx: usize, embedding: Vec<f32>) {
+        self.make_mut();
+        match self {
+            Store::F32(embeddings) => embeddings[idx] = embedding,
+            Store::Int8(store) => {
+                let (codes, scale) = quantize(&embedding);
+                store.codes[idx * store.dim..(idx + 1) * store.dim].copy_from_slice(&codes);
+                store.scales[idx] = scale;
+            }
+            #[cfg(feature = "mmap")]
+            Store::Mapped(_) => unreachable!("made mutable above"),
+            Store::External(_) => unreachable!("made mutable above"),
+        }
+    }
+
+    // Like `Vec::swap_remove`; a quantized embedding comes back dequantized
+    pub(crate) fn swap_remove(&mut self, idx: usize) -> Vec<f32> {
+        self.make_mut();
+        match self {
+            Store::F32(embeddings) => embeddings.swap_remove(idx),
+            Store::Int8(store) => {
+                let removed = store.dequantize(idx);
+                let last = store.scales.len() - 1;
+                if idx != last {
+                    store.codes.copy_within(last * store.dim.., idx * store.dim);
+                }
+                store.codes.truncate(last * store.dim);
+                store.scales.swap_remove(idx);
+                removed
+            }
+            #[cfg(feature = "mmap")]
+            Store::Mapped(_) => unreachable!("made mutable above"),
+            Store::External(_) => unreachable!("made mutable above"),
+        }
+    }
+}
+
+impl Int8Store {
+    fn row(&self, idx: usize) -> &[i8] {
+        &self.codes[idx * self.dim..(idx + 1) * self.dim]
+    }
+
+    fn dequantize(&self, idx: usize) -> Vec<f32> {
+        let scale = self.scales[idx];
+        self.row(idx).iter().map(|&c| c as f32 * scale).collect()
+    }
+
+    fn push(&mut self, embedding: &[f32]) {
+        let (codes, scale) = quantize(embedding);
+        self.codes.extend_from_slice(&codes);
+        self.scales.push(scale);
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl MappedStore {
+    fn row(&self, idx: usize) -> &[f32] {
+        let region = &self.map[self.offset..];
+        // SAFETY: `Store::mapped` checked that the region is aligned for f32 and holds
+        // `count * dim` of them, and every bit pattern is a valid f32
+        let all: &[f32] =
+            unsafe { std::slice::from_raw_parts(region.as_ptr().cast(), self.count * self.dim) };
+        &all[idx * self.dim..(idx + 1) * self.dim]
+    }
+}
+
+// Symmetric per-vector quantization: the largest magnitude maps to ±127
+fn quantize(embedding: &[f32]) -> (Vec<i8>, f32) {
+    let max = embedding.iter().fold(0.0f32, |m, x| m.max(x.abs()));
+    if max == 0.0 || !max.is_finite() {
+        return (vec![0; embedding.len()], 0.0);
+    }
+    let scale = max / 127.0;
+    let codes = embedding
+        .iter()
+        .map(|x| (x / scale).round().clamp(-127.0, 127.0) as i8)
+        .collect();
+    (codes, scale)
+}
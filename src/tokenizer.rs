@@ -0,0 +1,42 @@
+/// Counts tokens so chunk budgets can track an embedding model's real input limit.
+pub trait TokenCounter: Send + Sync {
+    fn count(&self, text: &str) -> usize;
+}
+
+impl std::fmt::Debug for dyn TokenCounter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("dyn TokenCounter")
+    }
+}
+
+/// Counts whitespace-separated words. Cheap, and a rough lower bound for subword tokenizers.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WhitespaceTokenCounter;
+
+impl TokenCounter for WhitespaceTokenCounter {
+    fn count(&self, text: &str) -> usize {
+        text.split_whitespace().count()
+    }
+}
+
+/// OpenAI's `cl100k_base` BPE via `tiktoken-rs`.
+#[cfg(feature = "tiktoken")]
+pub struct TiktokenCounter {
+    bpe: tiktoken_rs::CoreBPE,
+}
+
+#[cfg(feature = "tiktoken")]
+impl TiktokenCounter {
+    pub fn new() -> crate::Result<Self> {
+        let bpe =
+            tiktoken_rs::cl100k_base().map_err(|e| crate::RAGError::ModelInit(e.to_string()))?;
+        Ok(Self { bpe })
+    }
+}
+
+#[cfg(feature = "tiktoken")]
+impl TokenCounter for TiktokenCounter {
+    fn count(&self, text: &str) -> usize {
+        self.bpe.encode_ordinary(text).len()
+    }
+}
@@ -0,0 +1,92 @@
+//! Small vector helpers shared across the crate.
+
+// Independent partial sums per iteration. Floating-point addition isn't associative, so the
+// compiler only vectorizes the loop when the accumulators are spelled out like this.
+const LANES: usize = 8;
+
+// Dot product over the common prefix of `a` and `b`. Uses 256-bit registers when the CPU has
+// AVX, otherwise whatever the target baseline offers (SSE2, NEON, or scalar code).
+pub(crate) fn dot(a: &[f32], b: &[f32]) -> f32 {
+    dot_dispatch(a, b)
+}
+
+// `dot` against int8 codes, widened to f32 lane by lane
+pub(crate) fn dot_i8(a: &[f32], b: &[i8]) -> f32 {
+    dot_dispatch(a, b)
+}
+
+fn dot_dispatch<T: Copy + Into<f32>>(a: &[f32], b: &[T]) -> f32 {
+    #[cfg(target_arch = "x86_64")]
+    if std::arch::is_x86_feature_detected!("avx") {
+        // SAFETY: the CPU supports AVX, checked just above
+        return unsafe { dot_avx(a, b) };
+    }
+    dot_lanes(a, b)
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx")]
+fn dot_avx<T: Copy + Into<f32>>(a: &[f32], b: &[T]) -> f32 {
+    dot_lanes(a, b)
+}
+
+// Inlined into each caller so it's compiled for that caller's target features
+#[inline(always)]
+fn dot_lanes<T: Copy + Into<f32>>(a: &[f32], b: &[T]) -> f32 {
+    let len = a.len().min(b.len());
+    let (a, b) = (&a[..len], &b[..len]);
+    let (a_chunks, a_tail) = a.as_chunks::<LANES>();
+    let (b_chunks, b_tail) = b.as_chunks::<LANES>();
+
+    let mut acc = [0.0f32; LANES];
+    for (x, y) in a_chunks.iter().zip(b_chunks) {
+        for lane in 0..LANES {
+            acc[lane] += x[lane] * y[lane].into();
+        }
+    }
+    let tail: f32 = a_tail.iter().zip(b_tail).map(|(x, &y)| x * y.into()).sum();
+    acc.iter().sum::<f32>() + tail
+}
+
+pub(crate) fn norm(v: &[f32]) -> f32 {
+    dot(v, v).sqrt()
+}
+
+/// Cosine similarity of `a` and `b`, in `[-1, 1]`.
+///
+/// A zero vector has no direction, so any comparison involving one scores
+/// `0.0` rather than NaN. Vectors of different lengths are compared over
+/// their common prefix: the extra components of the longer one are ignored.
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let len = a.len().min(b.len());
+    let (a, b) = (&a[..len], &b[..len]);
+    cosine_with_norms(a, b, norm(a), norm(b))
+}
+
+// `cosine_similarity` with the L2 norms of `a` and `b` precomputed
+pub(crate) fn cosine_with_norms(a: &[f32], b: &[f32], na: f32, nb: f32) -> f32 {
+    cosine_from_dot(dot(a, b), na, nb)
+}
+
+// Zero-length vectors have no direction; score them 0 instead of NaN
+pub(crate) fn cosine_from_dot(dot: f32, na: f32, nb: f32) -> f32 {
+    if na == 0.0 || nb == 0.0 {
+        return 0.0;
+    }
+    dot / (na * nb)
+}
+
+/// Scales `v` to unit length in place. Zero vectors have no direction and are
+/// left untouched.
+pub fn l2_normalize(v: &mut [f32]) {
+    let norm = dot(v, v).sqrt();
+    if norm > 0.0 {
+        v.iter_mut().for_each(|x| *x /= norm);
+    }
+}
+
+/// Cosine similarity of two vectors already scaled by [`l2_normalize`],
+/// which reduces to their dot product.
+pub fn cosine_normalized(a: &[f32], b: &[f32]) -> f32 {
+    dot(a, b)
+}
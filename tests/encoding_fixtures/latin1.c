@@ -0,0 +1,2 @@
+/* Caf crme: tat du systme, dj rgl */
+int etat_systeme(void) { return 0; }
@@ -0,0 +1,33 @@
+using System;
+
+namespace Acme.Billing
+{
+    public interface IInvoice
+    {
+        decimal Total();
+    }
+
+    public struct Money
+    {
+        public decimal Amount;
+
+        public Money(decimal amount) { Amount = amount; }
+    }
+
+    public class Invoice : IInvoice
+    {
+        private readonly decimal total;
+
+        public Invoice(decimal total)
+        {
+            this.total = total;
+        }
+
+        public decimal Total()
+        {
+            return total;
+        }
+
+        public override string ToString() => $"Invoice({total})";
+    }
+}
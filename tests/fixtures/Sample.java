@@ -0,0 +1,17 @@
+package store;
+
+import java.util.HashMap;
+
+public class Store {
+    private final HashMap<String, String> items = new HashMap<>();
+
+    public Store() {}
+
+    public void insert(String key, String value) {
+        items.put(key, value);
+    }
+}
+
+interface Renderer {
+    String render(Store store);
+}
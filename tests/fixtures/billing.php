@@ -0,0 +1,25 @@
+<?php
+
+namespace Acme\Billing;
+
+interface Invoice
+{
+    public function total(): float;
+}
+
+function format_amount(float $amount): string
+{
+    return number_format($amount, 2);
+}
+
+class Order implements Invoice
+{
+    public function __construct(private float $amount)
+    {
+    }
+
+    public function total(): float
+    {
+        return $this->amount;
+    }
+}
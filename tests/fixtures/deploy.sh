@@ -0,0 +1,21 @@
+#!/usr/bin/env bash
+set -euo pipefail
+
+# Print a timestamped message to stderr
+log() {
+    echo "[$(date +%H:%M:%S)] $*" >&2
+}
+
+deploy() {
+    local target="$1"
+    log "deploying to $target"
+    rsync -az build/ "$target:/srv/app/"
+}
+
+cat > /tmp/motd <<MOTD
+Deploy in progress.
+
+Please hold off on pushes until it finishes.
+MOTD
+
+deploy "${1:-staging}"
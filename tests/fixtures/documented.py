@@ -0,0 +1,9 @@
+import functools
+
+
+# Caches the parsed result.
+@functools.lru_cache(maxsize=None)
+def parse_header(line):
+    """Parse a `key: value` header line."""
+    key, value = line.split(":", 1)
+    return key.strip(), value.strip()
@@ -0,0 +1,23 @@
+use std::fmt;
+
+// A standalone comment that is separated by a blank line.
+
+/// Parses a `key: value` header line.
+///
+/// Returns `None` when the line has no colon.
+#[inline]
+#[must_use]
+pub fn parse_header(line: &str) -> Option<(&str, &str)> {
+    let (key, value) = line.split_once(':')?;
+    Some((key.trim(), value.trim()))
+}
+
+pub struct Wrapper(u8); // trailing comment
+
+pub fn bare() {}
+
+impl fmt::Display for Wrapper {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
@@ -0,0 +1,22 @@
+#include <cstdint>
+
+namespace geo {
+
+// The declaration and its struct specifier cover nearly the same span
+struct Point {
+    int x;
+    int y;
+} origin;
+
+class Shape {
+public:
+    int area() const {
+        return 0;
+    }
+};
+
+}
+
+int distance(geo::Point a, geo::Point b) {
+    return (a.x - b.x) + (a.y - b.y);
+}
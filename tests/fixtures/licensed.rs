@@ -0,0 +1,28 @@
+// Copyright (c) 2024 The Example Authors.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.
+/// Sums the readings, skipping sensor dropouts.
+pub fn total(readings: &[Option<u32>]) -> u32 {
+    let mut sum = 0; // running total
+    for reading in readings.iter().flatten() {
+        /* old clamping, kept for reference:
+        if *reading > 1000 {
+            continue;
+        }
+        */
+        sum += reading;
+    }
+    sum
+}
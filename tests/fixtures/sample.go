@@ -0,0 +1,20 @@
+package store
+
+import "strings"
+
+type Store struct {
+	items map[string]string
+}
+
+func NewStore() *Store {
+	return &Store{items: map[string]string{}}
+}
+
+func (s *Store) Insert(key, value string) {
+	s.items[key] = value
+}
+
+func ParseHeader(line string) (string, string) {
+	parts := strings.SplitN(line, ":", 2)
+	return strings.TrimSpace(parts[0]), strings.TrimSpace(parts[1])
+}
@@ -0,0 +1,31 @@
+use std::collections::HashMap;
+
+/// A tiny key-value store used by the chunking tests.
+pub struct Store {
+    items: HashMap<String, String>,
+}
+
+impl Store {
+    pub fn new() -> Self {
+        Self {
+            items: HashMap::new(),
+        }
+    }
+
+    pub fn insert(&mut self, key: &str, value: &str) {
+        self.items.insert(key.to_string(), value.to_string());
+    }
+}
+
+pub fn parse_header(line: &str) -> Option<(&str, &str)> {
+    let (key, value) = line.split_once(':')?;
+    Some((key.trim(), value.trim()))
+}
+
+pub fn render(store: &Store) -> String {
+    let mut out = String::new();
+    for (k, v) in &store.items {
+        out.push_str(&format!("{k}: {v}\n"));
+    }
+    out
+}
@@ -0,0 +1,25 @@
+import { readFileSync } from "fs";
+
+export interface Header {
+  key: string;
+  value: string;
+}
+
+export class Store {
+  private items = new Map<string, string>();
+
+  insert(key: string, value: string): void {
+    this.items.set(key, value);
+  }
+}
+
+export function parseHeader(line: string): Header {
+  const [key, value] = line.split(":");
+  return { key: key.trim(), value: value.trim() };
+}
+
+const loadLines = (path: string): string[] => readFileSync(path, "utf8").split("\n");
+
+function render(store: Store): string {
+  return JSON.stringify(store);
+}
@@ -0,0 +1,84 @@
+extern crate wubraglib;
+
+use std::collections::HashSet;
+
+use wubraglib::ann::AnnParams;
+use wubraglib::chunking::*;
+use wubraglib::document::Document;
+use wubraglib::indexing::{Index, Metric};
+
+// Small deterministic LCG so the fixture doesn't need a rand dependency
+fn random_vectors(count: usize, dim: usize, mut seed: u64) -> Vec<Vec<f32>> {
+    let mut next = move || {
+        seed = seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        ((seed >> 33) as f32 / (1u64 << 31) as f32) * 2.0 - 1.0
+    };
+    (0..count)
+        .map(|_| (0..dim).map(|_| next()).collect())
+        .collect()
+}
+
+fn random_index(count: usize, metric: Metric) -> Index {
+    let src: String = (0..count).map(|i| format!("fn f{i}() {{}}\n\n")).collect();
+    let chunks = Chunker::new()
+        .chunk_document(&Document::from_str("lib.rs", src))
+        .unwrap();
+    assert_eq!(chunks.len(), count);
+    Index::new_with_metric(chunks, random_vectors(count, 16, 42), metric).unwrap()
+}
+
+fn recall(index: &Index, params: AnnParams) -> f32 {
+    let ann = index.build_ann(params);
+    assert!(!ann.is_exact());
+    let queries = random_vectors(50, 16, 7);
+    let k = 10;
+    let mut found = 0;
+    for query in &queries {
+        let exact: HashSet<usize> = index
+            .search(query, k)
+            .unwrap()
+            .iter()
+            .map(|r| r.index)
+            .collect();
+        let approx = ann.search(query, k).unwrap();
+        assert!(
+            approx
+                .windows(2)
+                .all(|w| w[0].1 >= w[1].1 || index.metric() == Metric::Euclidean)
+        );
+        found += approx.iter().filter(|r| exact.contains(&r.0)).count();
+    }
+    found as f32 / (queries.len() * k) as f32
+}
+
+#[test]
+fn test_ann_recall_against_exact() {
+    let params = AnnParams {
+        exact_below: 100,
+        ..AnnParams::default()
+    };
+    for metric in [Metric::Cosine, Metric::Euclidean] {
+        let index = random_index(2000, metric);
+        let recall = recall(&index, params.clone());
+        assert!(recall > 0.9, "{metric:?} recall {recall}");
+    }
+}
+
+#[test]
+fn test_small_indexes_fall_back_to_exact() {
+    let index = random_index(50, Metric::Cosine);
+    let ann = index.build_ann(AnnParams::default());
+    assert!(ann.is_exact());
+    let query = &random_vectors(1, 16, 3)[0];
+    assert_eq!(
+        ann.search(query, 5).unwrap(),
+        index
+            .search(query, 5)
+            .unwrap()
+            .iter()
+            .map(|r| (r.index, r.score))
+            .collect::<Vec<_>>()
+    );
+}
@@ -0,0 +1,67 @@
+extern crate wubraglib;
+
+use std::io::Write;
+
+use wubraglib::document::{ArchiveFormat, Document, grab_all_documents_from_archive};
+
+// A tarball of (path, contents) pairs, built in memory
+fn tarball(files: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut builder = tar::Builder::new(Vec::new());
+    for (path, contents) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(1_700_000_000);
+        header.set_cksum();
+        builder.append_data(&mut header, path, *contents).unwrap();
+    }
+    builder.into_inner().unwrap()
+}
+
+#[test]
+fn test_tar_entries_become_documents() {
+    let lib = "pub fn add(a: i32, b: i32) -> i32 {\n    a + b\n}\n";
+    let main = "fn main() {\n    println!(\"{}\", demo::add(1, 2));\n}\n";
+    let bytes = tarball(&[
+        ("./src/lib.rs", lib.as_bytes()),
+        ("src/bin/main.rs", main.as_bytes()),
+        ("logo.png", b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"),
+    ]);
+
+    let docs = grab_all_documents_from_archive(bytes.as_slice(), ArchiveFormat::Tar).unwrap();
+    let paths: Vec<&str> = docs.iter().map(|d| d.path.as_str()).collect();
+    assert_eq!(paths, vec!["src/lib.rs", "src/bin/main.rs"]);
+    assert_eq!(
+        docs[0].id,
+        Document::from_str("src/lib.rs", lib.to_string()).id
+    );
+    assert_eq!(docs[0].ext, "rs");
+    assert_eq!(docs[1].text, main);
+    assert_eq!(docs[1].size, main.len() as u64);
+    assert!(docs[1].modified.is_some());
+
+    let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+    gz.write_all(&bytes).unwrap();
+    let gz = gz.finish().unwrap();
+    let unpacked = grab_all_documents_from_archive(gz.as_slice(), ArchiveFormat::TarGz).unwrap();
+    assert_eq!(unpacked.len(), 2);
+    assert_eq!(unpacked[0].id, docs[0].id);
+
+    assert!(grab_all_documents_from_archive(&b"not a tarball"[..], ArchiveFormat::TarGz).is_err());
+}
+
+#[test]
+fn test_oversized_entry_header_is_not_trusted() {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(u64::MAX >> 1);
+    header.set_mode(0o644);
+    header.set_cksum();
+    let mut builder = tar::Builder::new(Vec::new());
+    builder
+        .append_data(&mut header, "huge.txt", &b"tiny"[..])
+        .unwrap();
+    let bytes = builder.into_inner().unwrap();
+
+    // the entry runs past the end of the archive: an error, not an abort
+    assert!(grab_all_documents_from_archive(bytes.as_slice(), ArchiveFormat::Tar).is_err());
+}
@@ -0,0 +1,64 @@
+extern crate wubraglib;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use wubraglib::chunking::*;
+use wubraglib::document::Document;
+use wubraglib::embedding::{AsyncEmbed, Embedder};
+use wubraglib::*;
+
+// A remote-ish backend: embeds as [byte length, first byte], answers shorter texts
+// sooner, and records how many requests are in flight at once
+#[derive(Default)]
+struct SlowEmbed {
+    in_flight: AtomicUsize,
+    max_in_flight: AtomicUsize,
+}
+
+impl AsyncEmbed for SlowEmbed {
+    async fn embed(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+        let now = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        self.max_in_flight.fetch_max(now, Ordering::SeqCst);
+        let len: usize = texts.iter().map(|t| t.len()).sum();
+        tokio::time::sleep(Duration::from_millis(len as u64)).await;
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+
+        Ok(texts
+            .iter()
+            .map(|t| vec![t.len() as f32, t.bytes().next().unwrap_or(0) as f32])
+            .collect())
+    }
+
+    fn dim(&self) -> usize {
+        2
+    }
+}
+
+#[test]
+fn test_async_embedding_bounds_concurrency_and_keeps_order() {
+    let src = "fn a_long_function_name() {\n    let x = 1;\n}\n\nstruct B;\n\nfn c() {}\n\nenum Dee { E }\n";
+    let chunks = Chunker::new()
+        .chunk_document(&Document::from_str("lib.rs", src.to_string()))
+        .unwrap();
+    assert!(chunks.len() >= 3);
+
+    let embedder = Embedder::with_backend(SlowEmbed::default())
+        .with_batch_size(1)
+        .with_parallel_batches(2)
+        .with_normalize(false);
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .build()
+        .unwrap();
+    let embeddings = runtime
+        .block_on(embedder.embed_chunks_async(&chunks))
+        .unwrap();
+
+    assert_eq!(embeddings.len(), chunks.len());
+    for (chunk, embedding) in chunks.iter().zip(&embeddings) {
+        assert_eq!(embedding[0], chunk.text.len() as f32);
+        assert_eq!(embedding[1], chunk.text.as_bytes()[0] as f32);
+    }
+    assert_eq!(embedder.backend().max_in_flight.load(Ordering::SeqCst), 2);
+}
@@ -1,42 +1,88 @@
 extern crate wubraglib;
 
-use std::{collections::HashSet, path::Path};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
 
+use wubraglib::chunking::Chunker;
+use wubraglib::document::{
+    Document, DocumentCollector, DocumentID, IdScheme, documents_iter, grab_all_documents,
+    grab_changed_documents,
+};
+use wubraglib::embedding::Embedder;
+use wubraglib::indexing::Index;
 use wubraglib::*;
 
+// Builds a throwaway tree under the system temp dir from (relative path, contents) pairs
+fn scratch_tree(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let root = std::env::temp_dir().join(format!("wubraglib-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    for (relative, contents) in files {
+        let path = root.join(relative);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+    root
+}
+
+fn sorted_paths(collector: DocumentCollector) -> Vec<String> {
+    let mut paths: Vec<String> = collector
+        .collect()
+        .unwrap()
+        .into_iter()
+        .map(|d| d.path)
+        .collect();
+    paths.sort();
+    paths
+}
+
 #[test]
 fn test_ids_are_unique() {
-    let mut map: HashSet<u32> = HashSet::new();
-    let docs = grab_all_documents(Path::new("tests/examples/example-rs"));
+    let mut map: HashSet<DocumentID> = HashSet::new();
+    let docs = grab_all_documents(Path::new("tests/fixtures")).unwrap();
+    assert!(!docs.is_empty());
     for doc in &docs {
         assert!(!map.contains(&doc.id));
         map.insert(doc.id);
     }
-    assert!(true);
+}
+#[test]
+fn test_missing_root_is_an_error() {
+    let result = grab_all_documents(Path::new("tests/fixtures/does-not-exist"));
+    assert!(matches!(result, Err(RAGError::FileRead { .. })));
 }
 #[test]
 fn test_chunks() {
-    let docs = grab_all_documents(Path::new("tests/examples/example-rs"));
-    let _ = chunk_all_documents(&docs);
-    assert!(true);
+    let docs = grab_all_documents(Path::new("tests/fixtures")).unwrap();
+    let _ = Chunker::new().chunk_all_documents(&docs).unwrap();
 }
 #[test]
 fn test_run_query_returns_text() {
-    let docs = grab_all_documents(Path::new("tests/examples/example-rs"));
+    let docs = grab_all_documents(Path::new("tests/fixtures")).unwrap();
 
-    let (mut chunks, _id_to_idx) = chunk_all_documents(&docs);
+    let (mut chunks, _id_to_idx) = Chunker::new().chunk_all_documents(&docs).unwrap();
     chunks.truncate(20);
 
-    let embeddings = embed_chunks(&mut chunks);
+    let embeddings = Embedder::new().embed_chunks(&chunks).unwrap();
 
-    let index = Index::new(chunks, embeddings);
+    let index = Index::new(chunks, embeddings).unwrap();
 
-    let embedder = fastembed::TextEmbedding::try_new(fastembed::InitOptions::new(
+    let mut embedder = fastembed::TextEmbedding::try_new(fastembed::InitOptions::new(
         fastembed::EmbeddingModel::AllMiniLML6V2,
     ))
     .expect("failed to init embedder");
+    let query = embedder
+        .embed(vec!["parse a header line"], None)
+        .expect("failed to embed query");
 
-    let results = index.query(embedder, "example-rs", 1);
+    let results: Vec<&str> = index
+        .search(&query[0], 1)
+        .unwrap()
+        .into_iter()
+        .map(|r| index.retrieve(r.index).text.as_str())
+        .collect();
 
     for text in results.iter() {
         println!("{text}\n---\n");
@@ -45,3 +91,336 @@ fn test_run_query_returns_text() {
     assert!(!results.is_empty());
     assert!(results.iter().all(|s| !s.trim().is_empty()));
 }
+#[test]
+fn test_gitignored_directories_are_skipped() {
+    let root = scratch_tree(
+        "gitignore",
+        &[
+            (".gitignore", "build/\n*.log\n"),
+            ("src/main.rs", "fn main() {}\n"),
+            ("src/.ignore", "generated.rs\n"),
+            ("src/generated.rs", "// generated\n"),
+            ("build/out.rs", "fn out() {}\n"),
+            ("debug.log", "noise\n"),
+        ],
+    );
+
+    let paths = sorted_paths(DocumentCollector::new(&root));
+    assert_eq!(paths, vec!["src/main.rs"]);
+
+    let paths = sorted_paths(
+        DocumentCollector::new(&root)
+            .respect_ignore_files(false)
+            .include("**/*.rs")
+            .exclude("build"),
+    );
+    assert_eq!(paths, vec!["src/generated.rs", "src/main.rs"]);
+
+    fs::remove_dir_all(&root).unwrap();
+}
+#[test]
+fn test_files_over_max_bytes_are_skipped() {
+    let root = scratch_tree(
+        "max-bytes",
+        &[
+            ("small.txt", &"a".repeat(100)),
+            ("large.txt", &"a".repeat(101)),
+        ],
+    );
+
+    let paths = sorted_paths(DocumentCollector::new(&root).max_file_bytes(100));
+    assert_eq!(paths, vec!["small.txt"]);
+
+    fs::remove_dir_all(&root).unwrap();
+}
+#[test]
+fn test_binary_files_are_skipped() {
+    let root = scratch_tree(
+        "binary",
+        &[
+            (
+                "lib.rs",
+                "pub fn add(a: i32, b: i32) -> i32 {\n\ta + b\n}\n",
+            ),
+            ("blob.bin", "GIF89a\0\0\u{1}\u{2}valid utf-8 but not text"),
+            ("controls.dat", "\u{1}\u{2}\u{3}\u{4}\u{5}ab"),
+        ],
+    );
+
+    let paths = sorted_paths(DocumentCollector::new(&root));
+    assert_eq!(paths, vec!["lib.rs"]);
+
+    let paths = sorted_paths(DocumentCollector::new(&root).binary_detection(false));
+    assert_eq!(paths, vec!["blob.bin", "controls.dat", "lib.rs"]);
+
+    fs::remove_dir_all(&root).unwrap();
+}
+#[test]
+fn test_single_file_and_in_memory_documents() {
+    let walked = grab_all_documents(Path::new("tests/fixtures")).unwrap();
+    let walked = walked.iter().find(|d| d.path == "sample.rs").unwrap();
+
+    let loaded = Document::from_path(Path::new("tests/fixtures/sample.rs")).unwrap();
+    assert_eq!(loaded.id, walked.id);
+    assert_eq!(loaded.size, walked.size);
+
+    let file_root = grab_all_documents(Path::new("tests/fixtures/sample.rs")).unwrap();
+    assert_eq!(file_root.len(), 1);
+    assert_eq!(file_root[0].id, walked.id);
+
+    let buffer = Document::from_str("sample.rs", walked.text.clone());
+    assert_eq!(buffer.id, walked.id);
+    assert_eq!(buffer.ext, "rs");
+    assert_eq!(buffer.size, walked.text.len() as u64);
+
+    let missing = Document::from_path(Path::new("tests/fixtures/missing.rs"));
+    assert!(matches!(missing, Err(RAGError::FileRead { .. })));
+}
+#[test]
+fn test_only_modified_files_are_reread() {
+    let root = scratch_tree(
+        "changed",
+        &[("a.rs", "fn a() {}\n"), ("b.rs", "fn b() {}\n")],
+    );
+
+    let (first, mtimes) = grab_changed_documents(&root, &HashMap::new()).unwrap();
+    assert_eq!(first.len(), 2);
+    assert!(first.iter().all(|d| d.modified.is_some()));
+    assert_eq!(mtimes.len(), 2);
+
+    let (second, _) = grab_changed_documents(&root, &mtimes).unwrap();
+    assert!(second.is_empty());
+
+    let touched = root.join("b.rs");
+    fs::write(&touched, "fn b() { todo!() }\n").unwrap();
+    let later = mtimes["b.rs"] + std::time::Duration::from_secs(10);
+    fs::File::options()
+        .write(true)
+        .open(&touched)
+        .unwrap()
+        .set_modified(later)
+        .unwrap();
+
+    let (third, updated) = grab_changed_documents(&root, &mtimes).unwrap();
+    assert_eq!(third.len(), 1);
+    assert_eq!(third[0].path, "b.rs");
+    assert_ne!(
+        third[0].id,
+        first.iter().find(|d| d.path == "b.rs").unwrap().id
+    );
+    assert_eq!(updated["b.rs"], later);
+    assert_eq!(updated["a.rs"], mtimes["a.rs"]);
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn test_serial_collection_matches_parallel() {
+    let serial_paths = |root: &Path| -> Vec<String> {
+        DocumentCollector::new(root)
+            .threads(1)
+            .collect()
+            .unwrap()
+            .into_iter()
+            .map(|d| d.path)
+            .collect()
+    };
+    let root = Path::new("tests/fixtures");
+
+    let serial = serial_paths(root);
+    assert!(!serial.is_empty());
+    assert_eq!(serial, serial_paths(root));
+
+    let mut sorted = serial.clone();
+    sorted.sort();
+    assert_eq!(sorted, sorted_paths(DocumentCollector::new(root)));
+    assert_eq!(
+        sorted,
+        sorted_paths(DocumentCollector::new(root).threads(3))
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_follow_links_survives_cycles() {
+    use std::os::unix::fs::symlink;
+
+    let root = scratch_tree(
+        "symlinks",
+        &[
+            ("top.rs", "fn top() {}\n"),
+            ("third_party/lib.rs", "fn vendored() {}\n"),
+            ("third_party/nested/deep.rs", "fn deep() {}\n"),
+        ],
+    );
+    // A relative link back up the tree, and a second route into the same directory
+    symlink("..", root.join("third_party/nested/up")).unwrap();
+    symlink("third_party", root.join("alias")).unwrap();
+
+    let not_followed = sorted_paths(DocumentCollector::new(&root).threads(1));
+    assert_eq!(
+        not_followed,
+        ["third_party/lib.rs", "third_party/nested/deep.rs", "top.rs"]
+    );
+
+    let followed = DocumentCollector::new(&root)
+        .threads(1)
+        .follow_links(true)
+        .collect()
+        .unwrap();
+    let texts: HashSet<&str> = followed.iter().map(|d| d.text.as_str()).collect();
+    assert_eq!(followed.len(), 3);
+    assert_eq!(texts.len(), 3);
+    assert_eq!(
+        sorted_paths(DocumentCollector::new(&root).follow_links(true)).len(),
+        3
+    );
+
+    let shallow = sorted_paths(DocumentCollector::new(&root).max_depth(Some(1)));
+    assert_eq!(shallow, ["top.rs"]);
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn test_path_only_ids_survive_edits() {
+    let ids = |root: &Path, scheme: IdScheme| -> DocumentID {
+        let docs = DocumentCollector::new(root)
+            .id_scheme(scheme)
+            .collect()
+            .unwrap();
+        assert_eq!(docs.len(), 1);
+        docs[0].id
+    };
+    let root = scratch_tree("id-scheme", &[("notes.md", "# Draft\n")]);
+    let content_before = ids(&root, IdScheme::PathAndContent);
+    let path_before = ids(&root, IdScheme::PathOnly);
+    assert_ne!(content_before, path_before);
+
+    fs::write(root.join("notes.md"), "# Final\n").unwrap();
+    assert_ne!(ids(&root, IdScheme::PathAndContent), content_before);
+    assert_eq!(ids(&root, IdScheme::PathOnly), path_before);
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn test_streaming_matches_collect() {
+    let root = Path::new("tests/fixtures");
+    let mut streamed: Vec<DocumentID> = documents_iter(root).map(|d| d.unwrap().id).collect();
+    let mut collected: Vec<DocumentID> = grab_all_documents(root)
+        .unwrap()
+        .into_iter()
+        .map(|d| d.id)
+        .collect();
+    assert!(!streamed.is_empty());
+    streamed.sort();
+    collected.sort();
+    assert_eq!(streamed, collected);
+
+    let mut missing = documents_iter(Path::new("tests/no-such-dir"));
+    assert!(matches!(
+        missing.next(),
+        Some(Err(RAGError::FileRead { .. }))
+    ));
+    assert!(missing.next().is_none());
+}
+
+#[test]
+fn test_lockfiles_are_skipped_by_default() {
+    let root = Path::new("tests/fixtures");
+    let paths = sorted_paths(DocumentCollector::new(root));
+    assert!(paths.contains(&"settings.json".to_string()));
+    assert!(!paths.contains(&"package-lock.json".to_string()));
+
+    let paths = sorted_paths(DocumentCollector::new(root).generated_globs(Vec::<String>::new()));
+    assert!(paths.contains(&"package-lock.json".to_string()));
+}
+
+#[test]
+fn test_ids_are_stable_for_the_hash_algorithm() {
+    let doc = Document::from_str("lib.rs", "fn main() {}\n".to_string());
+    let hex: String = doc.id.iter().map(|b| format!("{b:02x}")).collect();
+    #[cfg(not(feature = "blake3"))]
+    let expected = "e5280ed914a1435b3d85b5966df7eeb9254e1892dc0d6ca9894a0c593af4c0c8";
+    #[cfg(feature = "blake3")]
+    let expected = "2f4f7b5da32df7095a2e4d4795ead32ec8c7b08545dc607e85655d2caa17b012";
+    assert_eq!(hex, expected);
+
+    let chunks = Chunker::new().chunk_document(&doc).unwrap();
+    let again = Chunker::new()
+        .chunk_document(&Document::from_str("lib.rs", "fn main() {}\n".to_string()))
+        .unwrap();
+    assert_eq!(chunks[0].id, again[0].id);
+}
+
+// The `encoding` feature would decode the Latin-1 file instead of skipping it
+#[test]
+#[cfg(not(feature = "encoding"))]
+fn test_skipped_files_are_reported() {
+    use wubraglib::document::{IngestWarning, SkipReason};
+
+    let root = scratch_tree("warnings", &[("lib.rs", "pub fn ok() {}\n")]);
+    fs::write(root.join("latin1.txt"), b"caf\xe9 cr\xe8me br\xfbl\xe9e").unwrap();
+
+    let (docs, warnings) = DocumentCollector::new(&root)
+        .collect_with_warnings()
+        .unwrap();
+    assert_eq!(docs.len(), 1);
+    assert_eq!(
+        warnings,
+        vec![IngestWarning {
+            path: root.join("latin1.txt"),
+            reason: SkipReason::NonUtf8,
+        }]
+    );
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn test_skipped_directories_are_never_read() {
+    use wubraglib::document::SkipReason;
+
+    let root = scratch_tree(
+        "skip-dirs",
+        &[
+            ("app.py", "def main():\n    pass\n"),
+            ("__pycache__/notes.txt", "stale\n"),
+            (
+                "pkg/__pycache__/app.cpython-312.pyc",
+                "\u{1}\u{2}\u{3}\u{4}\u{5}pyc",
+            ),
+        ],
+    );
+
+    // A binary file that was read would come back as a warning
+    let (docs, warnings) = DocumentCollector::new(&root)
+        .collect_with_warnings()
+        .unwrap();
+    let paths: Vec<&str> = docs.iter().map(|d| d.path.as_str()).collect();
+    assert_eq!(paths, ["app.py"]);
+    assert!(warnings.is_empty(), "{warnings:?}");
+
+    let (docs, warnings) = DocumentCollector::new(&root)
+        .skip_dirs(Vec::<String>::new())
+        .collect_with_warnings()
+        .unwrap();
+    assert_eq!(docs.len(), 2);
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].reason, SkipReason::Binary);
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn test_collection_order_is_stable() {
+    let ids = |docs: Vec<Document>| -> Vec<(String, DocumentID)> {
+        docs.into_iter().map(|d| (d.path, d.id)).collect()
+    };
+    let first = ids(grab_all_documents(Path::new("tests/fixtures")).unwrap());
+    let second = ids(grab_all_documents(Path::new("tests/fixtures")).unwrap());
+    assert_eq!(first, second);
+    assert!(first.is_sorted_by(|a, b| a.0 < b.0));
+
+    let pooled = DocumentCollector::new("tests/fixtures").threads(4);
+    assert_eq!(ids(pooled.collect().unwrap()), first);
+}
@@ -0,0 +1,408 @@
+extern crate wubraglib;
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use wubraglib::chunking::*;
+use wubraglib::document::{Document, IdScheme};
+use wubraglib::embedding::{Embed, Embedder, EmbeddingCache};
+use wubraglib::indexing::{Index, UpdateReport};
+use wubraglib::*;
+
+// Deterministic stand-in for a real model: [byte length, first byte, word count]
+struct MockEmbed;
+
+impl Embed for MockEmbed {
+    fn embed(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+        Ok(texts
+            .iter()
+            .map(|t| {
+                vec![
+                    t.len() as f32,
+                    t.bytes().next().unwrap_or(0) as f32,
+                    t.split_whitespace().count() as f32,
+                ]
+            })
+            .collect())
+    }
+
+    fn dim(&self) -> usize {
+        3
+    }
+}
+
+fn sample_chunks() -> Vec<Chunk> {
+    let src = "fn alpha() {}\n\nstruct Beta;\n\nfn gamma(x: u8) -> u8 {\n    x\n}\n";
+    Chunker::new()
+        .chunk_document(&Document::from_str("lib.rs", src.to_string()))
+        .unwrap()
+}
+
+#[test]
+fn test_mock_backend_embeds_chunks_in_order() {
+    let chunks = sample_chunks();
+    let embedder = Embedder::with_backend(MockEmbed).with_normalize(false);
+    assert_eq!(embedder.dim(), 3);
+
+    let embeddings = embedder.embed_chunks(&chunks).unwrap();
+    assert_eq!(embeddings.len(), chunks.len());
+    for (chunk, embedding) in chunks.iter().zip(&embeddings) {
+        assert_eq!(embedding, &MockEmbed.embed(&[&chunk.text]).unwrap()[0]);
+        assert_eq!(embedding.len(), embedder.dim());
+    }
+}
+
+// Counts calls and can be switched off to prove the cache answers alone
+struct CountingEmbed {
+    calls: AtomicUsize,
+    offline: AtomicBool,
+}
+
+impl Embed for CountingEmbed {
+    fn embed(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+        if self.offline.load(Ordering::SeqCst) {
+            return Err(RAGError::Embedding("model unloaded".to_string()));
+        }
+        self.calls.fetch_add(texts.len(), Ordering::SeqCst);
+        MockEmbed.embed(texts)
+    }
+
+    fn dim(&self) -> usize {
+        3
+    }
+}
+
+#[test]
+fn test_cache_serves_repeated_chunks() {
+    let chunks = sample_chunks();
+    let backend = CountingEmbed {
+        calls: AtomicUsize::new(0),
+        offline: AtomicBool::new(false),
+    };
+    let embedder = Embedder::with_backend(backend).with_cache(EmbeddingCache::new());
+
+    let first = embedder.embed_chunks(&chunks).unwrap();
+    assert_eq!(
+        embedder.backend().calls.load(Ordering::SeqCst),
+        chunks.len()
+    );
+
+    embedder.backend().offline.store(true, Ordering::SeqCst);
+    let second = embedder.embed_chunks(&chunks).unwrap();
+    assert_eq!(first, second);
+    assert_eq!(
+        embedder.backend().calls.load(Ordering::SeqCst),
+        chunks.len()
+    );
+
+    let path = std::env::temp_dir().join(format!("wubraglib-cache-{}.bin", std::process::id()));
+    embedder.cache().unwrap().save(&path).unwrap();
+    let reloaded =
+        Embedder::with_backend(MockEmbed).with_cache(EmbeddingCache::load(&path).unwrap());
+    assert_eq!(reloaded.cache().unwrap().len(), chunks.len());
+    assert_eq!(reloaded.embed_chunks(&chunks).unwrap(), first);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_progress_reports_every_batch() {
+    let chunks = sample_chunks();
+    assert_eq!(chunks.len(), 3);
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let recorded = Arc::clone(&calls);
+    let driver = std::thread::current().id();
+    let embedder = Embedder::with_backend(MockEmbed)
+        .with_batch_size(2)
+        .with_progress(move |done, total| {
+            assert_eq!(std::thread::current().id(), driver);
+            recorded.lock().unwrap().push((done, total));
+        });
+
+    let embeddings = embedder.embed_chunks(&chunks).unwrap();
+    assert_eq!(embeddings.len(), 3);
+    assert_eq!(*calls.lock().unwrap(), vec![(2, 3), (3, 3)]);
+}
+
+#[test]
+fn test_embeddings_are_unit_length_by_default() {
+    let chunks = sample_chunks();
+    let embeddings = Embedder::with_backend(MockEmbed)
+        .embed_chunks(&chunks)
+        .unwrap();
+    for embedding in &embeddings {
+        let norm = embedding.iter().map(|x| x * x).sum::<f32>().sqrt();
+        assert!((norm - 1.0).abs() < 1e-5, "norm {norm}");
+        assert!((util::cosine_normalized(embedding, embedding) - 1.0).abs() < 1e-5);
+    }
+
+    let mut zero = vec![0.0; 3];
+    util::l2_normalize(&mut zero);
+    assert_eq!(zero, vec![0.0; 3]);
+}
+
+// Embeds "chunk N" as [N, 1], taking longer for earlier texts so batches finish out of order
+struct IndexTaggingEmbed;
+
+impl Embed for IndexTaggingEmbed {
+    fn embed(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+        let tags: Vec<f32> = texts
+            .iter()
+            .map(|t| t.trim_start_matches("chunk ").parse().unwrap())
+            .collect();
+        let delay = 40.0 - tags[0].min(40.0);
+        std::thread::sleep(std::time::Duration::from_millis(delay as u64));
+        Ok(tags.into_iter().map(|tag| vec![tag, 1.0]).collect())
+    }
+
+    fn dim(&self) -> usize {
+        2
+    }
+}
+
+#[test]
+fn test_parallel_batches_keep_input_order() {
+    let src: String = (0..40).map(|i| format!("chunk {i}\n\n")).collect();
+    let chunks = Chunker::new()
+        .chunk_document(&Document::from_str("notes.txt", src))
+        .unwrap();
+    assert_eq!(chunks.len(), 40);
+
+    for parallel_batches in [0, 1, 4] {
+        let embedder = Embedder::with_backend(IndexTaggingEmbed)
+            .with_normalize(false)
+            .with_batch_size(3)
+            .with_parallel_batches(parallel_batches);
+        let embeddings = embedder.embed_chunks(&chunks).unwrap();
+        assert_eq!(embeddings.len(), chunks.len());
+        for (i, (chunk, embedding)) in chunks.iter().zip(&embeddings).enumerate() {
+            assert_eq!(chunk.text, format!("chunk {i}"));
+            assert_eq!(embedding, &vec![i as f32, 1.0]);
+        }
+    }
+}
+
+#[test]
+fn test_embed_query_searches_chunk_embeddings() {
+    let chunks = sample_chunks();
+    let embedder = Embedder::with_backend(MockEmbed);
+    let embeddings = embedder.embed_chunks(&chunks).unwrap();
+    let index = indexing::Index::new(chunks, embeddings).unwrap();
+
+    let query = embedder.embed_query("struct Beta;").unwrap();
+    assert_eq!(query.len(), embedder.dim());
+    let norm = query.iter().map(|x| x * x).sum::<f32>().sqrt();
+    assert!((norm - 1.0).abs() < 1e-5);
+
+    let top = index.search(&query, 1).unwrap();
+    assert_eq!(index.retrieve(top[0].index).text, "struct Beta;");
+    assert!((top[0].score - 1.0).abs() < 1e-5);
+}
+
+// Remembers every text it is asked to embed, and in how many calls
+#[derive(Default)]
+struct RecordingEmbed {
+    seen: Mutex<Vec<String>>,
+    calls: AtomicUsize,
+}
+
+impl Embed for RecordingEmbed {
+    fn embed(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        let mut seen = self.seen.lock().unwrap();
+        seen.extend(texts.iter().map(|t| t.to_string()));
+        MockEmbed.embed(texts)
+    }
+
+    fn dim(&self) -> usize {
+        3
+    }
+}
+
+#[test]
+fn test_query_and_passage_prefixes() {
+    let chunks = sample_chunks();
+    let embedder = Embedder::with_backend(RecordingEmbed::default())
+        .with_query_prefix("query: ")
+        .with_passage_prefix("passage: ");
+
+    embedder.embed_chunks(&chunks).unwrap();
+    embedder.embed_query("where is beta").unwrap();
+
+    let expected: Vec<String> = chunks
+        .iter()
+        .map(|c| format!("passage: {}", c.text))
+        .chain(["query: where is beta".to_string()])
+        .collect();
+    assert_eq!(*embedder.backend().seen.lock().unwrap(), expected);
+    assert!(chunks.iter().all(|c| !c.text.starts_with("passage: ")));
+}
+
+// MockEmbed with a tiny input limit
+struct ShortContextEmbed;
+
+impl Embed for ShortContextEmbed {
+    fn embed(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+        MockEmbed.embed(texts)
+    }
+
+    fn dim(&self) -> usize {
+        MockEmbed.dim()
+    }
+
+    fn max_tokens(&self) -> Option<usize> {
+        Some(8)
+    }
+}
+
+#[test]
+fn test_overlong_chunks_are_reported() {
+    let src = "fn short() {}\n\n\
+               fn long(a: u8, b: u8, c: u8) -> u8 {\n    let sum = a + b + c;\n    sum * 2\n}\n";
+    let chunks = Chunker::new()
+        .chunk_document(&Document::from_str("lib.rs", src.to_string()))
+        .unwrap();
+    let long = chunks.iter().find(|c| c.text.contains("fn long")).unwrap();
+
+    let embedder = Embedder::with_backend(ShortContextEmbed);
+    let truncated = embedder.truncated_chunks(&chunks);
+    assert_eq!(truncated.len(), 1);
+    assert_eq!(truncated[0].id, long.id);
+    // the default only warns
+    assert_eq!(embedder.embed_chunks(&chunks).unwrap().len(), chunks.len());
+
+    let strict = Embedder::with_backend(ShortContextEmbed).with_error_on_truncation(true);
+    match strict.embed_chunks(&chunks) {
+        Err(RAGError::Embedding(message)) => {
+            let hex: String = long.id.iter().map(|b| format!("{b:02x}")).collect();
+            assert!(message.contains(&hex), "{message}");
+            assert!(message.contains("lib.rs:3"), "{message}");
+        }
+        other => panic!("expected a truncation error, got {other:?}"),
+    }
+
+    // Token counts recorded by the chunker take precedence over word counts
+    let mut counted = chunks.clone();
+    counted.iter_mut().for_each(|c| c.token_count = Some(1));
+    assert!(strict.embed_chunks(&counted).is_ok());
+    assert!(
+        Embedder::with_backend(MockEmbed)
+            .truncated_chunks(&chunks)
+            .is_empty()
+    );
+}
+
+#[test]
+fn test_update_embeds_only_changed_chunks() {
+    // Path-only document IDs, so untouched chunks keep their IDs across edits
+    let chunk = |src: &str| {
+        let mut doc = Document::from_str("lib.rs", src.to_string());
+        doc.id = IdScheme::PathOnly.document_id(&doc.path, &doc.text);
+        Chunker::new().chunk_document(&doc).unwrap()
+    };
+    let before = chunk("fn alpha() {}\n\nfn beta() {}\n\nfn gamma() {}\n");
+    let after = chunk("fn alpha() {}\n\nfn beta() {}\n\nfn gamma() -> u8 { 2 }\n");
+    assert_eq!(before.len(), 3);
+
+    let embedder = Embedder::with_backend(RecordingEmbed::default());
+    let embeddings = embedder.embed_chunks(&before).unwrap();
+    let mut index = Index::new(before.clone(), embeddings).unwrap();
+    let alpha = index.embedding(0).into_owned();
+    let backend = embedder.backend();
+    backend.seen.lock().unwrap().clear();
+    backend.calls.store(0, Ordering::SeqCst);
+
+    let report = index.update(after.clone(), &embedder).unwrap();
+    assert_eq!(
+        report,
+        UpdateReport {
+            added: 1,
+            removed: 1,
+            kept: 2
+        }
+    );
+    assert_eq!(backend.calls.load(Ordering::SeqCst), 1);
+    assert_eq!(*backend.seen.lock().unwrap(), ["fn gamma() -> u8 { 2 }"]);
+    index.validate().unwrap();
+    assert!(index.get(&before[2].id).is_none());
+    assert_eq!(
+        index.get(&after[2].id).unwrap().text,
+        "fn gamma() -> u8 { 2 }"
+    );
+    assert_eq!(index.embedding(index.id_to_idx()[&after[0].id]), alpha);
+    // embedded through the Embedder, so normalized like the rest
+    let gamma = index.embedding(index.id_to_idx()[&after[2].id]);
+    assert!((gamma.iter().map(|x| x * x).sum::<f32>() - 1.0).abs() < 1e-5);
+
+    // Nothing changed, nothing embedded
+    let report = index.update(after, &embedder).unwrap();
+    assert_eq!((report.added, report.removed, report.kept), (0, 0, 3));
+    assert_eq!(backend.calls.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn test_update_reuses_embeddings_of_renamed_chunks() {
+    // Default IDs: editing the first function renames every chunk in the file
+    let chunk = |src: &str| {
+        Chunker::new()
+            .chunk_document(&Document::from_str("lib.rs", src.to_string()))
+            .unwrap()
+    };
+    let before = chunk("fn alpha() {}\n\nfn beta() {}\n\nfn gamma() {}\n");
+    let after = chunk("fn alpha() -> u8 { 1 }\n\nfn beta() {}\n\nfn gamma() {}\n");
+    assert!(before.iter().all(|b| after.iter().all(|a| a.id != b.id)));
+
+    let embedder = Embedder::with_backend(RecordingEmbed::default());
+    let embeddings = embedder.embed_chunks(&before).unwrap();
+    let mut index = Index::new(before.clone(), embeddings).unwrap();
+    let old: Vec<Vec<f32>> = index.embeddings().map(|e| e.into_owned()).collect();
+    let backend = embedder.backend();
+    backend.seen.lock().unwrap().clear();
+    backend.calls.store(0, Ordering::SeqCst);
+
+    let report = index.update(after.clone(), &embedder).unwrap();
+    assert_eq!(
+        report,
+        UpdateReport {
+            added: 1,
+            removed: 3,
+            kept: 2
+        }
+    );
+    assert_eq!(backend.calls.load(Ordering::SeqCst), 1);
+    assert_eq!(*backend.seen.lock().unwrap(), ["fn alpha() -> u8 { 1 }"]);
+    index.validate().unwrap();
+    assert_eq!(index.chunks.len(), 3);
+    for i in 1..3 {
+        assert_eq!(index.embedding(index.id_to_idx()[&after[i].id]), old[i]);
+    }
+}
+
+#[test]
+fn test_embed_only_chunks_matching_a_filter() {
+    let (chunks, _) = Chunker::new()
+        .chunk_all_documents(&[
+            Document::from_str("lib.rs", "fn alpha() {}\n\nfn beta() {}\n".to_string()),
+            Document::from_str("edited.rs", "fn gamma() {}\n\nstruct Delta;\n".to_string()),
+        ])
+        .unwrap();
+    let edited = chunks
+        .iter()
+        .find(|c| c.path == "edited.rs")
+        .unwrap()
+        .doc_id;
+    let embedder = Embedder::with_backend(RecordingEmbed::default()).with_normalize(false);
+
+    let embedded = embedder
+        .embed_chunks_where(&chunks, |c| c.doc_id == edited)
+        .unwrap();
+    let expected: Vec<&Chunk> = chunks.iter().filter(|c| c.doc_id == edited).collect();
+    assert_eq!(expected.len(), 2);
+    assert_eq!(embedded.len(), 2);
+    for ((id, embedding), chunk) in embedded.iter().zip(&expected) {
+        assert_eq!(*id, chunk.id);
+        assert_eq!(embedding, &MockEmbed.embed(&[&chunk.text]).unwrap()[0]);
+    }
+
+    let texts: Vec<&str> = expected.iter().map(|c| c.text.as_str()).collect();
+    assert_eq!(*embedder.backend().seen.lock().unwrap(), texts);
+}
@@ -0,0 +1,46 @@
+extern crate wubraglib;
+
+use std::fs;
+
+use wubraglib::document::{Document, DocumentCollector};
+
+fn collect(root: &str) -> Vec<Document> {
+    let mut docs = DocumentCollector::new(root).collect().unwrap();
+    docs.sort_by(|a, b| a.path.cmp(&b.path));
+    docs
+}
+
+#[test]
+fn test_utf16_and_latin1_are_decoded() {
+    let docs = collect("tests/encoding_fixtures");
+    let paths: Vec<&str> = docs.iter().map(|d| d.path.as_str()).collect();
+    assert_eq!(paths, ["latin1.c", "window_utf16.cpp"]);
+
+    let latin1 = &docs[0];
+    assert!(latin1.text.starts_with("/* Café crème: état du système"));
+
+    let utf16 = &docs[1];
+    assert_eq!(utf16.ext, "cpp");
+    assert!(!utf16.text.contains('\0'));
+    assert!(utf16.text.contains("// Größenänderung des Fensters\r\n"));
+    assert!(
+        utf16
+            .text
+            .contains("void ResizeWindow(int width, int height)")
+    );
+}
+
+#[test]
+fn test_undetectable_bytes_are_skipped() {
+    let root = std::env::temp_dir().join(format!("wubraglib-encoding-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(&root).unwrap();
+    // Truncated UTF-16 with an odd trailing byte can't be decoded cleanly
+    fs::write(root.join("broken.txt"), [0xff, 0xfe, b'h', 0, b'i']).unwrap();
+    fs::write(root.join("ok.txt"), "fine\n").unwrap();
+
+    let docs = DocumentCollector::new(&root).collect().unwrap();
+    let paths: Vec<&str> = docs.iter().map(|d| d.path.as_str()).collect();
+    assert_eq!(paths, ["ok.txt"]);
+    fs::remove_dir_all(&root).unwrap();
+}
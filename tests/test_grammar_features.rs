@@ -0,0 +1,31 @@
+extern crate wubraglib;
+
+use wubraglib::RAGError;
+use wubraglib::chunking::*;
+use wubraglib::document::Document;
+
+// Passes with any set of `lang-*` features; the interesting configuration leaves out
+// `lang-cuda` (see Cargo.toml)
+#[test]
+fn test_cuda_without_its_grammar_chunks_naively() {
+    let src = "__global__ void scale(float *v, float k) {\n    v[threadIdx.x] *= k;\n}\n\n\
+               __device__ float twice(float x) {\n    return 2.0f * x;\n}\n";
+    let kernel = Document::from_str("kernel.cu", src.to_string());
+    let (chunks, stats) = Chunker::new().chunk_document_with_stats(&kernel).unwrap();
+    assert_eq!(chunks.len(), 2);
+
+    let query = Chunker::builder()
+        .query("cu", None, Some("(function_definition) @chunk".into()))
+        .build();
+    if cfg!(feature = "lang-cuda") {
+        assert!(LANGUAGE_MAP.contains_key("cu"));
+        assert_eq!(stats.strategy, ChunkStrategy::TreeSitter);
+        assert!(query.is_ok());
+    } else {
+        assert!(!LANGUAGE_MAP.contains_key("cu"));
+        assert_eq!(stats.strategy, ChunkStrategy::Naive);
+        assert!(!stats.parse_failed);
+        assert!(chunks.iter().all(|c| c.kind == ChunkKind::Paragraph));
+        assert!(matches!(query, Err(RAGError::ParsingFailed { .. })));
+    }
+}
@@ -0,0 +1,1035 @@
+extern crate wubraglib;
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use wubraglib::RAGError;
+use wubraglib::chunking::*;
+use wubraglib::document::{Document, DocumentCollector};
+use wubraglib::embedding::EmbeddingCache;
+use wubraglib::indexing::{Index, Metric, QuantMode, SearchResult, VectorStore};
+
+fn doc(path: &str, text: &str) -> Document {
+    Document {
+        id: [3; 32],
+        path: path.to_string(),
+        text: text.to_string(),
+        ext: path
+            .rsplit_once('.')
+            .map(|(_, e)| e)
+            .unwrap_or("")
+            .to_string(),
+        language: None,
+        size: text.len() as u64,
+        modified: None,
+    }
+}
+
+#[test]
+fn test_chunk_text_from_source() {
+    let src = "fn ünï() {}\n\nfn cödé() -> u8 {\n    1\n}\n";
+    let (chunks, _) = Chunker::new()
+        .chunk_all_documents(&[doc("lib.rs", src)])
+        .unwrap();
+    let embeddings = (0..chunks.len())
+        .map(|i| vec![1.0, i as f32, 0.5])
+        .collect();
+    let index = Index::new(chunks.clone(), embeddings).unwrap();
+
+    for (i, chunk) in chunks.iter().enumerate() {
+        assert_eq!(
+            index.chunk_text_from_source(i, src),
+            Some(chunk.text.as_str())
+        );
+    }
+}
+
+#[test]
+fn test_chunk_text_from_source_after_edits() {
+    let docs = DocumentCollector::new("tests/fixtures").collect().unwrap();
+    let chunker = Chunker::builder()
+        .strip_comments(true)
+        .container_mode(ContainerMode::Skeleton)
+        .min_chunk_chars(200)
+        .overlap_chars(20)
+        .build()
+        .unwrap();
+    let (chunks, _) = chunker.chunk_all_documents(&docs).unwrap();
+    let embeddings = vec![vec![1.0]; chunks.len()];
+    let index = Index::new(chunks, embeddings).unwrap();
+
+    // checked against the edited chunk texts
+    for (i, chunk) in index.chunks.iter().enumerate() {
+        let doc = docs.iter().find(|d| d.id == chunk.doc_id).unwrap();
+        assert!(index.chunk_text_from_source(i, &doc.text).is_some());
+    }
+}
+
+#[test]
+fn test_chunk_text_from_wrong_source() {
+    let (chunks, _) = Chunker::new()
+        .chunk_all_documents(&[doc("lib.rs", "fn alpha() {}\n")])
+        .unwrap();
+    let index = Index::new(chunks, vec![vec![1.0]]).unwrap();
+
+    assert_eq!(index.chunk_text_from_source(0, "fn gamma() {}\n"), None);
+    // edited since indexing: too short, or the end offset lands inside `é`
+    assert_eq!(index.chunk_text_from_source(0, "fn a() {}"), None);
+    assert_eq!(index.chunk_text_from_source(0, "fn alpha() {é"), None);
+    assert_eq!(index.chunk_text_from_source(1, "fn alpha() {}\n"), None);
+}
+
+#[test]
+fn test_save_and_load_round_trip() {
+    let src = "fn alpha() {}\n\nfn beta() -> u8 {\n    1\n}\n\nfn gamma() {}\n";
+    let (chunks, _) = Chunker::new()
+        .chunk_all_documents(&[doc("lib.rs", src)])
+        .unwrap();
+    let embeddings: Vec<Vec<f32>> = (0..chunks.len())
+        .map(|i| vec![1.0, i as f32, (i * i) as f32 * 0.25])
+        .collect();
+    let index = Index::new(chunks, embeddings).unwrap();
+
+    let path = std::env::temp_dir().join(format!("wubraglib-index-{}.bin", std::process::id()));
+    index.save(&path).unwrap();
+    let loaded = Index::load(&path).unwrap();
+
+    assert!(loaded.embeddings().eq(index.embeddings()));
+    assert_eq!(loaded.id_to_idx(), index.id_to_idx());
+    for (a, b) in loaded.chunks.iter().zip(&index.chunks) {
+        assert_eq!((a.id, &a.text, &a.raw_kind), (b.id, &b.text, &b.raw_kind));
+        assert_eq!((a.start_byte, a.end_byte), (b.start_byte, b.end_byte));
+    }
+    let query = [1.0, 1.5, 0.5];
+    assert_eq!(
+        loaded.search(&query, 3).unwrap(),
+        index.search(&query, 3).unwrap()
+    );
+
+    std::fs::write(&path, b"not an index").unwrap();
+    assert!(matches!(
+        Index::load(&path),
+        Err(RAGError::Deserialization(_))
+    ));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_files_from_a_build_with_another_id_hash_are_refused() {
+    let (ours, other) = if cfg!(feature = "blake3") {
+        (b"blake3", b"sha256")
+    } else {
+        (b"sha256", b"blake3")
+    };
+    // as if written by that build: the name follows the magic, version and its length
+    let swap_hash = |path: &std::path::Path| {
+        let mut bytes = std::fs::read(path).unwrap();
+        assert_eq!(&bytes[20..26], ours);
+        bytes[20..26].copy_from_slice(other);
+        std::fs::write(path, bytes).unwrap();
+    };
+    let path = std::env::temp_dir().join(format!("wubraglib-hash-{}.bin", std::process::id()));
+
+    let (chunks, _) = Chunker::new()
+        .chunk_all_documents(&[doc("lib.rs", "fn alpha() {}\n")])
+        .unwrap();
+    let id = chunks[0].id;
+    Index::new(chunks, vec![vec![1.0, 0.0]])
+        .unwrap()
+        .save(&path)
+        .unwrap();
+    Index::load(&path).unwrap();
+    swap_hash(&path);
+    assert!(matches!(
+        Index::load(&path),
+        Err(RAGError::Deserialization(message)) if message.contains("hashed with")
+    ));
+
+    let mut cache = EmbeddingCache::new();
+    cache.insert(id, vec![1.0, 0.0]);
+    cache.save(&path).unwrap();
+    EmbeddingCache::load(&path).unwrap();
+    swap_hash(&path);
+    assert!(matches!(
+        EmbeddingCache::load(&path),
+        Err(RAGError::Deserialization(message)) if message.contains("hashed with")
+    ));
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_empty_index_round_trips() {
+    let path = std::env::temp_dir().join(format!("wubraglib-empty-{}.bin", std::process::id()));
+
+    Index::new(vec![], vec![]).unwrap().save(&path).unwrap();
+    let loaded = Index::load(&path).unwrap();
+    assert!(loaded.chunks.is_empty());
+    assert_eq!(loaded.dim(), None);
+
+    // emptied by removing every chunk, which keeps the dimension
+    let (chunks, _) = Chunker::new()
+        .chunk_all_documents(&[doc("lib.rs", "fn alpha() {}\n")])
+        .unwrap();
+    let id = chunks[0].id;
+    let mut index = Index::new(chunks, vec![vec![1.0, 0.0]]).unwrap();
+    index.remove(&id).unwrap();
+    index.save(&path).unwrap();
+    let loaded = Index::load(&path).unwrap();
+    assert!(loaded.chunks.is_empty());
+    assert_eq!(loaded.dim(), Some(2));
+    assert!(loaded.search(&[1.0, 0.0], 3).unwrap().is_empty());
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_metric_rankings() {
+    let chunks: Vec<Chunk> = ["a", "b", "c"]
+        .iter()
+        .flat_map(|name| {
+            let src = format!("fn {name}() {{}}\n");
+            Chunker::new()
+                .chunk_document(&doc(&format!("{name}.rs"), &src))
+                .unwrap()
+        })
+        .collect();
+    // a: same direction as the query but short, b: long and slightly off-axis, c: exact match
+    let embeddings = vec![vec![0.1, 0.0], vec![10.0, 1.0], vec![1.0, 0.0]];
+    let query = [1.0, 0.0];
+
+    let ranking = |metric| {
+        Index::new_with_metric(chunks.clone(), embeddings.clone(), metric)
+            .unwrap()
+            .search(&query, 3)
+            .unwrap()
+            .into_iter()
+            .map(|r| r.index)
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(ranking(Metric::Cosine)[2], 1);
+    assert_eq!(ranking(Metric::DotProduct), vec![1, 2, 0]);
+    assert_eq!(ranking(Metric::Euclidean), vec![2, 0, 1]);
+
+    let euclidean = Index::new_with_metric(chunks, embeddings, Metric::Euclidean).unwrap();
+    let scores: Vec<f32> = euclidean
+        .search(&query, 3)
+        .unwrap()
+        .iter()
+        .map(|r| r.score)
+        .collect();
+    assert!(scores.windows(2).all(|w| w[0] <= w[1]));
+    assert_eq!(scores[0], 0.0);
+}
+
+#[test]
+fn test_cached_norms_match_naive_cosine() {
+    let src = "fn a() {}\n\nfn b() {}\n\nfn c() {}\n\nfn d() {}\n";
+    let (chunks, _) = Chunker::new()
+        .chunk_all_documents(&[doc("lib.rs", src)])
+        .unwrap();
+    let mut embeddings: Vec<Vec<f32>> = (0..chunks.len())
+        .map(|i| vec![0.3 + i as f32, -1.7 * i as f32, 0.01, 2.5 / (i + 1) as f32])
+        .collect();
+    embeddings[1] = vec![0.0; 4];
+    let index = Index::new(chunks, embeddings.clone()).unwrap();
+    assert_eq!(index.norms().len(), embeddings.len());
+
+    let query = [0.9, -0.4, 1.3, 0.2];
+    let naive = |b: &[f32]| {
+        let dot: f32 = query.iter().zip(b).map(|(x, y)| x * y).sum();
+        let na = query.iter().map(|x| x * x).sum::<f32>().sqrt();
+        let nb = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if nb == 0.0 { 0.0 } else { dot / (na * nb) }
+    };
+    for r in index.search(&query, embeddings.len()).unwrap() {
+        assert_eq!(r.score.to_bits(), naive(&embeddings[r.index]).to_bits());
+    }
+}
+
+#[test]
+fn test_search_ids_survive_rebuilds() {
+    let src = "fn a() {}\n\nfn b() {}\n\nfn c() {}\n";
+    let (chunks, _) = Chunker::new()
+        .chunk_all_documents(&[doc("lib.rs", src)])
+        .unwrap();
+    let embeddings = vec![vec![1.0, 0.0], vec![0.0, 1.0], vec![0.7, 0.7]];
+    let index = Index::new(chunks.clone(), embeddings.clone()).unwrap();
+
+    let query = [0.0, 1.0];
+    let best_idx = index.search(&query, 1).unwrap()[0].index;
+    let (best_id, _) = index.search_ids(&query, 1).unwrap()[0];
+    assert_eq!(index.get(&best_id).unwrap().text, "fn b() {}");
+
+    // Drop the first chunk: positions shift, IDs don't
+    let rebuilt = Index::new(chunks[1..].to_vec(), embeddings[1..].to_vec()).unwrap();
+    assert_ne!(rebuilt.retrieve(best_idx).id, best_id);
+    assert_eq!(rebuilt.search_ids(&query, 1).unwrap()[0].0, best_id);
+    assert_eq!(rebuilt.get(&best_id).unwrap().text, "fn b() {}");
+    assert!(rebuilt.get(&chunks[0].id).is_none());
+}
+
+#[test]
+fn test_add_and_remove() {
+    let src = "fn a() {}\n\nfn b() {}\n\nfn c() {}\n";
+    let (chunks, _) = Chunker::new()
+        .chunk_all_documents(&[doc("lib.rs", src)])
+        .unwrap();
+    let mut index = Index::new(chunks[..2].to_vec(), vec![vec![1.0, 0.0], vec![0.0, 1.0]]).unwrap();
+
+    let query = [0.6, 0.8];
+    index.add(chunks[2].clone(), vec![0.6, 0.8]).unwrap();
+    assert_eq!(index.search_ids(&query, 1).unwrap()[0].0, chunks[2].id);
+    assert!(matches!(
+        index.add(chunks[2].clone(), vec![1.0, 0.0, 0.0]),
+        Err(RAGError::DimensionMismatch {
+            expected: 2,
+            got: 3
+        })
+    ));
+
+    let (removed, embedding) = index.remove(&chunks[0].id).unwrap();
+    assert_eq!(removed.id, chunks[0].id);
+    assert_eq!(embedding, vec![1.0, 0.0]);
+    assert!(index.remove(&chunks[0].id).is_none());
+    assert_eq!(index.chunks.len(), 2);
+
+    let results = index.search_ids(&[1.0, 0.0], 3).unwrap();
+    assert!(results.iter().all(|(id, _)| *id != chunks[0].id));
+    for (id, _) in results {
+        assert_eq!(index.get(&id).unwrap().id, id);
+    }
+}
+
+#[test]
+fn test_search_threshold_filters_low_scores() {
+    let src = "fn a() {}\n\nfn b() {}\n\nfn c() {}\n";
+    let (chunks, _) = Chunker::new()
+        .chunk_all_documents(&[doc("lib.rs", src)])
+        .unwrap();
+    let embeddings = vec![vec![1.0, 0.0], vec![0.0, 0.0], vec![0.8, 0.6]];
+    let index = Index::new(chunks, embeddings).unwrap();
+
+    let hits = index.search_threshold(&[1.0, 0.0], 3, 0.5).unwrap();
+    assert_eq!(hits.iter().map(|r| r.0).collect::<Vec<_>>(), vec![0, 2]);
+
+    // Orthogonal to everything stored
+    assert!(
+        index
+            .search_threshold(&[0.0, 1.0], 3, 0.7)
+            .unwrap()
+            .is_empty()
+    );
+    assert!(
+        index
+            .search_threshold(&[0.0, 0.0], 3, 0.1)
+            .unwrap()
+            .is_empty()
+    );
+
+    let euclidean = Index::new_with_metric(
+        index.chunks.clone(),
+        index.embeddings().map(|e| e.into_owned()).collect(),
+        Metric::Euclidean,
+    )
+    .unwrap();
+    let near = euclidean.search_threshold(&[1.0, 0.0], 3, 0.7).unwrap();
+    assert_eq!(near.iter().map(|r| r.0).collect::<Vec<_>>(), vec![0, 2]);
+}
+
+#[test]
+fn test_nan_scores_sort_last() {
+    let src = "fn a() {}\n\nfn b() {}\n\nfn c() {}\n";
+    let (chunks, _) = Chunker::new()
+        .chunk_all_documents(&[doc("lib.rs", src)])
+        .unwrap();
+    let embeddings = vec![vec![f32::NAN, 1.0], vec![0.0, 0.0], vec![1.0, 0.5]];
+
+    for metric in [Metric::Cosine, Metric::DotProduct, Metric::Euclidean] {
+        let index = Index::new_with_metric(chunks.clone(), embeddings.clone(), metric).unwrap();
+        let results = index.search(&[1.0, 0.0], 3).unwrap();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].index, 2, "{metric:?}");
+        assert_eq!(results[2].index, 0, "{metric:?}");
+        assert!(results[2].score.is_nan());
+    }
+}
+
+#[test]
+fn test_search_batch_matches_sequential() {
+    let src = "fn a() {}\n\nfn b() {}\n\nfn c() {}\n\nfn d() {}\n";
+    let (chunks, _) = Chunker::new()
+        .chunk_all_documents(&[doc("lib.rs", src)])
+        .unwrap();
+    let embeddings = vec![
+        vec![1.0, 0.0, 0.2],
+        vec![0.1, 1.0, 0.0],
+        vec![0.5, 0.5, 0.5],
+        vec![-1.0, 0.3, 0.9],
+    ];
+    let index = Index::new(chunks, embeddings).unwrap();
+
+    let queries = vec![
+        vec![1.0, 0.1, 0.0],
+        vec![0.0, 0.0, 1.0],
+        vec![-0.2, 1.0, 0.1],
+    ];
+    let batch = index.search_batch(&queries, 2).unwrap();
+    let sequential: Vec<_> = queries
+        .iter()
+        .map(|q| {
+            let results = index.search(q, 2).unwrap();
+            results
+                .iter()
+                .map(|r| (r.index, r.score))
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    assert_eq!(batch, sequential);
+
+    let ragged = vec![vec![1.0, 0.0, 0.0], vec![1.0, 0.0]];
+    match index.search_batch(&ragged, 2) {
+        Err(RAGError::BatchQuery { query: 1, source }) => assert!(matches!(
+            *source,
+            RAGError::DimensionMismatch {
+                expected: 3,
+                got: 2
+            }
+        )),
+        other => panic!("unexpected {other:?}"),
+    }
+}
+
+#[test]
+fn test_mmr_prefers_diverse_results() {
+    let src = "fn a() {}\n\nfn b() {}\n\nfn c() {}\n";
+    let (chunks, _) = Chunker::new()
+        .chunk_all_documents(&[doc("lib.rs", src)])
+        .unwrap();
+    // a and b are near-duplicates, c is relevant but points elsewhere
+    let embeddings = vec![
+        vec![1.0, 0.0, 0.0],
+        vec![0.99, 0.01, 0.0],
+        vec![0.7, 0.0, 0.7],
+    ];
+    let index = Index::new(chunks, embeddings).unwrap();
+    let query = [1.0, 0.0, 0.1];
+
+    let plain: Vec<usize> = index
+        .search(&query, 2)
+        .unwrap()
+        .iter()
+        .map(|r| r.index)
+        .collect();
+    assert_eq!(plain, vec![0, 1]);
+
+    let diverse: Vec<usize> = index
+        .search_mmr(&query, 2, 0.5)
+        .unwrap()
+        .iter()
+        .map(|r| r.0)
+        .collect();
+    assert_eq!(diverse, vec![0, 2]);
+
+    assert_eq!(
+        index.search_mmr(&query, 3, 1.0).unwrap(),
+        index
+            .search(&query, 3)
+            .unwrap()
+            .iter()
+            .map(|r| (r.index, r.score))
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_search_unique_docs() {
+    let mut long = doc("long.rs", "fn a() {}\n\nfn b() {}\n\nfn c() {}\n");
+    long.id = [1; 32];
+    let short = doc("short.rs", "fn d() {}\n");
+    let (chunks, _) = Chunker::new().chunk_all_documents(&[long, short]).unwrap();
+    let embeddings: Vec<Vec<f32>> = chunks
+        .iter()
+        .map(|c| match c.doc_id {
+            [1, ..] => vec![1.0, 0.05 * c.start_line as f32],
+            _ => vec![0.6, 0.8],
+        })
+        .collect();
+    let index = Index::new(chunks, embeddings).unwrap();
+    let query = [1.0, 0.0];
+
+    let plain: Vec<_> = index
+        .search(&query, 2)
+        .unwrap()
+        .iter()
+        .map(|r| index.retrieve(r.index).doc_id)
+        .collect();
+    assert!(plain.iter().all(|id| *id == [1; 32]));
+
+    let unique = index.search_unique_docs(&query, 2).unwrap();
+    assert_eq!(unique.len(), 2);
+    let docs: Vec<_> = unique.iter().map(|r| index.retrieve(r.0).doc_id).collect();
+    assert_eq!(docs, vec![[1; 32], [3; 32]]);
+    assert_eq!(index.retrieve(unique[0].0).text, "fn a() {}");
+}
+
+#[test]
+fn test_hybrid_search_surfaces_exact_identifier() {
+    let src = "fn parse_headers_lenient(input: &str) {}\n\n\
+               fn parse_header(line: &str) {}\n\n\
+               fn render_footer() {}\n";
+    let (chunks, _) = Chunker::new()
+        .chunk_all_documents(&[doc("lib.rs", src)])
+        .unwrap();
+    assert_eq!(chunks.len(), 3);
+    // The embedding model prefers the similarly named but wrong function
+    let embeddings = vec![vec![1.0, 0.1], vec![0.8, 0.6], vec![0.0, 1.0]];
+    let index = Index::new(chunks, embeddings).unwrap();
+    let query_emb = [1.0, 0.0];
+
+    assert_eq!(index.search(&query_emb, 1).unwrap()[0].index, 0);
+    let hybrid = index
+        .search_hybrid("parse_header", &query_emb, 3, 0.5)
+        .unwrap();
+    assert_eq!(
+        index.retrieve(hybrid[0].0).text,
+        "fn parse_header(line: &str) {}"
+    );
+    assert!(hybrid.windows(2).all(|w| w[0].1 >= w[1].1));
+
+    let pure_vector = index
+        .search_hybrid("parse_header", &query_emb, 1, 1.0)
+        .unwrap();
+    assert_eq!(pure_vector[0].0, 0);
+}
+
+#[test]
+fn test_filtered_search() {
+    let lib = doc(
+        "src/lib.rs",
+        "struct Point {\n    x: f32,\n}\n\nfn point() {}\n\nstruct Line {\n    a: Point,\n}\n",
+    );
+    let mut other = doc("tests/other.rs", "fn helper() {}\n");
+    other.id = [4; 32];
+    let (chunks, _) = Chunker::new().chunk_all_documents(&[lib, other]).unwrap();
+    let embeddings: Vec<Vec<f32>> = chunks
+        .iter()
+        .map(|c| match c.raw_kind.as_ref() {
+            "function_item" => vec![1.0, 0.0],
+            _ => vec![0.5, 0.5],
+        })
+        .collect();
+    let index = Index::new(chunks, embeddings).unwrap();
+    let query = [1.0, 0.0];
+
+    let structs = index.search_by_type(&query, 10, "struct_item").unwrap();
+    assert_eq!(structs.len(), 2);
+    assert!(
+        structs
+            .iter()
+            .all(|r| index.retrieve(r.0).raw_kind == "struct_item")
+    );
+
+    let in_src = index.search_in_path(&query, 10, "src/").unwrap();
+    assert_eq!(in_src.len(), 3);
+    assert!(
+        in_src
+            .iter()
+            .all(|r| index.retrieve(r.0).path == "src/lib.rs")
+    );
+
+    let helpers = index
+        .search_filtered(&query, 10, |c| c.text.contains("helper"))
+        .unwrap();
+    assert_eq!(helpers.len(), 1);
+    assert_eq!(index.retrieve(helpers[0].0).path, "tests/other.rs");
+}
+
+#[test]
+fn test_dimension_is_validated() {
+    let src = "fn a() {}\n\nfn b() {}\n\nfn c() {}\n";
+    let (chunks, _) = Chunker::new()
+        .chunk_all_documents(&[doc("lib.rs", src)])
+        .unwrap();
+
+    let ragged = vec![vec![1.0, 0.0, 0.0], vec![0.0, 1.0, 0.0], vec![0.0, 1.0]];
+    assert!(matches!(
+        Index::new(chunks.clone(), ragged),
+        Err(RAGError::DimensionMismatch {
+            expected: 3,
+            got: 2
+        })
+    ));
+
+    let embeddings = vec![
+        vec![1.0, 0.0, 0.0],
+        vec![0.0, 1.0, 0.0],
+        vec![0.0, 0.0, 1.0],
+    ];
+    let index = Index::new(chunks, embeddings).unwrap();
+    assert_eq!(index.dim(), Some(3));
+    assert!(matches!(
+        index.search(&[1.0, 0.0], 1),
+        Err(RAGError::DimensionMismatch {
+            expected: 3,
+            got: 2
+        })
+    ));
+    assert!(matches!(
+        index.search(&[1.0, 0.0, 0.0, 0.0], 1),
+        Err(RAGError::DimensionMismatch {
+            expected: 3,
+            got: 4
+        })
+    ));
+}
+
+#[test]
+fn test_quantized_search_matches_exact() {
+    let src: String = (0..40).map(|i| format!("fn f{i}() {{}}\n\n")).collect();
+    let (chunks, _) = Chunker::new()
+        .chunk_all_documents(&[doc("lib.rs", &src)])
+        .unwrap();
+    let mut seed = 0x9e37_79b9_7f4a_7c15u64;
+    let mut random = move || {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        (seed >> 40) as f32 / (1u64 << 23) as f32 - 1.0
+    };
+    let embeddings: Vec<Vec<f32>> = (0..chunks.len())
+        .map(|_| (0..64).map(|_| random()).collect())
+        .collect();
+
+    let exact = Index::new(chunks.clone(), embeddings.clone()).unwrap();
+    let mut quantized = Index::quantized(chunks, embeddings.clone(), QuantMode::Int8).unwrap();
+    assert_eq!(quantized.quantization(), Some(QuantMode::Int8));
+    assert_eq!(exact.quantization(), None);
+
+    for target in &embeddings {
+        // a noisy copy of a stored embedding, as a query close to one chunk would be
+        let query: Vec<f32> = target.iter().map(|x| x + 0.3 * random()).collect();
+        let expected = exact.search(&query, 5).unwrap();
+        let got = quantized.search(&query, 5).unwrap();
+        assert_eq!(got[0].index, expected[0].index);
+        for (g, e) in got.iter().zip(&expected) {
+            assert!(
+                (g.score - e.score).abs() < 0.01,
+                "{} vs {}",
+                g.score,
+                e.score
+            );
+        }
+    }
+
+    // the stored values are approximations of the originals
+    for (stored, original) in quantized.embeddings().zip(&embeddings) {
+        for (s, o) in stored.iter().zip(original) {
+            assert!((s - o).abs() <= 1.0 / 254.0 + 1e-6);
+        }
+    }
+
+    // edits keep the quantized layout consistent
+    let removed_id = quantized.chunks[3].id;
+    let moved_id = quantized.chunks.last().unwrap().id;
+    let (_, removed) = quantized.remove(&removed_id).unwrap();
+    assert!((removed[0] - embeddings[3][0]).abs() < 0.01);
+    let moved = quantized.get(&moved_id).unwrap().clone();
+    quantized.add(moved, embeddings[3].clone()).unwrap();
+    let top = quantized.search(&embeddings[3], 1).unwrap();
+    assert_eq!(top[0].chunk_id, moved_id);
+}
+
+#[test]
+fn test_retrieve_with_neighbors() {
+    let mut main = doc(
+        "main.rs",
+        "fn a() {}\n\nfn b() {}\n\nfn c() {}\n\nfn d() {}\n\nfn e() {}\n",
+    );
+    main.id = [1; 32];
+    let other = doc("other.rs", "fn x() {}\n\nfn y() {}\n");
+    let (mut chunks, _) = Chunker::new().chunk_all_documents(&[main, other]).unwrap();
+    // positions in the index say nothing about positions in the document
+    chunks.reverse();
+    let embeddings = vec![vec![1.0, 0.0]; chunks.len()];
+    let index = Index::new(chunks, embeddings).unwrap();
+
+    let texts = |chunks: Vec<&Chunk>| chunks.iter().map(|c| c.text.clone()).collect::<Vec<_>>();
+    let middle = index
+        .chunks
+        .iter()
+        .position(|c| c.text == "fn c() {}")
+        .unwrap();
+    let around = index.retrieve_with_neighbors(middle, 1);
+    assert!(around.iter().all(|c| c.doc_id == [1; 32]));
+    assert!(
+        around
+            .windows(2)
+            .all(|w| w[1].doc_order == w[0].doc_order + 1)
+    );
+    assert_eq!(texts(around), ["fn b() {}", "fn c() {}", "fn d() {}"]);
+
+    // the window is clipped at the document's edges
+    let first = index
+        .chunks
+        .iter()
+        .position(|c| c.text == "fn x() {}")
+        .unwrap();
+    assert_eq!(
+        texts(index.retrieve_with_neighbors(first, 3)),
+        ["fn x() {}", "fn y() {}"]
+    );
+    assert_eq!(
+        texts(index.retrieve_with_neighbors(middle, 0)),
+        ["fn c() {}"]
+    );
+}
+
+#[test]
+fn test_validate_reports_each_inconsistency() {
+    let chunks = Chunker::new()
+        .chunk_document(&doc("notes.txt", "one\n\ntwo\n\nthree\n"))
+        .unwrap();
+    let embeddings = |n: usize| -> Vec<Vec<f32>> { (0..n).map(|i| vec![1.0, i as f32]).collect() };
+
+    let index = Index::new(chunks.clone(), embeddings(3)).unwrap();
+    index.validate().unwrap();
+
+    let short = Index::new(chunks.clone(), embeddings(2)).unwrap();
+    assert!(matches!(
+        short.validate(),
+        Err(RAGError::CountMismatch {
+            chunks: 3,
+            embeddings: 2
+        })
+    ));
+
+    let mut poisoned = embeddings(3);
+    poisoned[1][0] = f32::NAN;
+    let nan = Index::new(chunks.clone(), poisoned).unwrap();
+    assert!(matches!(
+        nan.validate(),
+        Err(RAGError::NonFiniteEmbedding { idx: 1 })
+    ));
+
+    // The second copy of a chunk claims the ID, leaving the first unreachable
+    let mut doubled = chunks.clone();
+    doubled[2] = doubled[0].clone();
+    let duplicate = Index::new(doubled, embeddings(3)).unwrap();
+    assert!(matches!(
+        duplicate.validate(),
+        Err(RAGError::IdMapMismatch { idx: 0 })
+    ));
+
+    let mut edited = Index::new(chunks.clone(), embeddings(3)).unwrap();
+    edited.chunks[1].id = [9; 32];
+    assert!(matches!(
+        edited.validate(),
+        Err(RAGError::IdMapMismatch { idx: 1 })
+    ));
+
+    let mut truncated = Index::new(chunks, embeddings(3)).unwrap();
+    truncated.chunks.pop();
+    assert!(matches!(
+        truncated.validate(),
+        Err(RAGError::CountMismatch {
+            chunks: 2,
+            embeddings: 3
+        })
+    ));
+}
+
+#[test]
+fn test_manifest_lists_each_document_once() {
+    let docs =
+        wubraglib::document::grab_all_documents(std::path::Path::new("tests/fixtures")).unwrap();
+    let (chunks, _) = Chunker::new().chunk_all_documents(&docs).unwrap();
+    let embeddings = (0..chunks.len()).map(|i| vec![1.0, i as f32]).collect();
+    let index = Index::new(chunks, embeddings).unwrap();
+
+    let manifest = index.manifest();
+    assert_eq!(manifest.len(), docs.len());
+    let mut paths: Vec<&str> = manifest.iter().map(|e| e.path.as_str()).collect();
+    paths.sort_unstable();
+    let mut expected: Vec<&str> = docs.iter().map(|d| d.path.as_str()).collect();
+    expected.sort_unstable();
+    assert_eq!(paths, expected);
+    assert_eq!(
+        manifest.iter().map(|e| e.chunk_count).sum::<usize>(),
+        index.chunks.len()
+    );
+    let sample = manifest.iter().find(|e| e.path == "sample.rs").unwrap();
+    let sample_chunks = index.chunks.iter().filter(|c| c.path == "sample.rs");
+    assert_eq!(
+        sample.total_chars,
+        sample_chunks.map(|c| c.char_count).sum::<usize>()
+    );
+    assert_eq!(sample.size, None);
+
+    // Joining adds file metadata, plus documents that produced no chunks
+    let blank = Document::from_str("empty.txt", "\n\n".to_string());
+    let mut all_docs = docs.clone();
+    all_docs.push(blank.clone());
+    let joined = index.manifest_with_documents(&all_docs);
+    assert_eq!(joined.len(), docs.len() + 1);
+    let script = joined.iter().find(|e| e.path == "run_checks").unwrap();
+    assert_eq!(script.language.as_deref(), Some("py"));
+    assert!(script.size.is_some_and(|s| s > 0));
+    let last = joined.last().unwrap();
+    assert_eq!((last.doc_id, last.chunk_count), (blank.id, 0));
+}
+
+#[test]
+fn test_type_weights_reorder_results() {
+    let src = "fn parse() {}\n\nstruct Parser;\n";
+    let (chunks, _) = Chunker::new()
+        .chunk_all_documents(&[doc("lib.rs", src)])
+        .unwrap();
+    let struct_idx = chunks
+        .iter()
+        .position(|c| c.raw_kind == "struct_item")
+        .unwrap();
+    // Both chunks are equally similar to the query
+    let embeddings = chunks
+        .iter()
+        .map(|c| match c.raw_kind.as_ref() {
+            "struct_item" => vec![1.0, 0.0, 0.2],
+            _ => vec![1.0, 0.2, 0.0],
+        })
+        .collect();
+    let index = Index::new(chunks, embeddings).unwrap();
+    let query = [1.0, 0.0, 0.0];
+
+    let unweighted = index.search_weighted(&query, 2, &HashMap::new()).unwrap();
+    assert_eq!(unweighted[0].1, unweighted[1].1);
+
+    let weights = HashMap::from([("struct_item", 1.5)]);
+    let weighted = index.search_weighted(&query, 2, &weights).unwrap();
+    assert_eq!(weighted[0].0, struct_idx);
+    assert!((weighted[0].1 - 1.5 * weighted[1].1).abs() < 1e-6);
+
+    // a boost still favours its kind when both chunks point away from the query
+    let opposite = [-1.0, 0.0, 0.0];
+    let weighted = index.search_weighted(&opposite, 2, &weights).unwrap();
+    assert_eq!(weighted[0].0, struct_idx);
+    assert!(weighted.iter().all(|&(_, score)| score >= 0.0));
+}
+
+#[test]
+fn test_substring_search_finds_rare_identifier() {
+    let src = "fn alpha() {}\n\nfn beta() {\n    frobnicate_widget(1);\n}\n\nfn gamma() {}\n";
+    let (chunks, _) = Chunker::new()
+        .chunk_all_documents(&[doc("lib.rs", src)])
+        .unwrap();
+    let embeddings = (0..chunks.len()).map(|i| vec![1.0, i as f32]).collect();
+    let index = Index::new(chunks, embeddings).unwrap();
+
+    let hits = index.search_substring("frobnicate_widget", true);
+    assert_eq!(hits.len(), 1);
+    assert!(index.retrieve(hits[0]).text.starts_with("fn beta"));
+
+    assert!(index.search_substring("Frobnicate_Widget", true).is_empty());
+    assert_eq!(index.search_substring("Frobnicate_Widget", false), hits);
+}
+
+#[test]
+fn test_tied_scores_resolve_by_position() {
+    let src: String = (0..40).map(|i| format!("fn f{i}() {{}}\n\n")).collect();
+    let (chunks, _) = Chunker::new()
+        .chunk_all_documents(&[doc("lib.rs", &src)])
+        .unwrap();
+    let embeddings = vec![vec![0.6, 0.8]; chunks.len()];
+    let index = Index::new(chunks, embeddings).unwrap();
+
+    let expected: Vec<usize> = (0..10).collect();
+    for _ in 0..20 {
+        let order: Vec<usize> = index
+            .search(&[1.0, 0.0], 10)
+            .unwrap()
+            .iter()
+            .map(|r| r.index)
+            .collect();
+        assert_eq!(order, expected);
+    }
+}
+
+#[test]
+fn test_assemble_context_merges_results_per_document() {
+    let lib = "fn alpha() {}\n\nfn beta() {}\n\nfn gamma() {}\n\nfn delta() {}\n";
+    let (chunks, _) = Chunker::new()
+        .chunk_all_documents(&[
+            doc("lib.rs", lib),
+            Document {
+                id: [4; 32],
+                ..doc("main.rs", "fn main() {}\n")
+            },
+        ])
+        .unwrap();
+    let embeddings = (0..chunks.len()).map(|i| vec![1.0, i as f32]).collect();
+    let index = Index::new(chunks, embeddings).unwrap();
+    let find = |text: &str| {
+        index
+            .chunks
+            .iter()
+            .position(|c| c.text.starts_with(text))
+            .unwrap()
+    };
+    let (alpha, beta, delta, main) = (
+        find("fn alpha"),
+        find("fn beta"),
+        find("fn delta"),
+        find("fn main"),
+    );
+
+    let results = [(delta, 0.9), (main, 0.8), (beta, 0.7), (alpha, 0.6)];
+    let contexts = index.assemble_context(&results, 1000);
+    assert_eq!(contexts.len(), 2);
+    assert_eq!(contexts[0].path, "lib.rs");
+    assert_eq!(contexts[0].score, 0.9);
+    assert_eq!(contexts[0].chunks, vec![alpha, beta, delta]);
+    assert_eq!(
+        contexts[0].text,
+        "fn alpha() {}\n\nfn beta() {}\n…\nfn delta() {}"
+    );
+    assert_eq!(contexts[1].text, "fn main() {}");
+
+    let contexts = index.assemble_context(&results, 10);
+    assert_eq!(contexts.len(), 1);
+    assert_eq!(contexts[0].text, "fn alpha()");
+}
+
+#[test]
+fn test_search_results_order_by_score() {
+    let src = "fn a() {}\n\nfn b() {}\n\nfn c() {}\n";
+    let (chunks, _) = Chunker::new()
+        .chunk_all_documents(&[doc("lib.rs", src)])
+        .unwrap();
+    let embeddings = vec![vec![0.0, 1.0], vec![1.0, 0.0], vec![0.7, 0.7]];
+    let index = Index::new(chunks, embeddings).unwrap();
+
+    let results = index.search(&[1.0, 0.0], 3).unwrap();
+    assert_eq!(results[0].index, 1);
+    assert_eq!(results[0].chunk_id, index.chunks[1].id);
+    assert!((results[0].score - 1.0).abs() < 1e-6);
+    assert!(results.windows(2).all(|w| w[0] > w[1]));
+
+    let mut heap: std::collections::BinaryHeap<SearchResult> =
+        results.iter().rev().copied().collect();
+    let popped: Vec<usize> = std::iter::from_fn(|| heap.pop()).map(|r| r.index).collect();
+    assert_eq!(popped, vec![1, 2, 0]);
+
+    #[allow(deprecated)]
+    let raw = index.search_raw(&[1.0, 0.0], 3).unwrap();
+    let typed: Vec<(usize, f32)> = results.iter().map(|r| (r.index, r.score)).collect();
+    assert_eq!(raw, typed);
+}
+
+#[test]
+fn test_top_k_matches_full_sort() {
+    let src: String = (0..300).map(|i| format!("fn f{i}() {{}}\n\n")).collect();
+    let (chunks, _) = Chunker::new()
+        .chunk_all_documents(&[doc("lib.rs", &src)])
+        .unwrap();
+    // Few distinct vectors, so many scores tie
+    let embeddings: Vec<Vec<f32>> = (0..chunks.len())
+        .map(|i| vec![(i % 7) as f32, (i % 5) as f32, 1.0])
+        .collect();
+    let n = embeddings.len();
+
+    for metric in [Metric::Cosine, Metric::DotProduct, Metric::Euclidean] {
+        let index = Index::new_with_metric(chunks.clone(), embeddings.clone(), metric).unwrap();
+        let query = [0.3, 1.0, 0.5];
+        let mut sorted: Vec<(usize, f32)> = (0..n)
+            .map(|i| (i, metric.score(&query, &embeddings[i])))
+            .collect();
+        sorted.sort_by(|a, b| {
+            let by_score = if metric.higher_is_better() {
+                b.1.total_cmp(&a.1)
+            } else {
+                a.1.total_cmp(&b.1)
+            };
+            by_score.then(a.0.cmp(&b.0))
+        });
+
+        for k in [0, 1, 5, 64, n, n + 10] {
+            let got: Vec<usize> = index
+                .search(&query, k)
+                .unwrap()
+                .iter()
+                .map(|r| r.index)
+                .collect();
+            let expected: Vec<usize> = sorted.iter().take(k).map(|r| r.0).collect();
+            assert_eq!(got, expected, "{metric:?} k={k}");
+        }
+    }
+}
+
+// Keeps embeddings as little-endian bytes, decoding a row on every read like a
+// store that pages them in from disk
+struct ByteStore {
+    bytes: Vec<u8>,
+    dim: usize,
+}
+
+impl VectorStore for ByteStore {
+    fn len(&self) -> usize {
+        self.bytes.len() / (self.dim * 4)
+    }
+
+    fn get(&self, idx: usize) -> Cow<'_, [f32]> {
+        let row = &self.bytes[idx * self.dim * 4..(idx + 1) * self.dim * 4];
+        Cow::Owned(
+            row.chunks_exact(4)
+                .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+                .collect(),
+        )
+    }
+
+    fn dim(&self) -> usize {
+        self.dim
+    }
+}
+
+#[test]
+fn test_vector_store_search_matches_in_memory() {
+    let src: String = (0..50).map(|i| format!("fn f{i}() {{}}\n\n")).collect();
+    let (chunks, _) = Chunker::new()
+        .chunk_all_documents(&[doc("lib.rs", &src)])
+        .unwrap();
+    let embeddings: Vec<Vec<f32>> = (0..chunks.len())
+        .map(|i| vec![(i % 7) as f32, (i % 5) as f32 - 2.0, 1.0])
+        .collect();
+    let bytes = embeddings
+        .iter()
+        .flatten()
+        .flat_map(|x| x.to_le_bytes())
+        .collect();
+
+    for metric in [Metric::Cosine, Metric::DotProduct, Metric::Euclidean] {
+        let memory = Index::new_with_metric(chunks.clone(), embeddings.clone(), metric).unwrap();
+        let store = ByteStore {
+            bytes: Vec::clone(&bytes),
+            dim: 3,
+        };
+        let external = Index::with_vector_store(chunks.clone(), store, metric).unwrap();
+        external.validate().unwrap();
+        assert_eq!(external.dim(), Some(3));
+
+        let query = [0.3, 1.0, 0.5];
+        let hits = |index: &Index| -> Vec<(usize, f32)> {
+            let results = index.search(&query, 10).unwrap();
+            results.iter().map(|r| (r.index, r.score)).collect()
+        };
+        assert_eq!(hits(&external), hits(&memory), "{metric:?}");
+    }
+
+    let short = ByteStore {
+        bytes: vec![0; 12],
+        dim: 3,
+    };
+    assert!(matches!(
+        Index::with_vector_store(chunks, short, Metric::Cosine),
+        Err(RAGError::CountMismatch { embeddings: 1, .. })
+    ));
+}
@@ -0,0 +1,114 @@
+use wubraglib::RAGError;
+use wubraglib::chunking::*;
+use wubraglib::document::Document;
+use wubraglib::indexing::{Index, Metric};
+
+fn doc(path: &str, text: &str) -> Document {
+    Document {
+        id: [5; 32],
+        path: path.to_string(),
+        text: text.to_string(),
+        ext: "rs".to_string(),
+        language: None,
+        size: text.len() as u64,
+        modified: None,
+    }
+}
+
+fn sample_index(metric: Metric) -> Index {
+    // odd-length names leave the chunk section unaligned, exercising the padding
+    let src = "fn a() {}\n\nfn bee() -> u8 {\n    1\n}\n\nfn ceee() {}\n\nfn dd() {}\n";
+    let (chunks, _) = Chunker::new()
+        .chunk_all_documents(&[doc("lib.rs", src)])
+        .unwrap();
+    let embeddings = (0..chunks.len())
+        .map(|i| vec![1.0 - i as f32 * 0.3, i as f32 * 0.7, 0.25, -(i as f32)])
+        .collect();
+    Index::new_with_metric(chunks, embeddings, metric).unwrap()
+}
+
+fn temp_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("wubraglib-{name}-{}.bin", std::process::id()))
+}
+
+#[test]
+fn test_mmap_search_matches_heap_load() {
+    for metric in [Metric::Cosine, Metric::DotProduct, Metric::Euclidean] {
+        let index = sample_index(metric);
+        let path = temp_path(&format!("mmap-{metric:?}"));
+        index.save(&path).unwrap();
+
+        let loaded = Index::load(&path).unwrap();
+        let mapped = Index::load_mmap(&path).unwrap();
+        assert_eq!(mapped.metric(), metric);
+        assert_eq!(mapped.dim(), Some(4));
+        assert!(mapped.embeddings().eq(loaded.embeddings()));
+        for query in [
+            [1.0, 0.0, 0.0, 0.0],
+            [0.1, 0.9, 0.2, -0.5],
+            [0.0, 0.0, 1.0, 0.0],
+        ] {
+            assert_eq!(
+                mapped.search(&query, 3).unwrap(),
+                loaded.search(&query, 3).unwrap()
+            );
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+}
+
+#[test]
+fn test_mmap_index_can_still_be_edited() {
+    let index = sample_index(Metric::Cosine);
+    let path = temp_path("mmap-edit");
+    index.save(&path).unwrap();
+
+    let mut mapped = Index::load_mmap(&path).unwrap();
+    let first = mapped.chunks[0].clone();
+    mapped.remove(&first.id).unwrap();
+    mapped.add(first.clone(), vec![0.0, 0.0, 0.0, 1.0]).unwrap();
+    let top = mapped.search_ids(&[0.0, 0.0, 0.0, 1.0], 1).unwrap();
+    assert_eq!(top[0].0, first.id);
+    drop(mapped);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_mmap_rejects_truncated_files() {
+    let index = sample_index(Metric::Cosine);
+    let path = temp_path("mmap-truncated");
+    index.save(&path).unwrap();
+    let bytes = std::fs::read(&path).unwrap();
+
+    for len in [bytes.len() - 1, bytes.len() - 16, 40] {
+        std::fs::write(&path, &bytes[..len]).unwrap();
+        assert!(matches!(
+            Index::load_mmap(&path),
+            Err(RAGError::Deserialization(_))
+        ));
+        assert!(matches!(
+            Index::load(&path),
+            Err(RAGError::Deserialization(_))
+        ));
+    }
+
+    // trailing garbage means the header's dimension and count don't describe the file
+    let mut padded = bytes.clone();
+    padded.extend_from_slice(&[0; 4]);
+    std::fs::write(&path, &padded).unwrap();
+    assert!(matches!(
+        Index::load_mmap(&path),
+        Err(RAGError::Deserialization(_))
+    ));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_mmap_loads_an_empty_index() {
+    let path = temp_path("empty");
+    Index::new(vec![], vec![]).unwrap().save(&path).unwrap();
+    let index = Index::load_mmap(&path).unwrap();
+    assert!(index.chunks.is_empty());
+    assert_eq!(index.dim(), None);
+    std::fs::remove_file(&path).unwrap();
+}
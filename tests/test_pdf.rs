@@ -0,0 +1,40 @@
+extern crate wubraglib;
+
+use std::fs;
+
+use wubraglib::chunking::Chunker;
+use wubraglib::document::DocumentCollector;
+
+#[test]
+fn test_pdf_text_is_extracted() {
+    let docs = DocumentCollector::new("tests/pdf_fixtures")
+        .collect()
+        .unwrap();
+    assert_eq!(docs.len(), 1);
+    let pdf = &docs[0];
+    assert_eq!(pdf.path, "guide.pdf");
+    assert_eq!(pdf.ext, "pdf");
+    assert!(
+        pdf.text.contains("Retrieval augmented generation"),
+        "{:?}",
+        pdf.text
+    );
+
+    let chunks = Chunker::new().chunk_document(pdf).unwrap();
+    assert!(!chunks.is_empty());
+    assert!(chunks.iter().all(|c| c.raw_kind == "paragraph"));
+}
+
+#[test]
+fn test_broken_pdf_is_skipped() {
+    let root = std::env::temp_dir().join(format!("wubraglib-pdf-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(&root).unwrap();
+    fs::write(root.join("broken.pdf"), b"%PDF-1.4\nnot really a pdf\n").unwrap();
+    fs::write(root.join("notes.md"), "# Notes\n").unwrap();
+
+    let docs = DocumentCollector::new(&root).collect().unwrap();
+    let paths: Vec<&str> = docs.iter().map(|d| d.path.as_str()).collect();
+    assert_eq!(paths, ["notes.md"]);
+    fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,130 @@
+extern crate wubraglib;
+
+use wubraglib::chunking::Chunker;
+use wubraglib::document::DocumentCollector;
+use wubraglib::embedding::{Embed, Embedder, EmbeddingMeta};
+use wubraglib::indexing::{Index, Metric};
+use wubraglib::pipeline::RagPipeline;
+use wubraglib::*;
+
+const DIM: usize = 64;
+
+// Hashed bag of words, so texts sharing identifiers land close together
+struct BagOfWords;
+
+impl Embed for BagOfWords {
+    fn embed(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+        Ok(texts
+            .iter()
+            .map(|t| {
+                let mut v = vec![0.0; DIM];
+                for word in t.split(|c: char| !c.is_alphanumeric() && c != '_') {
+                    if !word.is_empty() {
+                        let h = word
+                            .bytes()
+                            .fold(0usize, |h, b| h.wrapping_mul(31).wrapping_add(b as usize));
+                        v[h % DIM] += 1.0;
+                    }
+                }
+                v
+            })
+            .collect())
+    }
+
+    fn dim(&self) -> usize {
+        DIM
+    }
+
+    fn model_id(&self) -> &str {
+        "bag-of-words"
+    }
+}
+
+#[test]
+fn test_pipeline_builds_searchable_index() {
+    let index = RagPipeline::new()
+        .root("tests/fixtures")
+        .chunker(Chunker::builder().max_chunk_chars(400).build().unwrap())
+        .embedder(Embedder::with_backend(BagOfWords))
+        .build()
+        .unwrap();
+    assert!(!index.chunks.is_empty());
+
+    let query = BagOfWords
+        .embed(&["parse_header line split_once key value"])
+        .unwrap();
+    let hits = index.search(&query[0], 1).unwrap();
+    assert_eq!(hits.len(), 1);
+    assert!(index.chunks[hits[0].index].text.contains("fn parse_header"));
+}
+
+#[test]
+fn test_pipeline_surfaces_stage_errors() {
+    let Err(err) = RagPipeline::new()
+        .collector(DocumentCollector::new("tests/fixtures").include("[bad"))
+        .embedder(Embedder::with_backend(BagOfWords))
+        .build()
+    else {
+        panic!("invalid include glob should fail the collect stage");
+    };
+    assert!(matches!(err, RAGError::InvalidGlob { .. }), "{err:?}");
+}
+
+#[test]
+fn test_pipeline_records_embedding_meta() {
+    let index = RagPipeline::new()
+        .root("tests/fixtures")
+        .embedder(Embedder::with_backend(BagOfWords))
+        .build()
+        .unwrap();
+    let meta = EmbeddingMeta {
+        model_id: "bag-of-words".to_string(),
+        dim: DIM,
+        normalized: true,
+    };
+    assert_eq!(index.embedding_meta(), Some(&meta));
+
+    let path = std::env::temp_dir().join(format!("wubraglib-meta-{}.bin", std::process::id()));
+    index.save(&path).unwrap();
+    let loaded = Index::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(loaded.embedding_meta(), Some(&meta));
+
+    let query = Embedder::with_backend(BagOfWords)
+        .embed_query("parse_header")
+        .unwrap();
+    assert!(loaded.search_with_meta(&query, 1, &meta).is_ok());
+    let other = EmbeddingMeta {
+        model_id: "another-model".to_string(),
+        ..meta.clone()
+    };
+    assert!(matches!(
+        loaded.search_with_meta(&query, 1, &other),
+        Err(RAGError::ModelMismatch { .. })
+    ));
+    let wider = EmbeddingMeta {
+        dim: 2 * DIM,
+        ..meta.clone()
+    };
+    assert!(matches!(
+        loaded.check_meta(&wider),
+        Err(RAGError::DimensionMismatch { .. })
+    ));
+
+    // cosine ignores length, so only other metrics care about normalization
+    let unnormalized = EmbeddingMeta {
+        normalized: false,
+        ..meta.clone()
+    };
+    assert!(loaded.check_meta(&unnormalized).is_ok());
+    let dot = RagPipeline::new()
+        .root("tests/fixtures")
+        .embedder(Embedder::with_backend(BagOfWords))
+        .metric(Metric::DotProduct)
+        .build()
+        .unwrap();
+    assert!(matches!(
+        dot.check_meta(&unnormalized),
+        Err(RAGError::ModelMismatch { .. })
+    ));
+}
@@ -0,0 +1,57 @@
+extern crate wubraglib;
+
+use wubraglib::chunking::*;
+use wubraglib::document::Document;
+
+#[test]
+fn test_chunk_json_round_trip() {
+    let src = "impl Foo {\n    fn bar(&self) {}\n}\n";
+    let chunker = Chunker::builder()
+        .emit_nested_functions(true)
+        .build()
+        .unwrap();
+    let chunks = chunker
+        .chunk_document(&Document::from_str("lib.rs", src.to_string()))
+        .unwrap();
+    let nested = chunks.iter().find(|c| c.parent_id.is_some()).unwrap();
+
+    let json = serde_json::to_string(nested).unwrap();
+    let hex_id: String = nested.id.iter().map(|b| format!("{b:02x}")).collect();
+    assert!(json.contains(&format!("\"id\":\"{hex_id}\"")));
+
+    let back: Chunk = serde_json::from_str(&json).unwrap();
+    assert_eq!(back.id, nested.id);
+    assert_eq!(back.doc_id, nested.doc_id);
+    assert_eq!(back.parent_id, nested.parent_id);
+    assert_eq!(back.text, nested.text);
+    assert_eq!(back.kind, nested.kind);
+    assert_eq!(back.raw_kind, nested.raw_kind);
+    assert_eq!(back.char_count, nested.char_count);
+    assert_eq!(
+        (
+            back.start_line,
+            back.end_line,
+            back.start_byte,
+            back.end_byte
+        ),
+        (
+            nested.start_line,
+            nested.end_line,
+            nested.start_byte,
+            nested.end_byte
+        )
+    );
+    assert_eq!(back.token_count, nested.token_count);
+}
+
+#[test]
+fn test_document_json_round_trip() {
+    let doc = Document::from_str("notes/readme.md", "# Title\n\nBody\n".to_string());
+    let json = serde_json::to_string(&doc).unwrap();
+    let back: Document = serde_json::from_str(&json).unwrap();
+    assert_eq!(back.id, doc.id);
+    assert_eq!(
+        (back.path, back.text, back.ext),
+        (doc.path, doc.text, doc.ext)
+    );
+}
@@ -0,0 +1,75 @@
+use wubraglib::util::{cosine_normalized, cosine_similarity, l2_normalize};
+
+fn close(a: f32, b: f32) -> bool {
+    (a - b).abs() < 1e-6
+}
+
+#[test]
+fn test_cosine_orthogonal() {
+    assert!(close(cosine_similarity(&[1.0, 0.0], &[0.0, 3.0]), 0.0));
+    assert!(close(cosine_similarity(&[1.0, 2.0], &[-2.0, 1.0]), 0.0));
+}
+
+#[test]
+fn test_cosine_identical_and_opposite() {
+    let v = [0.3, -1.2, 4.0];
+    assert!(close(cosine_similarity(&v, &v), 1.0));
+    // magnitude doesn't matter, only direction
+    assert!(close(cosine_similarity(&v, &[0.6, -2.4, 8.0]), 1.0));
+    assert!(close(cosine_similarity(&v, &[-0.3, 1.2, -4.0]), -1.0));
+}
+
+#[test]
+fn test_cosine_zero_vector() {
+    let zero = [0.0; 3];
+    assert_eq!(cosine_similarity(&zero, &[1.0, 2.0, 3.0]), 0.0);
+    assert_eq!(cosine_similarity(&zero, &zero), 0.0);
+}
+
+#[test]
+fn test_cosine_mismatched_lengths_use_common_prefix() {
+    assert!(close(cosine_similarity(&[1.0, 0.0], &[1.0, 0.0, 5.0]), 1.0));
+    assert!(close(cosine_similarity(&[0.0, 1.0, 7.0], &[1.0, 0.0]), 0.0));
+}
+
+#[test]
+fn test_cosine_matches_normalized_dot() {
+    let (mut a, mut b) = ([1.0, 2.0, 3.0], [-4.0, 0.5, 2.0]);
+    let expected = cosine_similarity(&a, &b);
+    l2_normalize(&mut a);
+    l2_normalize(&mut b);
+    assert!(close(cosine_normalized(&a, &b), expected));
+}
+
+// Deterministic values in [-1, 1) from a xorshift generator
+fn random_vector(seed: &mut u64, dim: usize) -> Vec<f32> {
+    (0..dim)
+        .map(|_| {
+            *seed ^= *seed << 13;
+            *seed ^= *seed >> 7;
+            *seed ^= *seed << 17;
+            (*seed >> 40) as f32 / (1u64 << 23) as f32 - 1.0
+        })
+        .collect()
+}
+
+fn scalar_cosine(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let na = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let nb = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    dot / (na * nb)
+}
+
+#[test]
+fn test_vectorized_cosine_matches_scalar() {
+    let mut seed = 0x2545_f491_4f6c_dd1d;
+    // 768 fills the vector lanes exactly; the others leave a remainder tail
+    for dim in [768, 770, 5, 1] {
+        for _ in 0..20 {
+            let a = random_vector(&mut seed, dim);
+            let b = random_vector(&mut seed, dim);
+            let (fast, slow) = (cosine_similarity(&a, &b), scalar_cosine(&a, &b));
+            assert!((fast - slow).abs() < 1e-5, "dim {dim}: {fast} vs {slow}");
+        }
+    }
+}